@@ -0,0 +1,98 @@
+use std::collections::{BTreeSet, HashMap};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// Funds an Authorize reserved on an account, until they are captured, voided or the authorization expires.
+/// Sequence numbers are engine record sequence numbers (see `Engine::record_sequence`).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Authorization {
+    pub client_id: u16,
+    /// Currency of the reserved funds, None for the default currency.
+    pub currency: Option<String>,
+    /// Amount still reserved. Partial voids lower it.
+    pub amount: Decimal,
+    pub authorized_at: u64,
+    /// Sequence number of the last record the authorization outlives. None when it never expires by count.
+    pub expires_after: Option<u64>,
+    /// Timestamp from which on the authorization is expired. None when it never expires by time.
+    pub expires_at: Option<u64>,
+}
+
+/// Open authorizations by transaction ID, indexed by when they expire.
+/// Authorizations are forgotten once captured, voided or expired. The journal keeps their history.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Authorizations {
+    open: HashMap<u32, Authorization>,
+    // (expires_after, transaction ID) of every open authorization that expires by count
+    by_record: BTreeSet<(u64, u32)>,
+    // (expires_at, transaction ID) of every open authorization that expires by time
+    by_time: BTreeSet<(u64, u32)>,
+}
+
+impl Authorizations {
+    /// The open authorization of the transaction, if any.
+    pub fn get(&self, transaction_id: u32) -> Option<&Authorization> {
+        self.open.get(&transaction_id)
+    }
+
+    /// Returns true when the transaction is an open authorization.
+    pub fn is_open(&self, transaction_id: u32) -> bool {
+        self.open.contains_key(&transaction_id)
+    }
+
+    /// IDs of every open authorization, sorted ascending.
+    pub fn transaction_ids(&self) -> Vec<u32> {
+        let mut transaction_ids: Vec<u32> = self.open.keys().cloned().collect();
+        transaction_ids.sort_unstable();
+        transaction_ids
+    }
+
+    /// IDs of the open authorizations that expired by the time record `record_sequence` is applied, with `clock` the latest
+    /// timestamp seen so far. Sorted ascending.
+    pub fn expired(&self, record_sequence: u64, clock: Option<u64>) -> Vec<u32> {
+        let by_record = self.by_record.iter().take_while(|(expires_after, _)| *expires_after < record_sequence);
+        let by_time = self.by_time.iter().take_while(|(expires_at, _)| clock.is_some_and(|clock| *expires_at <= clock));
+        let mut transaction_ids: Vec<u32> = by_record.chain(by_time).map(|(_, transaction_id)| *transaction_id).collect();
+        transaction_ids.sort_unstable();
+        transaction_ids.dedup();
+        transaction_ids
+    }
+
+    /// Opens an authorization, replacing any open one of the same transaction, e.g. when loading a snapshot.
+    pub fn open(&mut self, transaction_id: u32, authorization: Authorization) {
+        self.close(transaction_id);
+        if let Some(expires_after) = authorization.expires_after {
+            self.by_record.insert((expires_after, transaction_id));
+        }
+        if let Some(expires_at) = authorization.expires_at {
+            self.by_time.insert((expires_at, transaction_id));
+        }
+        self.open.insert(transaction_id, authorization);
+    }
+
+    /// Releases `amount` of the reserved funds, closing the authorization once nothing is reserved. Does nothing if none is open.
+    pub fn release(&mut self, transaction_id: u32, amount: Decimal) {
+        if let Some(authorization) = self.open.get_mut(&transaction_id) {
+            authorization.amount -= amount;
+            if authorization.amount <= Decimal::ZERO {
+                self.close(transaction_id);
+            }
+        }
+    }
+
+    /// Closes the authorization of the transaction, returning it if it was open.
+    pub fn close(&mut self, transaction_id: u32) -> Option<Authorization> {
+        let authorization = self.open.remove(&transaction_id)?;
+        if let Some(expires_after) = authorization.expires_after {
+            self.by_record.remove(&(expires_after, transaction_id));
+        }
+        if let Some(expires_at) = authorization.expires_at {
+            self.by_time.remove(&(expires_at, transaction_id));
+        }
+        Some(authorization)
+    }
+
+    pub(crate) fn into_authorizations(self) -> impl Iterator<Item = (u32, Authorization)> {
+        self.open.into_iter()
+    }
+}
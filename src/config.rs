@@ -0,0 +1,45 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// What a Dispute on a stored withdrawal does.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum WithdrawalDisputePolicy {
+    /// Disputes on withdrawals are rejected. Only deposits can be disputed.
+    #[default]
+    Reject,
+    /// The disputed withdrawal amount is held as a pending credit: held and total grow while available is unchanged.
+    /// A Resolve drops the pending credit and a Chargeback credits it back to available.
+    CreditPending,
+}
+
+/// Behavior options for an Engine. The default matches the documented behavior of the CLI.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EngineConfig {
+    pub withdrawal_disputes: WithdrawalDisputePolicy,
+    /// How many times a single transaction can be disputed, counting resolved disputes. None means no limit.
+    /// A charged back transaction can never be disputed again.
+    pub max_disputes: Option<u32>,
+    /// How many subsequent records an authorization outlives before it expires and its funds are released. None means never.
+    pub authorization_expiry_records: Option<u64>,
+    /// How many seconds after its timestamp an authorization expires, once a record with a timestamp that late arrives.
+    /// None means never. Authorizations without a timestamp only expire by count.
+    pub authorization_expiry_seconds: Option<u64>,
+}
+
+/// What to do with an amount that has more decimal places than its currency: four for the default currency.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum PrecisionPolicy {
+    /// The record is rejected.
+    #[default]
+    Reject,
+    /// The amount is rounded to the decimal places of its currency, with midpoints rounded away from zero.
+    Round,
+}
+
+/// Options for the validation applied to records before they reach the Engine.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ValidationConfig {
+    pub precision: PrecisionPolicy,
+}
@@ -0,0 +1,28 @@
+use rust_decimal::{Decimal, RoundingStrategy};
+use crate::validation::AMOUNT_DECIMAL_PLACES;
+
+/// Minor-unit decimal places of the named currencies the engine accepts, per ISO 4217.
+/// Balances are kept to four decimal places, so currencies with finer minor units can't be listed.
+const MINOR_UNITS: &[(&str, u32)] = &[
+    ("AED", 2), ("AUD", 2), ("BHD", 3), ("BRL", 2), ("CAD", 2), ("CHF", 2), ("CLF", 4), ("CLP", 0),
+    ("CNY", 2), ("CZK", 2), ("DKK", 2), ("EUR", 2), ("GBP", 2), ("HKD", 2), ("HUF", 2), ("IDR", 2),
+    ("ILS", 2), ("INR", 2), ("ISK", 0), ("JOD", 3), ("JPY", 0), ("KRW", 0), ("KWD", 3), ("MXN", 2),
+    ("NOK", 2), ("NZD", 2), ("OMR", 3), ("PHP", 2), ("PLN", 2), ("SAR", 2), ("SEK", 2), ("SGD", 2),
+    ("THB", 2), ("TND", 3), ("TRY", 2), ("TWD", 2), ("USD", 2), ("VND", 0), ("ZAR", 2),
+];
+
+/// Decimal places amounts in `currency` may have, or None for a currency the engine does not know.
+/// Codes are matched as given, so callers uppercase them first. The default currency (None) keeps four places.
+pub fn minor_units(currency: Option<&str>) -> Option<u32> {
+    match currency {
+        None => Some(AMOUNT_DECIMAL_PLACES),
+        Some(code) => MINOR_UNITS.binary_search_by_key(&code, |(known, _)| known).ok().map(|index| MINOR_UNITS[index].1),
+    }
+}
+
+/// Converts `amount` at `rate` into `target`, rounded to the minor units of `target` with midpoints away from zero.
+/// None when the product overflows or `target` is not known.
+pub fn convert(amount: Decimal, rate: Decimal, target: Option<&str>) -> Option<Decimal> {
+    let decimal_places = minor_units(target)?;
+    Some(amount.checked_mul(rate)?.round_dp_with_strategy(decimal_places, RoundingStrategy::MidpointAwayFromZero))
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// Lifecycle state of a single dispute. Open is the only state a Resolve or Chargeback applies to.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeState {
+    Open,
+    Resolved,
+    ChargedBack,
+}
+
+/// One dispute of a transaction, from the record that opened it to the record that closed it.
+/// Both are engine record sequence numbers (see `Engine::record_sequence`).
+///
+/// A dispute may cover only part of the transaction, and may be resolved or charged back in several parts.
+/// It stays Open until nothing is held any more, and is then closed as ChargedBack if any part was charged back.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct Dispute {
+    pub state: DisputeState,
+    /// Amount held when the dispute was opened. Absent in snapshots before version 3, which the loader fills in.
+    #[serde(default)]
+    pub amount: Decimal,
+    /// Part of `amount` released by resolves so far.
+    #[serde(default)]
+    pub released: Decimal,
+    /// Part of `amount` charged back so far.
+    #[serde(default)]
+    pub charged_back: Decimal,
+    pub opened_at: u64,
+    pub closed_at: Option<u64>,
+}
+
+impl Dispute {
+    /// Amount still held by this dispute.
+    pub fn held(&self) -> Decimal {
+        self.amount - self.released - self.charged_back
+    }
+}
+
+/// Every dispute ever raised, per transaction, oldest first. Only the latest dispute of a transaction can be open.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Disputes {
+    history: HashMap<u32, Vec<Dispute>>,
+}
+
+impl Disputes {
+    /// Returns true when the transaction currently has an open dispute.
+    pub fn is_open(&self, transaction_id: u32) -> bool {
+        self.latest(transaction_id).is_some_and(|dispute| dispute.state == DisputeState::Open)
+    }
+
+    /// The most recent dispute of the transaction, if it was ever disputed.
+    pub fn latest(&self, transaction_id: u32) -> Option<&Dispute> {
+        self.history(transaction_id).last()
+    }
+
+    /// Every dispute of the transaction, oldest first. Empty if it was never disputed.
+    pub fn history(&self, transaction_id: u32) -> &[Dispute] {
+        self.history.get(&transaction_id).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// IDs of transactions with an open dispute, sorted ascending.
+    pub fn open_transaction_ids(&self) -> Vec<u32> {
+        let mut transaction_ids: Vec<u32> = self.history.keys().cloned().filter(|transaction_id| self.is_open(*transaction_id)).collect();
+        transaction_ids.sort_unstable();
+        transaction_ids
+    }
+
+    /// IDs of every transaction that was ever disputed, sorted ascending.
+    pub fn transaction_ids(&self) -> Vec<u32> {
+        let mut transaction_ids: Vec<u32> = self.history.keys().cloned().collect();
+        transaction_ids.sort_unstable();
+        transaction_ids
+    }
+
+    /// Total charged back across every dispute of the transaction. That part can never be disputed again.
+    pub fn charged_back(&self, transaction_id: u32) -> Decimal {
+        self.history(transaction_id).iter().map(|dispute| dispute.charged_back).sum()
+    }
+
+    /// Opens a new dispute holding `amount`. The engine checks the transition is allowed before calling this.
+    pub fn open(&mut self, transaction_id: u32, amount: Decimal, at: u64) {
+        self.history.entry(transaction_id).or_default().push(Dispute {
+            state: DisputeState::Open,
+            amount,
+            released: Decimal::ZERO,
+            charged_back: Decimal::ZERO,
+            opened_at: at,
+            closed_at: None,
+        });
+    }
+
+    /// Releases `amount` of the open dispute of the transaction. Does nothing if none is open.
+    pub fn release(&mut self, transaction_id: u32, amount: Decimal, at: u64) {
+        if let Some(dispute) = self.open_dispute_mut(transaction_id) {
+            dispute.released += amount;
+            close_if_settled(dispute, at);
+        }
+    }
+
+    /// Charges back `amount` of the open dispute of the transaction. Does nothing if none is open.
+    pub fn charge_back(&mut self, transaction_id: u32, amount: Decimal, at: u64) {
+        if let Some(dispute) = self.open_dispute_mut(transaction_id) {
+            dispute.charged_back += amount;
+            close_if_settled(dispute, at);
+        }
+    }
+
+    /// Replaces the history of a transaction, e.g. when loading a snapshot.
+    pub fn insert_history(&mut self, transaction_id: u32, history: Vec<Dispute>) {
+        self.history.insert(transaction_id, history);
+    }
+
+    pub(crate) fn into_histories(self) -> impl Iterator<Item = (u32, Vec<Dispute>)> {
+        self.history.into_iter()
+    }
+
+    fn open_dispute_mut(&mut self, transaction_id: u32) -> Option<&mut Dispute> {
+        self.history.get_mut(&transaction_id)
+            .and_then(|history| history.last_mut())
+            .filter(|dispute| dispute.state == DisputeState::Open)
+    }
+}
+
+fn close_if_settled(dispute: &mut Dispute, at: u64) {
+    if dispute.held() <= Decimal::ZERO {
+        dispute.state = if dispute.charged_back > Decimal::ZERO { DisputeState::ChargedBack } else { DisputeState::Resolved };
+        dispute.closed_at = Some(at);
+    }
+}
@@ -1,24 +1,433 @@
 use log::{warn, error};
-use crate::model::{Account, TransactionRecord, TransactionType};
+use rust_decimal::{Decimal, RoundingStrategy};
+use crate::authorization::{Authorization, Authorizations};
+use crate::currency;
+use crate::config::{EngineConfig, WithdrawalDisputePolicy};
+use crate::dispute::{Dispute, Disputes};
+use crate::model::{Account, RejectionReason, TransactionOutcome, TransactionRecord, TransactionType};
+use crate::journal::{LedgerEvent, LedgerEventKind};
 use crate::error::ApplicationError;
-use std::collections::{HashMap, HashSet};
+use crate::rates::RateTable;
+use crate::validation::AMOUNT_DECIMAL_PLACES;
+use crate::storage::{AccountStore, TransactionStore};
+use std::collections::HashMap;
 
-pub struct Engine {
-    pub accounts: HashMap<u16, Account>,
-    pub transactions: HashMap<u32, TransactionRecord>,
-    pub disputes: HashSet<u32>,
+/// Largest balance magnitude an account can hold. This is the full 96-bit Decimal mantissa at four decimal places,
+/// above which balances could no longer be kept exact to four places.
+pub const MAX_BALANCE: Decimal = Decimal::from_parts(u32::MAX, u32::MAX, u32::MAX, false, 4);
+
+// Amounts held by open disputes of one client, per currency (None for the default currency).
+type DisputedAmounts = HashMap<Option<String>, Decimal>;
+
+// Amounts reserved by open authorizations of one client, per currency (None for the default currency).
+type ReservedAmounts = HashMap<Option<String>, Decimal>;
+
+/// Payments engine. Accounts and transactions live in pluggable stores, defaulting to in-memory HashMaps.
+pub struct Engine<A = HashMap<u16, Account>, T = HashMap<u32, TransactionRecord>> {
+    pub accounts: A,
+    pub transactions: T,
+    pub disputes: Disputes,
+    pub authorizations: Authorizations,
+    pub config: EngineConfig,
+    /// Rates Convert records are applied at. Empty by default, which rejects every conversion.
+    pub rates: RateTable,
+    // Events recorded since the last take_events call. Only populated when journaling is enabled.
+    events: Vec<LedgerEvent>,
+    journaling: bool,
+    pub(crate) next_sequence: u64,
+    pub(crate) record_sequence: u64,
+    // Latest timestamp of any record processed so far. Authorizations expire by it.
+    pub(crate) clock: Option<u64>,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Engine {
     pub fn new() -> Self {
+        Engine::with_stores(HashMap::new(), HashMap::new())
+    }
+
+    /// Creates an in-memory engine with the given behavior options.
+    pub fn with_config(config: EngineConfig) -> Self {
+        let mut engine = Engine::new();
+        engine.config = config;
+        engine
+    }
+}
+
+impl<A: AccountStore, T: TransactionStore> Engine<A, T> {
+    /// Creates an engine on top of the given stores, e.g. the SQLite stores for inputs too large for memory.
+    pub fn with_stores(accounts: A, transactions: T) -> Self {
         Engine {
-            accounts: HashMap::new(),
-            transactions: HashMap::new(),
-            disputes: HashSet::new(),
+            accounts,
+            transactions,
+            disputes: Disputes::default(),
+            authorizations: Authorizations::default(),
+            config: EngineConfig::default(),
+            rates: RateTable::default(),
+            events: Vec::new(),
+            journaling: false,
+            next_sequence: 0,
+            record_sequence: 0,
+            clock: None,
+        }
+    }
+
+    /// Starts recording a LedgerEvent for every applied state change. Recorded events must be drained with take_events.
+    pub fn enable_journal(&mut self) {
+        self.journaling = true;
+    }
+
+    /// Sequence number the next journaled event will receive.
+    pub fn journal_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+
+    /// Number of records processed so far, including rejected ones. Dispute history is stamped with it.
+    pub fn record_sequence(&self) -> u64 {
+        self.record_sequence
+    }
+
+    /// Latest timestamp of any record processed so far, which authorizations expire by. None until a record carries one.
+    pub fn clock(&self) -> Option<u64> {
+        self.clock
+    }
+
+    /// Every dispute raised against the transaction, oldest first, with the record sequence numbers that opened and closed it.
+    pub fn dispute_history(&self, transaction_id: u32) -> &[Dispute] {
+        self.disputes.history(transaction_id)
+    }
+
+    /// Drains the events recorded since the previous call.
+    pub fn take_events(&mut self) -> Vec<LedgerEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    // Records an event along with the resulting state of the affected account, in the currency the event is in.
+    // Returns the event so callers can fill in what only some events carry, such as a reason or a rate.
+    fn record_event(&mut self, kind: LedgerEventKind, client_id: u16, transaction_id: u32, amount: Option<Decimal>, currency: Option<&str>, account: &Account) -> Option<&mut LedgerEvent> {
+        if !self.journaling {
+            return None;
+        }
+        let balance = account.balance(currency);
+        self.events.push(LedgerEvent {
+            sequence: self.next_sequence,
+            record: self.record_sequence,
+            kind,
+            client_id,
+            transaction_id,
+            amount,
+            available: balance.available,
+            held: balance.held,
+            total: balance.total,
+            locked: account.locked,
+            closed: account.closed,
+            reason: None,
+            currency: currency.map(str::to_string),
+            rate: None,
+            counterparty: None,
+            reserved: balance.reserved,
+            expires_after: None,
+            expires_at: None,
+            original_transaction_id: None,
+        });
+        self.next_sequence += 1;
+        self.events.last_mut()
+    }
+
+    /// Re-applies a journaled event and verifies the affected account ends up in the state the event recorded.
+    /// Used to rebuild an Engine from a journal without the original input.
+    pub fn apply_event(&mut self, event: &LedgerEvent) -> Result<(), ApplicationError> {
+        if event.sequence != self.next_sequence {
+            return Err(ApplicationError::JournalOutOfOrder { expected: self.next_sequence, found: event.sequence });
+        }
+        let amount = event.amount.unwrap_or(Decimal::ZERO);
+        let currency = event.currency.as_deref();
+        self.record_sequence = self.record_sequence.max(event.record);
+        let mut account = self.accounts.get_account(event.client_id)?.unwrap_or_default();
+        let mut balance = account.balance(currency);
+        match event.kind {
+            LedgerEventKind::DepositCredited => {
+                balance.available = checked_add(balance.available, amount, event.client_id, event.transaction_id)?;
+                balance.total = checked_add(balance.total, amount, event.client_id, event.transaction_id)?;
+                self.transactions.put_transaction(TransactionRecord {
+                    currency: event.currency.clone(),
+                    ..TransactionRecord::new(TransactionType::Deposit, event.client_id, event.transaction_id, event.amount)
+                })?;
+            }
+            LedgerEventKind::WithdrawalDebited => {
+                balance.available = checked_sub(balance.available, amount, event.client_id, event.transaction_id)?;
+                balance.total = checked_sub(balance.total, amount, event.client_id, event.transaction_id)?;
+                self.transactions.put_transaction(TransactionRecord {
+                    currency: event.currency.clone(),
+                    ..TransactionRecord::new(TransactionType::Withdrawal, event.client_id, event.transaction_id, event.amount)
+                })?;
+            }
+            LedgerEventKind::FundsHeld => {
+                balance.available = checked_sub(balance.available, amount, event.client_id, event.transaction_id)?;
+                balance.held = checked_add(balance.held, amount, event.client_id, event.transaction_id)?;
+                self.disputes.open(event.transaction_id, amount, event.record);
+            }
+            LedgerEventKind::FundsReleased => {
+                balance.held = checked_sub(balance.held, amount, event.client_id, event.transaction_id)?;
+                balance.available = checked_add(balance.available, amount, event.client_id, event.transaction_id)?;
+                self.disputes.release(event.transaction_id, amount, event.record);
+            }
+            LedgerEventKind::ChargebackPosted => {
+                balance.held = checked_sub(balance.held, amount, event.client_id, event.transaction_id)?;
+                balance.total = checked_sub(balance.total, amount, event.client_id, event.transaction_id)?;
+                self.disputes.charge_back(event.transaction_id, amount, event.record);
+            }
+            LedgerEventKind::CreditPending => {
+                balance.held = checked_add(balance.held, amount, event.client_id, event.transaction_id)?;
+                balance.total = checked_add(balance.total, amount, event.client_id, event.transaction_id)?;
+                self.disputes.open(event.transaction_id, amount, event.record);
+            }
+            LedgerEventKind::CreditCancelled => {
+                balance.held = checked_sub(balance.held, amount, event.client_id, event.transaction_id)?;
+                balance.total = checked_sub(balance.total, amount, event.client_id, event.transaction_id)?;
+                self.disputes.release(event.transaction_id, amount, event.record);
+            }
+            LedgerEventKind::CreditPosted => {
+                balance.held = checked_sub(balance.held, amount, event.client_id, event.transaction_id)?;
+                balance.available = checked_add(balance.available, amount, event.client_id, event.transaction_id)?;
+                self.disputes.charge_back(event.transaction_id, amount, event.record);
+            }
+            LedgerEventKind::AccountLocked | LedgerEventKind::AccountFrozen => {
+                account.locked = true;
+            }
+            LedgerEventKind::AccountUnlocked => {
+                account.locked = false;
+            }
+            LedgerEventKind::AccountClosed => {
+                account.locked = true;
+                account.closed = true;
+            }
+            LedgerEventKind::BalanceAdjusted | LedgerEventKind::ConversionReversed | LedgerEventKind::TransferCredited | LedgerEventKind::TransferReversed => {
+                balance.available = checked_add(balance.available, amount, event.client_id, event.transaction_id)?;
+                balance.total = checked_add(balance.total, amount, event.client_id, event.transaction_id)?;
+            }
+            LedgerEventKind::ConversionDebited => {
+                balance.available = checked_sub(balance.available, amount, event.client_id, event.transaction_id)?;
+                balance.total = checked_sub(balance.total, amount, event.client_id, event.transaction_id)?;
+                self.transactions.put_transaction(TransactionRecord {
+                    currency: event.currency.clone(),
+                    rate: event.rate,
+                    ..TransactionRecord::new(TransactionType::Convert, event.client_id, event.transaction_id, event.amount)
+                })?;
+            }
+            LedgerEventKind::TransferDebited => {
+                balance.available = checked_sub(balance.available, amount, event.client_id, event.transaction_id)?;
+                balance.total = checked_sub(balance.total, amount, event.client_id, event.transaction_id)?;
+                self.transactions.put_transaction(TransactionRecord {
+                    currency: event.currency.clone(),
+                    counterparty: event.counterparty,
+                    ..TransactionRecord::new(TransactionType::Transfer, event.client_id, event.transaction_id, event.amount)
+                })?;
+            }
+            LedgerEventKind::RefundCredited => {
+                balance.available = checked_add(balance.available, amount, event.client_id, event.transaction_id)?;
+                balance.total = checked_add(balance.total, amount, event.client_id, event.transaction_id)?;
+                self.transactions.put_transaction(TransactionRecord {
+                    currency: event.currency.clone(),
+                    original_transaction_id: event.original_transaction_id,
+                    ..TransactionRecord::new(TransactionType::Refund, event.client_id, event.transaction_id, event.amount)
+                })?;
+                // The refunded withdrawal keeps count of what was refunded of it
+                let mut original = match event.original_transaction_id.map(|original_id| self.transactions.get_transaction(original_id)).transpose()?.flatten() {
+                    Some(original) => original,
+                    None => {
+                        error!("Replaying refund {} for transaction {}, but the refunded withdrawal was never replayed.", event.sequence, event.transaction_id);
+                        return Err(ApplicationError::JournalMismatch { sequence: event.sequence, event: event.kind, client_id: event.client_id });
+                    }
+                };
+                original.refunded = Some(original.refunded.unwrap_or_default() + amount);
+                self.transactions.put_transaction(original)?;
+            }
+            LedgerEventKind::FundsReserved => {
+                balance.available = checked_sub(balance.available, amount, event.client_id, event.transaction_id)?;
+                balance.reserved = checked_add(balance.reserved, amount, event.client_id, event.transaction_id)?;
+                self.transactions.put_transaction(TransactionRecord {
+                    currency: event.currency.clone(),
+                    ..TransactionRecord::new(TransactionType::Authorize, event.client_id, event.transaction_id, event.amount)
+                })?;
+                self.authorizations.open(event.transaction_id, Authorization {
+                    client_id: event.client_id,
+                    currency: event.currency.clone(),
+                    amount,
+                    authorized_at: event.record,
+                    expires_after: event.expires_after,
+                    expires_at: event.expires_at,
+                });
+            }
+            LedgerEventKind::ReservationCaptured => {
+                balance.reserved = checked_sub(balance.reserved, amount, event.client_id, event.transaction_id)?;
+                balance.total = checked_sub(balance.total, amount, event.client_id, event.transaction_id)?;
+                self.transactions.put_transaction(TransactionRecord {
+                    currency: event.currency.clone(),
+                    ..TransactionRecord::new(TransactionType::Withdrawal, event.client_id, event.transaction_id, event.amount)
+                })?;
+                self.authorizations.close(event.transaction_id);
+            }
+            LedgerEventKind::ReservationReleased | LedgerEventKind::ReservationExpired => {
+                balance.reserved = checked_sub(balance.reserved, amount, event.client_id, event.transaction_id)?;
+                balance.available = checked_add(balance.available, amount, event.client_id, event.transaction_id)?;
+                self.authorizations.release(event.transaction_id, amount);
+            }
+            LedgerEventKind::ConversionCredited => {
+                balance.available = checked_add(balance.available, amount, event.client_id, event.transaction_id)?;
+                balance.total = checked_add(balance.total, amount, event.client_id, event.transaction_id)?;
+                // The debit event before this one stored the transaction. Only this one knows the target currency.
+                let mut record = match self.transactions.get_transaction(event.transaction_id)? {
+                    Some(record) => record,
+                    None => {
+                        error!("Replaying conversion credit {} for transaction {}, but its debit was never replayed.", event.sequence, event.transaction_id);
+                        return Err(ApplicationError::JournalMismatch { sequence: event.sequence, event: event.kind, client_id: event.client_id });
+                    }
+                };
+                record.target_currency = event.currency.clone();
+                self.transactions.put_transaction(record)?;
+            }
+        }
+        // Only events with an amount move funds. The others must not open a balance in a currency the account never held.
+        if event.amount.is_some() {
+            account.set_balance(currency, balance);
+        }
+        if !event.matches(&account) {
+            error!("Replaying event {} for client {} did not reproduce the recorded account state.", event.sequence, event.client_id);
+            return Err(ApplicationError::JournalMismatch { sequence: event.sequence, event: event.kind, client_id: event.client_id });
+        }
+        self.accounts.put_account(event.client_id, account)?;
+        self.next_sequence += 1;
+        Ok(())
+    }
+
+    /// Verifies the ledger invariants of every account against the stored transactions, open disputes and open authorizations,
+    /// in every currency: available + held + reserved == total, held and reserved are never negative, held equals the sum of
+    /// the client's disputed amounts and reserved the sum of its authorized amounts. Returns the first violation found.
+    pub fn check_invariants(&self) -> Result<(), ApplicationError> {
+        let mut disputed = self.disputed_amounts(None)?;
+        let mut reserved = self.reserved_amounts(None);
+        for client_id in self.accounts.client_ids()? {
+            if let Some(account) = self.accounts.get_account(client_id)? {
+                let client_reserved = reserved.remove(&client_id).unwrap_or_default();
+                check_account_invariants(client_id, &account, disputed.remove(&client_id).unwrap_or_default(), client_reserved)?;
+            }
+        }
+        // Any disputed amount left over belongs to a client without an account
+        if let Some(client_id) = disputed.keys().min() {
+            return Err(invariant_violation(*client_id, "open disputes reference a client without an account".to_string()));
+        }
+        if let Some(client_id) = reserved.keys().min() {
+            return Err(invariant_violation(*client_id, "open authorizations reference a client without an account".to_string()));
+        }
+        Ok(())
+    }
+
+    // Sums the amounts reserved by open authorizations per client and currency, optionally only for one client.
+    fn reserved_amounts(&self, only_client_id: Option<u16>) -> HashMap<u16, ReservedAmounts> {
+        let mut reserved: HashMap<u16, ReservedAmounts> = HashMap::new();
+        for transaction_id in self.authorizations.transaction_ids() {
+            if let Some(authorization) = self.authorizations.get(transaction_id) {
+                if only_client_id.is_some_and(|client_id| client_id != authorization.client_id) {
+                    continue;
+                }
+                *reserved.entry(authorization.client_id).or_default().entry(authorization.currency.clone()).or_default() += authorization.amount;
+            }
+        }
+        reserved
+    }
+
+    // Releases the funds of every authorization that expired by the current record, before that record is applied.
+    pub(crate) fn expire_authorizations(&mut self) -> Result<(), ApplicationError> {
+        for transaction_id in self.authorizations.expired(self.record_sequence, self.clock) {
+            let authorization = match self.authorizations.get(transaction_id).cloned() {
+                Some(authorization) => authorization,
+                None => continue,
+            };
+            let client_id = authorization.client_id;
+            let mut account = match self.accounts.get_account(client_id)? {
+                Some(account) => account,
+                None => {
+                    // This shouldn’t normally happen, but guard nonetheless.
+                    error!("Authorization {} expired, but account is missing for client {}.", transaction_id, client_id);
+                    return Err(ApplicationError::AccountNotFound { client_id, transaction_type: TransactionType::Authorize });
+                }
+            };
+            let currency = authorization.currency.as_deref();
+            let mut balance = account.balance(currency);
+            balance.reserved = checked_sub(balance.reserved, authorization.amount, client_id, transaction_id)?;
+            balance.available = checked_add(balance.available, authorization.amount, client_id, transaction_id)?;
+            account.set_balance(currency, balance);
+            self.authorizations.close(transaction_id);
+            self.record_event(LedgerEventKind::ReservationExpired, client_id, transaction_id, Some(authorization.amount), currency, &account);
+            self.accounts.put_account(client_id, account)?;
+        }
+        Ok(())
+    }
+
+    // Sums the amounts held by open disputes per owning client and currency, optionally only for one client.
+    fn disputed_amounts(&self, only_client_id: Option<u16>) -> Result<HashMap<u16, DisputedAmounts>, ApplicationError> {
+        let mut disputed: HashMap<u16, DisputedAmounts> = HashMap::new();
+        for transaction_id in self.disputes.open_transaction_ids() {
+            let disputed_transaction = match self.transactions.get_transaction(transaction_id)? {
+                Some(disputed_transaction) => disputed_transaction,
+                None => {
+                    error!("Dispute exists for unknown transaction {}.", transaction_id);
+                    return Err(ApplicationError::TransactionNotFound { transaction_id, transaction_type: TransactionType::Dispute });
+                }
+            };
+            let holder_id = disputed_transaction.holding_client_id();
+            if only_client_id.is_some_and(|client_id| client_id != holder_id) {
+                continue;
+            }
+            let held = self.disputes.latest(transaction_id).map(Dispute::held).unwrap_or_default();
+            let currency = disputed_transaction.disputable_funds().0.map(str::to_string);
+            let sum = disputed.entry(holder_id).or_default().entry(currency).or_default();
+            *sum = sum.checked_add(held)
+                .ok_or_else(|| invariant_violation(holder_id, "disputed amounts overflow".to_string()))?;
+        }
+        Ok(disputed)
+    }
+
+    /// Applies a single record to the engine.
+    /// Records that represent bad input are skipped and reported as TransactionOutcome::Rejected,
+    /// while Err is reserved for broken engine state.
+    ///
+    /// Debug builds verify the invariants of the affected account after every applied record and panic on a violation.
+    pub fn process_transaction(&mut self, record: TransactionRecord) -> Result<TransactionOutcome, ApplicationError> {
+        // A transfer moves the funds of its counterparty too, and a dispute of one holds them on the recipient
+        let mut client_ids = vec![record.client_id];
+        if cfg!(debug_assertions) {
+            client_ids.extend(record.counterparty);
+            if let Some(transaction) = self.transactions.get_transaction(record.transaction_id)? {
+                client_ids.push(transaction.holding_client_id());
+            }
+            client_ids.dedup();
+        }
+        self.record_sequence += 1;
+        self.clock = self.clock.max(record.timestamp);
+        self.expire_authorizations()?;
+        let outcome = self.apply_record(record)?;
+        if cfg!(debug_assertions) && outcome.is_applied() {
+            for client_id in client_ids {
+                if let Some(account) = self.accounts.get_account(client_id)? {
+                    let disputed = self.disputed_amounts(Some(client_id))?.remove(&client_id).unwrap_or_default();
+                    let reserved = self.reserved_amounts(Some(client_id)).remove(&client_id).unwrap_or_default();
+                    if let Err(e) = check_account_invariants(client_id, &account, disputed, reserved) {
+                        panic!("{}", e);
+                    }
+                }
+            }
         }
+        Ok(outcome)
     }
 
-    pub fn process_transaction(&mut self, record: TransactionRecord) -> Result<(), ApplicationError> {
+    fn apply_record(&mut self, record: TransactionRecord) -> Result<TransactionOutcome, ApplicationError> {
         let client_id = record.client_id;
         let transaction_id = record.transaction_id;
 
@@ -29,29 +438,40 @@ impl Engine {
                     Some(amount) => amount,
                     None => {
                         warn!("Deposit transaction {} missing amount. Skipping.", transaction_id);
-                        return Ok(());
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::MissingAmount));
                     }
                 };
                 
                 // Create account if it doesn't exist on Deposit
-                let account = self.accounts.entry(client_id).or_insert_with(Account::new);
+                let mut account = self.accounts.get_account(client_id)?.unwrap_or_default();
+
+                // Warn and skip if account is closed on Deposit.
+                if account.closed {
+                    warn!("Deposit on closed account is not allowed for client {} in transaction {}. Skipping.", client_id, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::AccountClosed));
+                }
 
                 // Warn and skip if account is locked on Deposit.
                 if account.locked {
                     warn!("Deposit on locked account is not allowed for client {} in transaction {}. Skipping.", client_id, transaction_id);
-                    return Ok(());
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::AccountLocked));
                 }
 
 
                 // Warn and skip if the transaction ID has already been used.
-                if self.transactions.contains_key(&transaction_id) {
+                if self.transactions.contains_transaction(transaction_id)? {
                     warn!("Transaction has already been processed for transaction {}. Skipping.", transaction_id);
-                    return Ok(());
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::DuplicateTransaction));
                 }
 
-                account.available += amount;
-                account.total += amount;
-                self.transactions.insert(transaction_id, record);
+                let currency = record.currency.clone();
+                let mut balance = account.balance(currency.as_deref());
+                balance.available = checked_add(balance.available, amount, client_id, transaction_id)?;
+                balance.total = checked_add(balance.total, amount, client_id, transaction_id)?;
+                account.set_balance(currency.as_deref(), balance);
+                self.transactions.put_transaction(record)?;
+                self.record_event(LedgerEventKind::DepositCredited, client_id, transaction_id, Some(amount), currency.as_deref(), &account);
+                self.accounts.put_account(client_id, account)?;
             }
             TransactionType::Withdrawal => {
                 // Warn and skip when Withdrawal transaction is missing amount.
@@ -59,91 +479,611 @@ impl Engine {
                     Some(amount) => amount,
                     None => {
                         warn!("Withdrawal transaction {} missing amount. Skipping.", transaction_id);
-                        return Ok(());
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::MissingAmount));
                     }
                 };
 
                 // Warn and skip if account doesn't exist on Withdrawal.
-                let account = match self.accounts.get_mut(&client_id) {
+                let mut account = match self.accounts.get_account(client_id)? {
                     Some(account) => account,
                     None => {
                         warn!("Withdrawal for unknown client {} in transaction {}. Skipping.", client_id, transaction_id);
-                        return Ok(());
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::UnknownAccount));
                     }
                 };
 
+                // Warn and skip if account is closed on Withdrawal.
+                if account.closed {
+                    warn!("Withdrawal on closed account is not allowed for client {} in transaction {}. Skipping.", client_id, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::AccountClosed));
+                }
+
                 // Warn and skip if account is locked on Withdrawal.
                 if account.locked {
                     warn!("Withdrawal on locked account is not allowed for client {} in transaction {}. Skipping.", client_id, transaction_id);
-                    return Ok(());
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::AccountLocked));
                 }
 
 
                 // Warn and skip if the transaction ID has already been used.
-                if self.transactions.contains_key(&transaction_id) {
+                if self.transactions.contains_transaction(transaction_id)? {
                     warn!("Transaction has already been processed for transaction {}. Skipping.", transaction_id);
-                    return Ok(());
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::DuplicateTransaction));
                 }
 
-                if account.available >= amount {
-                    account.available -= amount;
-                    account.total -= amount;
-                    self.transactions.insert(transaction_id, record);
+                // Funds are withdrawn from the bucket of the withdrawal's currency only. Other currencies don't cover it.
+                let currency = record.currency.clone();
+                let mut balance = account.balance(currency.as_deref());
+                if balance.available >= amount {
+                    balance.available = checked_sub(balance.available, amount, client_id, transaction_id)?;
+                    balance.total = checked_sub(balance.total, amount, client_id, transaction_id)?;
+                    account.set_balance(currency.as_deref(), balance);
+                    self.transactions.put_transaction(record)?;
+                    self.record_event(LedgerEventKind::WithdrawalDebited, client_id, transaction_id, Some(amount), currency.as_deref(), &account);
+                    self.accounts.put_account(client_id, account)?;
                 }
                 else {
                     warn!("Withdrawal request failed due to insufficient available funds for client {} in transaction {}. Skipping.", client_id, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::InsufficientFunds));
                 }
             }
+            TransactionType::Convert => {
+                // Warn and skip when Convert transaction is missing amount.
+                let amount = match record.amount {
+                    Some(amount) => amount,
+                    None => {
+                        warn!("Convert transaction {} missing amount. Skipping.", transaction_id);
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::MissingAmount));
+                    }
+                };
+
+                // Warn and skip if account doesn't exist on Convert.
+                let mut account = match self.accounts.get_account(client_id)? {
+                    Some(account) => account,
+                    None => {
+                        warn!("Convert for unknown client {} in transaction {}. Skipping.", client_id, transaction_id);
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::UnknownAccount));
+                    }
+                };
+
+                // Warn and skip if account is closed on Convert.
+                if account.closed {
+                    warn!("Convert on closed account is not allowed for client {} in transaction {}. Skipping.", client_id, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::AccountClosed));
+                }
+
+                // Warn and skip if account is locked on Convert.
+                if account.locked {
+                    warn!("Convert on locked account is not allowed for client {} in transaction {}. Skipping.", client_id, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::AccountLocked));
+                }
+
+                // Warn and skip if the transaction ID has already been used.
+                if self.transactions.contains_transaction(transaction_id)? {
+                    warn!("Transaction has already been processed for transaction {}. Skipping.", transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::DuplicateTransaction));
+                }
+
+                // Warn and skip unless Convert names two different currencies. The default currency has no code to list rates by.
+                let (source, target) = match (record.currency.clone(), record.target_currency.clone()) {
+                    (Some(source), Some(target)) if source != target => (source, target),
+                    _ => {
+                        warn!("Convert transaction {} needs a currency and a different target currency. Skipping.", transaction_id);
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::InvalidConversion));
+                    }
+                };
+
+                // Warn and skip when Convert transaction is missing the timestamp its rate is picked by.
+                let timestamp = match record.timestamp {
+                    Some(timestamp) => timestamp,
+                    None => {
+                        warn!("Convert transaction {} missing timestamp. Skipping.", transaction_id);
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::MissingTimestamp));
+                    }
+                };
+
+                // Warn and skip when no rate is in effect for the currencies at the timestamp on Convert.
+                let rate = match self.rates.rate(&source, &target, timestamp) {
+                    Some(rate) => rate,
+                    None => {
+                        warn!("No rate from {} to {} is in effect at {} for transaction {}. Skipping.", source, target, timestamp, transaction_id);
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::UnknownRate));
+                    }
+                };
+
+                // Warn and skip when the converted amount is too small for the minor units of the target currency.
+                let converted = match currency::convert(amount, rate, Some(&target)) {
+                    Some(converted) if converted > Decimal::ZERO => converted,
+                    Some(_) => {
+                        warn!("Convert transaction {} amount {} converts to nothing in {}. Skipping.", transaction_id, amount, target);
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::NonPositiveAmount));
+                    }
+                    None => {
+                        warn!("Convert transaction {} is in unsupported target currency {}. Skipping.", transaction_id, target);
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::UnknownCurrency));
+                    }
+                };
+
+                let mut source_balance = account.balance(Some(&source));
+                if source_balance.available < amount {
+                    warn!("Convert request failed due to insufficient available funds for client {} in transaction {}. Skipping.", client_id, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::InsufficientFunds));
+                }
+                source_balance.available = checked_sub(source_balance.available, amount, client_id, transaction_id)?;
+                source_balance.total = checked_sub(source_balance.total, amount, client_id, transaction_id)?;
+                let mut target_balance = account.balance(Some(&target));
+                target_balance.available = checked_add(target_balance.available, converted, client_id, transaction_id)?;
+                target_balance.total = checked_add(target_balance.total, converted, client_id, transaction_id)?;
+
+                // The rate is stored with the transaction, so a later chargeback reverses the conversion at it
+                account.set_balance(Some(&source), source_balance);
+                if let Some(event) = self.record_event(LedgerEventKind::ConversionDebited, client_id, transaction_id, Some(amount), Some(&source), &account) {
+                    event.rate = Some(rate);
+                }
+                account.set_balance(Some(&target), target_balance);
+                if let Some(event) = self.record_event(LedgerEventKind::ConversionCredited, client_id, transaction_id, Some(converted), Some(&target), &account) {
+                    event.rate = Some(rate);
+                }
+                self.transactions.put_transaction(TransactionRecord { rate: Some(rate), ..record })?;
+                self.accounts.put_account(client_id, account)?;
+            }
+            TransactionType::Transfer => {
+                // Warn and skip when Transfer transaction is missing amount.
+                let amount = match record.amount {
+                    Some(amount) => amount,
+                    None => {
+                        warn!("Transfer transaction {} missing amount. Skipping.", transaction_id);
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::MissingAmount));
+                    }
+                };
+
+                // Warn and skip unless Transfer names a counterparty other than the client.
+                let recipient_id = match record.counterparty {
+                    Some(counterparty) if counterparty != client_id => counterparty,
+                    _ => {
+                        warn!("Transfer transaction {} needs a counterparty other than client {}. Skipping.", transaction_id, client_id);
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::InvalidCounterparty));
+                    }
+                };
+
+                // Warn and skip if account doesn't exist on Transfer.
+                let mut account = match self.accounts.get_account(client_id)? {
+                    Some(account) => account,
+                    None => {
+                        warn!("Transfer for unknown client {} in transaction {}. Skipping.", client_id, transaction_id);
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::UnknownAccount));
+                    }
+                };
+
+                // Warn and skip if account is closed on Transfer.
+                if account.closed {
+                    warn!("Transfer on closed account is not allowed for client {} in transaction {}. Skipping.", client_id, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::AccountClosed));
+                }
+
+                // Warn and skip if account is locked on Transfer.
+                if account.locked {
+                    warn!("Transfer on locked account is not allowed for client {} in transaction {}. Skipping.", client_id, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::AccountLocked));
+                }
+
+                // Warn and skip if the counterparty has no account on Transfer. Transfers never open accounts.
+                let mut recipient = match self.accounts.get_account(recipient_id)? {
+                    Some(recipient) => recipient,
+                    None => {
+                        warn!("Transfer to unknown client {} in transaction {}. Skipping.", recipient_id, transaction_id);
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::UnknownCounterparty));
+                    }
+                };
+
+                // Warn and skip if the counterparty account is closed on Transfer.
+                if recipient.closed {
+                    warn!("Transfer to closed account is not allowed for client {} in transaction {}. Skipping.", recipient_id, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::CounterpartyClosed));
+                }
+
+                // Warn and skip if the counterparty account is locked on Transfer.
+                if recipient.locked {
+                    warn!("Transfer to locked account is not allowed for client {} in transaction {}. Skipping.", recipient_id, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::CounterpartyLocked));
+                }
+
+                // Warn and skip if the transaction ID has already been used.
+                if self.transactions.contains_transaction(transaction_id)? {
+                    warn!("Transaction has already been processed for transaction {}. Skipping.", transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::DuplicateTransaction));
+                }
+
+                let currency = record.currency.clone();
+                let mut balance = account.balance(currency.as_deref());
+                if balance.available < amount {
+                    warn!("Transfer request failed due to insufficient available funds for client {} in transaction {}. Skipping.", client_id, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::InsufficientFunds));
+                }
+
+                // Both sides are computed before either is stored, so a transfer applies to both accounts or to neither
+                balance.available = checked_sub(balance.available, amount, client_id, transaction_id)?;
+                balance.total = checked_sub(balance.total, amount, client_id, transaction_id)?;
+                let mut recipient_balance = recipient.balance(currency.as_deref());
+                recipient_balance.available = checked_add(recipient_balance.available, amount, recipient_id, transaction_id)?;
+                recipient_balance.total = checked_add(recipient_balance.total, amount, recipient_id, transaction_id)?;
+
+                account.set_balance(currency.as_deref(), balance);
+                if let Some(event) = self.record_event(LedgerEventKind::TransferDebited, client_id, transaction_id, Some(amount), currency.as_deref(), &account) {
+                    event.counterparty = Some(recipient_id);
+                }
+                recipient.set_balance(currency.as_deref(), recipient_balance);
+                if let Some(event) = self.record_event(LedgerEventKind::TransferCredited, recipient_id, transaction_id, Some(amount), currency.as_deref(), &recipient) {
+                    event.counterparty = Some(client_id);
+                }
+                self.transactions.put_transaction(record)?;
+                self.accounts.put_account(client_id, account)?;
+                self.accounts.put_account(recipient_id, recipient)?;
+            }
+            TransactionType::Authorize => {
+                // Warn and skip when Authorize transaction is missing amount.
+                let amount = match record.amount {
+                    Some(amount) => amount,
+                    None => {
+                        warn!("Authorize transaction {} missing amount. Skipping.", transaction_id);
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::MissingAmount));
+                    }
+                };
+
+                // Warn and skip if account doesn't exist on Authorize.
+                let mut account = match self.accounts.get_account(client_id)? {
+                    Some(account) => account,
+                    None => {
+                        warn!("Authorize for unknown client {} in transaction {}. Skipping.", client_id, transaction_id);
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::UnknownAccount));
+                    }
+                };
+
+                // Warn and skip if account is closed on Authorize.
+                if account.closed {
+                    warn!("Authorize on closed account is not allowed for client {} in transaction {}. Skipping.", client_id, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::AccountClosed));
+                }
+
+                // Warn and skip if account is locked on Authorize.
+                if account.locked {
+                    warn!("Authorize on locked account is not allowed for client {} in transaction {}. Skipping.", client_id, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::AccountLocked));
+                }
+
+                // Warn and skip if the transaction ID has already been used.
+                if self.transactions.contains_transaction(transaction_id)? {
+                    warn!("Transaction has already been processed for transaction {}. Skipping.", transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::DuplicateTransaction));
+                }
+
+                // Funds are reserved from the bucket of the authorization's currency only, as for a withdrawal.
+                let currency = record.currency.clone();
+                let mut balance = account.balance(currency.as_deref());
+                if balance.available < amount {
+                    warn!("Authorize request failed due to insufficient available funds for client {} in transaction {}. Skipping.", client_id, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::InsufficientFunds));
+                }
+                balance.available = checked_sub(balance.available, amount, client_id, transaction_id)?;
+                balance.reserved = checked_add(balance.reserved, amount, client_id, transaction_id)?;
+                account.set_balance(currency.as_deref(), balance);
+
+                // Expiry is fixed when the funds are reserved, so later configuration changes don't move it
+                let authorization = Authorization {
+                    client_id,
+                    currency: currency.clone(),
+                    amount,
+                    authorized_at: self.record_sequence,
+                    expires_after: self.config.authorization_expiry_records.map(|records| self.record_sequence.saturating_add(records)),
+                    expires_at: self.config.authorization_expiry_seconds.zip(record.timestamp).map(|(seconds, timestamp)| timestamp.saturating_add(seconds)),
+                };
+                if let Some(event) = self.record_event(LedgerEventKind::FundsReserved, client_id, transaction_id, Some(amount), currency.as_deref(), &account) {
+                    event.expires_after = authorization.expires_after;
+                    event.expires_at = authorization.expires_at;
+                }
+                self.authorizations.open(transaction_id, authorization);
+                self.transactions.put_transaction(record)?;
+                self.accounts.put_account(client_id, account)?;
+            }
+            TransactionType::Capture | TransactionType::Void => {
+                let transaction_type = record.transaction_type;
+
+                // Warn and skip when the transaction is not an open authorization on Capture and Void.
+                let authorization = match self.authorizations.get(transaction_id).cloned() {
+                    Some(authorization) => authorization,
+                    None => {
+                        warn!("{} on transaction {}, which is not an open authorization. Skipping.", transaction_type, transaction_id);
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::NotAuthorized));
+                    }
+                };
+
+                // Warn and skip for captures and voids that have transaction_id / client_id mismatch.
+                if client_id != authorization.client_id {
+                    warn!("{} for authorization {} has mismatched client_id. Authorization client_id is {}. {} record client_id is {}. Skipping.", transaction_type, transaction_id, authorization.client_id, transaction_type, client_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::ClientMismatch { owner_client_id: authorization.client_id }));
+                }
+
+                // Warn and skip captures and voids naming a different currency than the authorization. Rows without one take the authorization's.
+                let currency = authorization.currency.as_deref();
+                if record.currency.is_some() && record.currency.as_deref() != currency {
+                    warn!("{} for authorization {} is in {}, but the authorization is in {}. Skipping.", transaction_type, transaction_id, record.currency.as_deref().unwrap_or_default(), currency.unwrap_or("the default currency"));
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::CurrencyMismatch));
+                }
+
+                let mut account = match self.accounts.get_account(client_id)? {
+                    Some(account) => account,
+                    None => {
+                        // This shouldn’t normally happen, but guard nonetheless.
+                        error!("{} for open authorization {}, but account is missing for client {}.", transaction_type, transaction_id, client_id);
+                        Err(ApplicationError::AccountNotFound { client_id, transaction_type })
+                    }?
+                };
+
+                // Warn and skip captures on locked accounts. Like withdrawals, they take funds out of the account.
+                if transaction_type == TransactionType::Capture && account.locked {
+                    warn!("Capture on locked account is not allowed for client {} in transaction {}. Skipping.", client_id, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::AccountLocked));
+                }
+
+                // Capture or void the whole reserved amount unless the row names a portion of it.
+                let amount = record.amount.unwrap_or(authorization.amount);
+
+                // Warn and skip when the amount exceeds what the authorization reserves.
+                if amount > authorization.amount {
+                    warn!("{} amount {} exceeds the amount {} reserved by authorization {}. Skipping.", transaction_type, amount, authorization.amount, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::ExceedsReservedAmount));
+                }
+
+                let mut balance = account.balance(currency);
+                if transaction_type == TransactionType::Capture {
+                    // The captured funds leave the account, and the rest is released. An authorization is captured once.
+                    // Both balances are worked out before the first event is recorded, so an overflow records nothing.
+                    let released = authorization.amount - amount;
+                    let mut captured = balance;
+                    captured.reserved = checked_sub(balance.reserved, amount, client_id, transaction_id)?;
+                    captured.total = checked_sub(balance.total, amount, client_id, transaction_id)?;
+                    let mut released_balance = captured;
+                    released_balance.reserved = checked_sub(captured.reserved, released, client_id, transaction_id)?;
+                    released_balance.available = checked_add(captured.available, released, client_id, transaction_id)?;
+
+                    account.set_balance(currency, captured);
+                    self.record_event(LedgerEventKind::ReservationCaptured, client_id, transaction_id, Some(amount), currency, &account);
+                    if released > Decimal::ZERO {
+                        account.set_balance(currency, released_balance);
+                        self.record_event(LedgerEventKind::ReservationReleased, client_id, transaction_id, Some(released), currency, &account);
+                    }
+                    // From here on the transaction is a withdrawal of the captured amount, and disputed as one
+                    self.authorizations.close(transaction_id);
+                    self.transactions.put_transaction(TransactionRecord {
+                        currency: authorization.currency.clone(),
+                        ..TransactionRecord::new(TransactionType::Withdrawal, client_id, transaction_id, Some(amount))
+                    })?;
+                } else {
+                    balance.reserved = checked_sub(balance.reserved, amount, client_id, transaction_id)?;
+                    balance.available = checked_add(balance.available, amount, client_id, transaction_id)?;
+                    account.set_balance(currency, balance);
+                    self.authorizations.release(transaction_id, amount);
+                    self.record_event(LedgerEventKind::ReservationReleased, client_id, transaction_id, Some(amount), currency, &account);
+                }
+                self.accounts.put_account(client_id, account)?;
+            }
+            TransactionType::Refund => {
+                // Warn and skip when Refund transaction is missing amount.
+                let amount = match record.amount {
+                    Some(amount) => amount,
+                    None => {
+                        warn!("Refund transaction {} missing amount. Skipping.", transaction_id);
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::MissingAmount));
+                    }
+                };
+
+                // Warn and skip when the refunded transaction is missing or unknown on Refund.
+                let mut original = match record.original_transaction_id.map(|original_id| self.transactions.get_transaction(original_id)).transpose()?.flatten() {
+                    Some(original) => original,
+                    None => {
+                        warn!("Refund transaction {} references unknown transaction {:?}. Skipping.", transaction_id, record.original_transaction_id);
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::UnknownTransaction));
+                    }
+                };
+                let original_id = original.transaction_id;
+
+                // Warn and skip refunds of anything but a withdrawal. Only funds that left the account can be paid back.
+                if original.transaction_type != TransactionType::Withdrawal {
+                    warn!("Refund transaction {} references {} transaction {}, which is not a withdrawal. Skipping.", transaction_id, original.transaction_type, original_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::NotRefundable));
+                }
+
+                // Warn and skip for refunds that have original transaction / client_id mismatch.
+                if client_id != original.client_id {
+                    warn!("Refund for withdrawal {} has mismatched client_id. Withdrawal client_id is {}. Refund record client_id is {}. Skipping.", original_id, original.client_id, client_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::ClientMismatch { owner_client_id: original.client_id }));
+                }
+
+                // Warn and skip refunds naming a different currency than the withdrawal. Rows without one take the withdrawal's.
+                if record.currency.is_some() && record.currency != original.currency {
+                    warn!("Refund for withdrawal {} is in {}, but the withdrawal is in {}. Skipping.", original_id, record.currency.as_deref().unwrap_or_default(), original.currency.as_deref().unwrap_or("the default currency"));
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::CurrencyMismatch));
+                }
+                let currency = original.currency.clone();
+
+                let mut account = match self.accounts.get_account(client_id)? {
+                    Some(account) => account,
+                    None => {
+                        // This shouldn’t normally happen, but guard nonetheless.
+                        error!("Refund for known withdrawal {}, but account is missing for client {}.", original_id, client_id);
+                        Err(ApplicationError::AccountNotFound { client_id, transaction_type: TransactionType::Refund })
+                    }?
+                };
+
+                // Warn and skip if account is closed on Refund.
+                if account.closed {
+                    warn!("Refund on closed account is not allowed for client {} in transaction {}. Skipping.", client_id, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::AccountClosed));
+                }
+
+                // Warn and skip if account is locked on Refund. Like deposits, refunds credit the account.
+                if account.locked {
+                    warn!("Refund on locked account is not allowed for client {} in transaction {}. Skipping.", client_id, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::AccountLocked));
+                }
+
+                // Warn and skip if the transaction ID has already been used.
+                if self.transactions.contains_transaction(transaction_id)? {
+                    warn!("Transaction has already been processed for transaction {}. Skipping.", transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::DuplicateTransaction));
+                }
+
+                // Warn and skip when the refund exceeds what is left of the withdrawal. Charged back portions, and
+                // portions held by an open dispute, may already be paid back to the client, so they can't be refunded too.
+                let refunded = original.refunded.unwrap_or_default();
+                let disputed = if self.disputes.is_open(original_id) {
+                    self.disputes.latest(original_id).map(Dispute::held).unwrap_or_default()
+                } else {
+                    Decimal::ZERO
+                };
+                let refundable = original.amount.unwrap_or_default() - refunded - self.disputes.charged_back(original_id) - disputed;
+                if amount > refundable {
+                    warn!("Refund amount {} exceeds the amount {} left to refund of withdrawal {}. Skipping.", amount, refundable, original_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::ExceedsRefundableAmount));
+                }
+
+                let mut balance = account.balance(currency.as_deref());
+                balance.available = checked_add(balance.available, amount, client_id, transaction_id)?;
+                balance.total = checked_add(balance.total, amount, client_id, transaction_id)?;
+                account.set_balance(currency.as_deref(), balance);
+                if let Some(event) = self.record_event(LedgerEventKind::RefundCredited, client_id, transaction_id, Some(amount), currency.as_deref(), &account) {
+                    event.original_transaction_id = Some(original_id);
+                }
+                // The refund is stored like any credit, so it can be disputed, and the withdrawal keeps count of its refunds
+                original.refunded = Some(refunded + amount);
+                self.transactions.put_transaction(original)?;
+                self.transactions.put_transaction(TransactionRecord { currency, ..record })?;
+                self.accounts.put_account(client_id, account)?;
+            }
             TransactionType::Dispute => {
                 // Warn and skip when transaction is unknown on Dispute.
-                let disputed_transaction = match self.transactions.get(&transaction_id) {
+                let disputed_transaction = match self.transactions.get_transaction(transaction_id)? {
                     Some(disputed_transaction) => disputed_transaction,
                     None => {
                         warn!("Dispute on unknown transaction {}. Skipping.", transaction_id);
-                        return Ok(());
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::UnknownTransaction));
                     }
                 };
 
+                // Warn and skip disputes on authorizations that were never captured. Their funds never left the account.
+                if disputed_transaction.transaction_type == TransactionType::Authorize {
+                    warn!("Dispute on authorization {}, which was never captured. Skipping.", transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::NotCaptured));
+                }
+
                 // Warn and skip when transaction is already disputed on Dispute.
-                if self.disputes.contains(&transaction_id) {
+                if self.disputes.is_open(transaction_id) {
                     warn!("Dispute already exists for transaction {}. Skipping.", transaction_id);
-                    return Ok(());
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::AlreadyDisputed));
+                }
+
+                // Warn and skip when the whole transaction was already charged back on Dispute. A chargeback is final.
+                if disputed_transaction.disputable_funds().1.is_some_and(|amount| amount <= self.disputes.charged_back(transaction_id)) {
+                    warn!("Dispute on charged back transaction {}. Skipping.", transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::AlreadyChargedBack));
+                }
+
+                // Warn and skip when refunds paid back all of a withdrawal that was not charged back on Dispute.
+                let refunded = disputed_transaction.refunded.unwrap_or_default();
+                if refunded > Decimal::ZERO && disputed_transaction.amount.is_some_and(|amount| amount <= self.disputes.charged_back(transaction_id) + refunded) {
+                    warn!("Dispute on refunded withdrawal {}. Skipping.", transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::AlreadyRefunded));
+                }
+
+                // Warn and skip when transaction has been disputed as many times as the configured limit allows on Dispute.
+                if let Some(max_disputes) = self.config.max_disputes {
+                    if self.disputes.history(transaction_id).len() >= max_disputes as usize {
+                        warn!("Dispute limit of {} reached for transaction {}. Skipping.", max_disputes, transaction_id);
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::DisputeLimitReached));
+                    }
                 }
 
                 // Warn and continue for disputes that have transaction_id / client_id mismatch on Dispute.
                 // Arguably this could be ignored and Dispute could be processed only using the disputed_transaction's client_id, but it represents bad data from input so skipping.
                 if client_id != disputed_transaction.client_id {
                     warn!("Dispute for transaction {} has mismatched client_id. Disputed transaction client_id is {}. Dispute record client_id is {}. Skipping.", transaction_id, disputed_transaction.client_id, client_id);
-                    return Ok(());
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::ClientMismatch { owner_client_id: disputed_transaction.client_id }));
+                }
+
+                // Warn and skip disputes naming a different currency than the disputed transaction on Dispute. Rows without one take the transaction's.
+                if record.currency.is_some() && record.currency.as_deref() != disputed_transaction.disputable_funds().0 {
+                    warn!("Dispute for transaction {} is in {}, but the transaction is in {}. Skipping.", transaction_id, record.currency.as_deref().unwrap_or_default(), disputed_transaction.disputable_funds().0.unwrap_or("the default currency"));
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::CurrencyMismatch));
+                }
+                let (currency, transaction_amount) = disputed_transaction.disputable_funds();
+
+                // Warn and skip disputes on withdrawals unless the configured policy allows them.
+                let is_withdrawal = disputed_transaction.transaction_type == TransactionType::Withdrawal;
+                if is_withdrawal && self.config.withdrawal_disputes == WithdrawalDisputePolicy::Reject {
+                    warn!("Dispute on withdrawal transaction {} is not allowed by the withdrawal dispute policy. Skipping.", transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::WithdrawalNotDisputable));
                 }
 
-                let account = match self.accounts.get_mut(&disputed_transaction.client_id) {
+                // The funds are held where the transaction put them: on the recipient of a Transfer, otherwise on the client.
+                let holder_id = disputed_transaction.holding_client_id();
+                let mut account = match self.accounts.get_account(holder_id)? {
                     Some(account) => account,
                     None => {
                         // This shouldn’t normally happen, but guard nonetheless.
-                        error!("Dispute for known transaction {}, but account is missing for client {}.", transaction_id, disputed_transaction.client_id);
-                        Err(ApplicationError::AccountNotFound { client_id: client_id, transaction_type: TransactionType::Dispute })
+                        error!("Dispute for known transaction {}, but account is missing for client {}.", transaction_id, holder_id);
+                        Err(ApplicationError::AccountNotFound { client_id: holder_id, transaction_type: TransactionType::Dispute })
                     }?
                 };
-                if let Some(amount) = disputed_transaction.amount {
-                    account.available -= amount;
-                    account.held += amount;
-                    self.disputes.insert(transaction_id);
+
+                // Warn and skip disputes on closed accounts. Closing requires nothing held, and it must stay that way.
+                if account.closed {
+                    warn!("Dispute on closed account is not allowed for client {} in transaction {}. Skipping.", holder_id, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::AccountClosed));
+                }
+
+                if let Some(transaction_amount) = transaction_amount {
+                    // Dispute the whole remaining amount unless the row names a portion of it.
+                    // Charged back portions are gone for good, so only the rest can be disputed again. Refunded portions
+                    // of a withdrawal were already paid back, so they can't be credited again either.
+                    let disputable = transaction_amount - self.disputes.charged_back(transaction_id) - refunded;
+                    let amount = record.amount.unwrap_or(disputable);
+
+                    // Warn and skip when Dispute amount exceeds what is left to dispute.
+                    if amount > disputable {
+                        warn!("Dispute amount {} exceeds the disputable amount {} of transaction {}. Skipping.", amount, disputable, transaction_id);
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::ExceedsDisputableAmount));
+                    }
+
+                    let mut balance = account.balance(currency);
+                    let kind = if is_withdrawal {
+                        // The withdrawn funds already left the account, so they are held as a pending credit
+                        balance.held = checked_add(balance.held, amount, holder_id, transaction_id)?;
+                        balance.total = checked_add(balance.total, amount, holder_id, transaction_id)?;
+                        LedgerEventKind::CreditPending
+                    } else {
+                        balance.available = checked_sub(balance.available, amount, holder_id, transaction_id)?;
+                        balance.held = checked_add(balance.held, amount, holder_id, transaction_id)?;
+                        LedgerEventKind::FundsHeld
+                    };
+                    account.set_balance(currency, balance);
+                    self.disputes.open(transaction_id, amount, self.record_sequence);
+                    self.record_event(kind, holder_id, transaction_id, Some(amount), currency, &account);
+                    self.accounts.put_account(holder_id, account)?;
                 }
             }
             TransactionType::Resolve => {
                 // Warn and skip when dispute doesn't exist on Resolve.
-                if !self.disputes.contains(&transaction_id) {
+                if !self.disputes.is_open(transaction_id) {
                     warn!("Resolve on non-disputed transaction {}. Skipping.", transaction_id);
-                    return Ok(());
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::NotDisputed));
                 }
 
-                let disputed_transaction = match self.transactions.get(&transaction_id) {
+                let disputed_transaction = match self.transactions.get_transaction(transaction_id)? {
                     Some(disputed_transaction) => disputed_transaction,
                     None => {
                         // This shouldn’t normally happen, but guard nonetheless.
                         error!("Resolve on unknown transaction {}, but dispute exists.", transaction_id);
-                        Err(ApplicationError::TransactionNotFound { transaction_id: transaction_id, transaction_type: TransactionType::Dispute })
+                        Err(ApplicationError::TransactionNotFound { transaction_id, transaction_type: TransactionType::Resolve })
                     }?
                 };
 
@@ -151,36 +1091,66 @@ impl Engine {
                 // Arguably this could be ignored and Resolve could be processed only using the disputed_transaction's client_id, but it represents bad data from input so skipping.
                 if client_id != disputed_transaction.client_id {
                     warn!("Resolve for disputed transaction {} has mismatched client_id. Disputed transaction client_id is {}. Resolve record client_id is {}. Skipping.", transaction_id, disputed_transaction.client_id, client_id);
-                    return Ok(());
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::ClientMismatch { owner_client_id: disputed_transaction.client_id }));
+                }
+
+                // Warn and skip resolves naming a different currency than the disputed transaction on Resolve. Rows without one take the transaction's.
+                if record.currency.is_some() && record.currency.as_deref() != disputed_transaction.disputable_funds().0 {
+                    warn!("Resolve for transaction {} is in {}, but the transaction is in {}. Skipping.", transaction_id, record.currency.as_deref().unwrap_or_default(), disputed_transaction.disputable_funds().0.unwrap_or("the default currency"));
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::CurrencyMismatch));
                 }
+                let currency = disputed_transaction.disputable_funds().0;
 
-                let account = match self.accounts.get_mut(&disputed_transaction.client_id) {
+                let holder_id = disputed_transaction.holding_client_id();
+                let mut account = match self.accounts.get_account(holder_id)? {
                     Some(account) => account,
                     None => {
                         // This shouldn’t normally happen, but guard nonetheless.
-                        error!("Resolve for known transaction {}, but account is missing for client {}.", transaction_id, disputed_transaction.client_id);
-                        Err(ApplicationError::AccountNotFound { client_id: client_id, transaction_type: TransactionType::Resolve })
+                        error!("Resolve for known transaction {}, but account is missing for client {}.", transaction_id, holder_id);
+                        Err(ApplicationError::AccountNotFound { client_id: holder_id, transaction_type: TransactionType::Resolve })
                     }?
                 };
-                if let Some(amount) = disputed_transaction.amount {
-                    account.held -= amount;
-                    account.available += amount;
-                    self.disputes.remove(&transaction_id);
+                // Resolve the whole amount held by the dispute unless the row names a portion of it.
+                let held = self.disputes.latest(transaction_id).map(Dispute::held).unwrap_or_default();
+                let amount = record.amount.unwrap_or(held);
+
+                // Warn and skip when Resolve releases more than the dispute holds.
+                if amount > held {
+                    warn!("Resolve amount {} exceeds the amount {} held by the dispute of transaction {}. Skipping.", amount, held, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::ExceedsHeldAmount));
                 }
+
+                // Disputed withdrawals are held as pending credits regardless of the current policy,
+                // since the policy only decides whether a dispute can be opened.
+                let mut balance = account.balance(currency);
+                let kind = if disputed_transaction.transaction_type == TransactionType::Withdrawal {
+                    // The withdrawal stands, so the pending credit is dropped
+                    balance.held = checked_sub(balance.held, amount, holder_id, transaction_id)?;
+                    balance.total = checked_sub(balance.total, amount, holder_id, transaction_id)?;
+                    LedgerEventKind::CreditCancelled
+                } else {
+                    balance.held = checked_sub(balance.held, amount, holder_id, transaction_id)?;
+                    balance.available = checked_add(balance.available, amount, holder_id, transaction_id)?;
+                    LedgerEventKind::FundsReleased
+                };
+                account.set_balance(currency, balance);
+                self.disputes.release(transaction_id, amount, self.record_sequence);
+                self.record_event(kind, holder_id, transaction_id, Some(amount), currency, &account);
+                self.accounts.put_account(holder_id, account)?;
             }
             TransactionType::Chargeback => {
                 // Warn and skip when dispute doesn't exist on Chargeback.
-                if !self.disputes.contains(&transaction_id) {
+                if !self.disputes.is_open(transaction_id) {
                     warn!("Chargeback on non-disputed transaction {}. Skipping.", transaction_id);
-                    return Ok(());
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::NotDisputed));
                 }
 
-                let disputed_transaction = match self.transactions.get(&transaction_id) {
+                let disputed_transaction = match self.transactions.get_transaction(transaction_id)? {
                     Some(disputed_transaction) => disputed_transaction,
                     None => {
                         // This shouldn’t normally happen, but guard nonetheless.
                         error!("Chargeback on unknown transaction {}, but dispute exists.", transaction_id);
-                        Err(ApplicationError::TransactionNotFound { transaction_id: transaction_id, transaction_type: TransactionType::Chargeback })
+                        Err(ApplicationError::TransactionNotFound { transaction_id, transaction_type: TransactionType::Chargeback })
                     }?
                 };
 
@@ -188,25 +1158,273 @@ impl Engine {
                 // Arguably this could be ignored and Chargeback could be processed only using the disputed_transaction's client_id, but it represents bad data from input so skipping.
                 if client_id != disputed_transaction.client_id {
                     warn!("Chargeback for disputed transaction {} has mismatched client_id. Disputed transaction client_id is {}. Chargeback record client_id is {}. Skipping.", transaction_id, disputed_transaction.client_id, client_id);
-                    return Ok(());
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::ClientMismatch { owner_client_id: disputed_transaction.client_id }));
                 }
 
-                let account = match self.accounts.get_mut(&disputed_transaction.client_id) {
+                // Warn and skip chargebacks naming a different currency than the disputed transaction on Chargeback. Rows without one take the transaction's.
+                if record.currency.is_some() && record.currency.as_deref() != disputed_transaction.disputable_funds().0 {
+                    warn!("Chargeback for transaction {} is in {}, but the transaction is in {}. Skipping.", transaction_id, record.currency.as_deref().unwrap_or_default(), disputed_transaction.disputable_funds().0.unwrap_or("the default currency"));
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::CurrencyMismatch));
+                }
+                let currency = disputed_transaction.disputable_funds().0;
+
+                let holder_id = disputed_transaction.holding_client_id();
+                let mut account = match self.accounts.get_account(holder_id)? {
                     Some(account) => account,
                     None => {
                         // This shouldn’t normally happen, but guard nonetheless.
-                        error!("Chargeback for known transaction {}, but account is missing for client {}.", transaction_id, disputed_transaction.client_id);
-                        Err(ApplicationError::AccountNotFound { client_id: client_id, transaction_type: TransactionType::Resolve })
+                        error!("Chargeback for known transaction {}, but account is missing for client {}.", transaction_id, holder_id);
+                        Err(ApplicationError::AccountNotFound { client_id: holder_id, transaction_type: TransactionType::Chargeback })
                     }?
                 };
-                if let Some(amount) = disputed_transaction.amount {
-                    account.held -= amount;
-                    account.total -= amount;
-                    account.locked = true;
-                    self.disputes.remove(&transaction_id);
+                // Chargeback the whole amount held by the dispute unless the row names a portion of it.
+                let held = self.disputes.latest(transaction_id).map(Dispute::held).unwrap_or_default();
+                let amount = record.amount.unwrap_or(held);
+
+                // Warn and skip when Chargeback charges back more than the dispute holds.
+                if amount > held {
+                    warn!("Chargeback amount {} exceeds the amount {} held by the dispute of transaction {}. Skipping.", amount, held, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::ExceedsHeldAmount));
+                }
+
+                // A charged back transfer is paid back to its sender, which must still be able to take the funds
+                let mut sender = None;
+                if disputed_transaction.transaction_type == TransactionType::Transfer {
+                    let sender_account = match self.accounts.get_account(client_id)? {
+                        Some(sender_account) => sender_account,
+                        None => {
+                            // This shouldn’t normally happen, but guard nonetheless.
+                            error!("Chargeback for known transaction {}, but account is missing for client {}.", transaction_id, client_id);
+                            Err(ApplicationError::AccountNotFound { client_id, transaction_type: TransactionType::Chargeback })
+                        }?
+                    };
+                    // Warn and skip chargebacks of transfers from closed accounts. A closed account takes no more funds.
+                    if sender_account.closed {
+                        warn!("Chargeback paying back to closed account is not allowed for client {} in transaction {}. Skipping.", client_id, transaction_id);
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::AccountClosed));
+                    }
+                    sender = Some(sender_account);
+                }
+
+                let mut balance = account.balance(currency);
+                let kind = if disputed_transaction.transaction_type == TransactionType::Withdrawal {
+                    // The withdrawal is reversed, so the pending credit is paid back to the client
+                    balance.held = checked_sub(balance.held, amount, holder_id, transaction_id)?;
+                    balance.available = checked_add(balance.available, amount, holder_id, transaction_id)?;
+                    LedgerEventKind::CreditPosted
+                } else {
+                    balance.held = checked_sub(balance.held, amount, holder_id, transaction_id)?;
+                    balance.total = checked_sub(balance.total, amount, holder_id, transaction_id)?;
+                    LedgerEventKind::ChargebackPosted
+                };
+
+                // A charged back conversion is reversed: its source currency gets back what was converted, at the rate applied then.
+                // This and the sender's balance below are worked out up front, so an overflow leaves the dispute and the journal untouched too.
+                let charged_back = self.disputes.charged_back(transaction_id);
+                let mut reversal = None;
+                if disputed_transaction.transaction_type == TransactionType::Convert {
+                    let source = disputed_transaction.currency.as_deref();
+                    let reversed = reversed_amount(&disputed_transaction, charged_back, amount);
+                    if reversed > Decimal::ZERO {
+                        let mut source_balance = account.balance(source);
+                        source_balance.available = checked_add(source_balance.available, reversed, holder_id, transaction_id)?;
+                        source_balance.total = checked_add(source_balance.total, reversed, holder_id, transaction_id)?;
+                        reversal = Some((source, reversed, source_balance));
+                    }
+                }
+
+                // The sender of a charged back transfer gets the funds taken from the recipient
+                if let Some(sender) = sender.as_mut() {
+                    let mut sender_balance = sender.balance(currency);
+                    sender_balance.available = checked_add(sender_balance.available, amount, client_id, transaction_id)?;
+                    sender_balance.total = checked_add(sender_balance.total, amount, client_id, transaction_id)?;
+                    sender.set_balance(currency, sender_balance);
+                }
+
+                account.set_balance(currency, balance);
+                self.disputes.charge_back(transaction_id, amount, self.record_sequence);
+                self.record_event(kind, holder_id, transaction_id, Some(amount), currency, &account);
+                if let Some((source, reversed, source_balance)) = reversal {
+                    account.set_balance(source, source_balance);
+                    self.record_event(LedgerEventKind::ConversionReversed, holder_id, transaction_id, Some(reversed), source, &account);
+                }
+                account.locked = true;
+                self.record_event(LedgerEventKind::AccountLocked, holder_id, transaction_id, None, currency, &account);
+                self.accounts.put_account(holder_id, account)?;
+
+                if let Some(sender) = sender {
+                    if let Some(event) = self.record_event(LedgerEventKind::TransferReversed, client_id, transaction_id, Some(amount), currency, &sender) {
+                        event.counterparty = Some(holder_id);
+                    }
+                    self.accounts.put_account(client_id, sender)?;
                 }
             }
+            TransactionType::Unlock | TransactionType::Freeze | TransactionType::Close | TransactionType::Adjustment => {
+                return self.apply_administrative(record);
+            }
         }
-        Ok(())
+        Ok(TransactionOutcome::Applied)
     }
+
+    // Applies a record issued by support staff. The transaction ID only references the request in the audit trail,
+    // so it is neither stored nor checked for uniqueness, and administrative records cannot be disputed.
+    fn apply_administrative(&mut self, record: TransactionRecord) -> Result<TransactionOutcome, ApplicationError> {
+        let client_id = record.client_id;
+        let transaction_id = record.transaction_id;
+        let transaction_type = record.transaction_type;
+
+        // Warn and skip if account doesn't exist on administrative records.
+        let mut account = match self.accounts.get_account(client_id)? {
+            Some(account) => account,
+            None => {
+                warn!("{} for unknown client {} in transaction {}. Skipping.", transaction_type, client_id, transaction_id);
+                return Ok(TransactionOutcome::rejected(&record, RejectionReason::UnknownAccount));
+            }
+        };
+
+        // Warn and skip if account is closed on administrative records. Closing is final.
+        if account.closed {
+            warn!("{} on closed account is not allowed for client {} in transaction {}. Skipping.", transaction_type, client_id, transaction_id);
+            return Ok(TransactionOutcome::rejected(&record, RejectionReason::AccountClosed));
+        }
+
+        let (kind, amount) = match transaction_type {
+            TransactionType::Unlock => {
+                // Warn and skip if account is not locked on Unlock.
+                if !account.locked {
+                    warn!("Unlock of account that is not locked for client {} in transaction {}. Skipping.", client_id, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::AccountNotLocked));
+                }
+                account.locked = false;
+                (LedgerEventKind::AccountUnlocked, None)
+            }
+            TransactionType::Freeze => {
+                // Warn and skip if account is already locked on Freeze.
+                if account.locked {
+                    warn!("Freeze of account that is already locked for client {} in transaction {}. Skipping.", client_id, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::AccountLocked));
+                }
+                account.locked = true;
+                (LedgerEventKind::AccountFrozen, None)
+            }
+            TransactionType::Close => {
+                // Warn and skip if account still holds funds in any currency on Close. They would be stranded, so they must be adjusted out first.
+                if let Some((currency, balance)) = account.balances().find(|(_, balance)| balance.total != Decimal::ZERO || balance.held != Decimal::ZERO) {
+                    warn!("Close of account with {} total {} and held {} is not allowed for client {} in transaction {}. Skipping.", currency.unwrap_or("default currency"), balance.total, balance.held, client_id, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::NonZeroBalance));
+                }
+                account.locked = true;
+                account.closed = true;
+                (LedgerEventKind::AccountClosed, None)
+            }
+            TransactionType::Adjustment => {
+                // Warn and skip when Adjustment transaction is missing amount.
+                let amount = match record.amount {
+                    Some(amount) => amount,
+                    None => {
+                        warn!("Adjustment transaction {} missing amount. Skipping.", transaction_id);
+                        return Ok(TransactionOutcome::rejected(&record, RejectionReason::MissingAmount));
+                    }
+                };
+
+                // Warn and skip when Adjustment transaction is missing a reason. Manual balance changes must be explained.
+                if record.reason.as_deref().unwrap_or_default().trim().is_empty() {
+                    warn!("Adjustment transaction {} missing reason. Skipping.", transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::MissingReason));
+                }
+
+                // Warn and skip if a debit exceeds the available funds in the adjusted currency on Adjustment.
+                let mut balance = account.balance(record.currency.as_deref());
+                balance.available = checked_add(balance.available, amount, client_id, transaction_id)?;
+                if balance.available < Decimal::ZERO {
+                    warn!("Adjustment failed due to insufficient available funds for client {} in transaction {}. Skipping.", client_id, transaction_id);
+                    return Ok(TransactionOutcome::rejected(&record, RejectionReason::InsufficientFunds));
+                }
+                balance.total = checked_add(balance.total, amount, client_id, transaction_id)?;
+                account.set_balance(record.currency.as_deref(), balance);
+                (LedgerEventKind::BalanceAdjusted, Some(amount))
+            }
+            _ => unreachable!("apply_record only hands administrative records over"),
+        };
+        if let Some(event) = self.record_event(kind, client_id, transaction_id, amount, record.currency.as_deref(), &account) {
+            event.reason = record.reason;
+        }
+        self.accounts.put_account(client_id, account)?;
+        Ok(TransactionOutcome::Applied)
+    }
+}
+
+// Source currency amount a Convert gets back when `amount` of its converted funds is charged back, on top of the
+// `charged_back` part charged back before. Cumulative amounts are converted back at the applied rate, so the parts add
+// up to exactly the amount originally converted once all of it is charged back, whatever the rounding along the way.
+fn reversed_amount(conversion: &TransactionRecord, charged_back: Decimal, amount: Decimal) -> Decimal {
+    let decimal_places = currency::minor_units(conversion.currency.as_deref()).unwrap_or(AMOUNT_DECIMAL_PLACES);
+    let source_amount = |converted: Decimal| match (conversion.converted_amount(), conversion.amount, conversion.rate) {
+        (Some(total), Some(original), _) if converted >= total => original,
+        (_, _, Some(rate)) => converted.checked_div(rate).unwrap_or_default().round_dp_with_strategy(decimal_places, RoundingStrategy::MidpointAwayFromZero),
+        _ => Decimal::ZERO,
+    };
+    (source_amount(charged_back + amount) - source_amount(charged_back)).max(Decimal::ZERO)
+}
+
+// Balance arithmetic is checked so an overflow fails the offending record instead of panicking the whole run.
+// Callers mutate a loaded copy of the account, so on failure the stored account is left untouched.
+fn checked_add(balance: Decimal, amount: Decimal, client_id: u16, transaction_id: u32) -> Result<Decimal, ApplicationError> {
+    balance.checked_add(amount).filter(within_balance_range).ok_or_else(|| balance_overflow(client_id, transaction_id))
+}
+
+fn checked_sub(balance: Decimal, amount: Decimal, client_id: u16, transaction_id: u32) -> Result<Decimal, ApplicationError> {
+    balance.checked_sub(amount).filter(within_balance_range).ok_or_else(|| balance_overflow(client_id, transaction_id))
+}
+
+// Beyond MAX_BALANCE Decimal silently drops fractional digits, so that counts as an overflow too.
+fn within_balance_range(balance: &Decimal) -> bool {
+    balance.abs() <= MAX_BALANCE
+}
+
+fn balance_overflow(client_id: u16, transaction_id: u32) -> ApplicationError {
+    error!("Balance overflow for client {} in transaction {}.", client_id, transaction_id);
+    ApplicationError::BalanceOverflow { client_id, transaction_id }
+}
+
+fn check_account_invariants(client_id: u16, account: &Account, mut disputed: DisputedAmounts, mut reserved: ReservedAmounts) -> Result<(), ApplicationError> {
+    for (currency, balance) in account.balances() {
+        // Named currencies prefix the message, so default currency messages read as they did before currencies
+        let prefix = currency.map(|currency| format!("{} ", currency)).unwrap_or_default();
+        let disputed = disputed.remove(&currency.map(str::to_string)).unwrap_or(Decimal::ZERO);
+        let authorized = reserved.remove(&currency.map(str::to_string)).unwrap_or(Decimal::ZERO);
+        if balance.available.checked_add(balance.held).and_then(|sum| sum.checked_add(balance.reserved)) != Some(balance.total) {
+            // Reserved funds only show up in the message when there are any, like they do in the output
+            let reserved = if balance.reserved.is_zero() { String::new() } else { format!(" + reserved {}", balance.reserved) };
+            return Err(invariant_violation(client_id, format!("{}available {} + held {}{} does not equal total {}", prefix, balance.available, balance.held, reserved, balance.total)));
+        }
+        if balance.reserved < Decimal::ZERO {
+            return Err(invariant_violation(client_id, format!("{}reserved {} is negative", prefix, balance.reserved)));
+        }
+        if balance.reserved != authorized {
+            return Err(invariant_violation(client_id, format!("{}reserved {} does not equal the authorized amount {}", prefix, balance.reserved, authorized)));
+        }
+        if balance.held < Decimal::ZERO {
+            return Err(invariant_violation(client_id, format!("{}held {} is negative", prefix, balance.held)));
+        }
+        if balance.held != disputed {
+            return Err(invariant_violation(client_id, format!("{}held {} does not equal the disputed amount {}", prefix, balance.held, disputed)));
+        }
+        if account.closed && (!account.locked || balance.total != Decimal::ZERO) {
+            return Err(invariant_violation(client_id, format!("closed account is unlocked or holds {}total {}", prefix, balance.total)));
+        }
+    }
+    // Disputes holding funds in a currency the account has no balance in
+    if let Some(currency) = disputed.iter().find(|(_, amount)| !amount.is_zero()).map(|(currency, _)| currency) {
+        return Err(invariant_violation(client_id, format!("open disputes hold {} funds the account has no balance in", currency.as_deref().unwrap_or_default())));
+    }
+    if let Some(currency) = reserved.iter().find(|(_, amount)| !amount.is_zero()).map(|(currency, _)| currency) {
+        return Err(invariant_violation(client_id, format!("open authorizations reserve {} funds the account has no balance in", currency.as_deref().unwrap_or_default())));
+    }
+    Ok(())
+}
+
+fn invariant_violation(client_id: u16, reason: String) -> ApplicationError {
+    error!("Ledger invariant violated for client {}: {}.", client_id, reason);
+    ApplicationError::InvariantViolation { client_id, reason }
 }
@@ -1,4 +1,5 @@
 use thiserror::Error;
+use crate::journal::LedgerEventKind;
 use crate::model::TransactionType;
 
 #[derive(Error, Debug)]
@@ -12,9 +13,119 @@ pub enum ApplicationError {
     #[error("Decimal error: {0}")]
     Decimal(#[from] rust_decimal::Error),
 
+    #[error("Storage error: {0}")]
+    Storage(#[from] rusqlite::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
     #[error("Account Not Found. Account for client {client_id} not found. Transaction Type: {transaction_type}.")]
     AccountNotFound{client_id: u16, transaction_type: TransactionType},
 
     #[error("Transaction Not Found. Transaction ID: {transaction_id}. Transaction Type: {transaction_type}.")]
     TransactionNotFound{transaction_id: u32, transaction_type: TransactionType},
+
+    #[error("Journal Out Of Order. Expected event {expected} but found event {found}.")]
+    JournalOutOfOrder{expected: u64, found: u64},
+
+    #[error("Journal Mismatch. Replaying event {sequence} ({event}) for client {client_id} did not reproduce the recorded account state.")]
+    JournalMismatch{sequence: u64, event: LedgerEventKind, client_id: u16},
+
+    #[error("Unsupported Snapshot Version. Found version {found}, but only versions up to {supported} are supported.")]
+    UnsupportedSnapshotVersion{found: u32, supported: u32},
+
+    #[error("Invalid Snapshot. {reason}.")]
+    InvalidSnapshot{reason: String},
+
+    #[error("Invalid Input Mapping. {reason}.")]
+    InvalidMapping{reason: String},
+
+    #[error("Invalid Input Schema. {reason}.")]
+    InvalidSchema{reason: String},
+
+    #[error("Invalid Rate Table. {reason}.")]
+    InvalidRates{reason: String},
+
+    #[error("Balance Overflow. Applying transaction {transaction_id} would overflow a balance of client {client_id}.")]
+    BalanceOverflow{client_id: u16, transaction_id: u32},
+
+    #[error("Invariant Violation. Account of client {client_id}: {reason}.")]
+    InvariantViolation{client_id: u16, reason: String},
+
+    #[error("Worker Shard Stopped. A worker thread exited before processing finished.")]
+    ShardStopped,
+
+    #[error("Strict Mode Rejection. Row {row} of {file} was rejected: {message}.")]
+    StrictRejection{file: String, row: u64, message: String},
+}
+
+impl ApplicationError {
+    /// Stable machine-readable code, used in the rejected-records report.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApplicationError::Io(_)                     => "io_error",
+            ApplicationError::Csv(_)                    => "csv_error",
+            ApplicationError::Decimal(_)                => "decimal_error",
+            ApplicationError::Storage(_)                => "storage_error",
+            ApplicationError::Json(_)                   => "json_error",
+            ApplicationError::AccountNotFound { .. }    => "account_not_found",
+            ApplicationError::TransactionNotFound { .. } => "transaction_not_found",
+            ApplicationError::JournalOutOfOrder { .. }  => "journal_out_of_order",
+            ApplicationError::JournalMismatch { .. }    => "journal_mismatch",
+            ApplicationError::UnsupportedSnapshotVersion { .. } => "unsupported_snapshot_version",
+            ApplicationError::InvalidSnapshot { .. }    => "invalid_snapshot",
+            ApplicationError::InvalidMapping { .. }     => "invalid_mapping",
+            ApplicationError::InvalidSchema { .. }      => "invalid_schema",
+            ApplicationError::InvalidRates { .. }       => "invalid_rates",
+            ApplicationError::BalanceOverflow { .. }    => "balance_overflow",
+            ApplicationError::InvariantViolation { .. } => "invariant_violation",
+            ApplicationError::ShardStopped              => "shard_stopped",
+            ApplicationError::StrictRejection { .. }    => "strict_rejection",
+        }
+    }
+
+    /// Process exit status for a run that failed with this error.
+    pub fn exit_status(&self) -> ExitStatus {
+        match self {
+            ApplicationError::StrictRejection { .. } => ExitStatus::Rejections,
+            ApplicationError::Io(_)
+            | ApplicationError::Csv(_)
+            | ApplicationError::Json(_)
+            | ApplicationError::JournalOutOfOrder { .. }
+            | ApplicationError::UnsupportedSnapshotVersion { .. }
+            | ApplicationError::InvalidSnapshot { .. }
+            | ApplicationError::InvalidMapping { .. }
+            | ApplicationError::InvalidSchema { .. }
+            | ApplicationError::InvalidRates { .. } => ExitStatus::InputUnreadable,
+            ApplicationError::Decimal(_)
+            | ApplicationError::Storage(_)
+            | ApplicationError::AccountNotFound { .. }
+            | ApplicationError::TransactionNotFound { .. }
+            | ApplicationError::JournalMismatch { .. }
+            | ApplicationError::BalanceOverflow { .. }
+            | ApplicationError::InvariantViolation { .. }
+            | ApplicationError::ShardStopped => ExitStatus::LedgerError,
+        }
+    }
+}
+
+/// Exit status of the payments engine binary. The values are stable so schedulers can alert on them.
+/// Exit status 2 is left to invalid command line arguments, which is what clap exits with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// Every record was applied.
+    Success = 0,
+    /// Processing finished, but some records were rejected or could not be parsed.
+    /// With `--strict`, processing stopped at the first such record instead.
+    Rejections = 1,
+    /// An input, journal, snapshot or mapping file could not be read, or an output file could not be written.
+    InputUnreadable = 3,
+    /// The engine found its own state inconsistent, e.g. a dispute for a transaction or account it does not have.
+    LedgerError = 4,
+}
+
+impl From<ExitStatus> for std::process::ExitCode {
+    fn from(status: ExitStatus) -> Self {
+        std::process::ExitCode::from(status as u8)
+    }
 }
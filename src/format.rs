@@ -0,0 +1,250 @@
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{BufRead, BufWriter, Cursor, Read, Write};
+use std::path::Path;
+use clap::ValueEnum;
+use csv::WriterBuilder;
+use flate2::bufread::MultiGzDecoder;
+use serde::{Deserialize, Serialize};
+use crate::error::ApplicationError;
+use crate::model::{Account, Balance, TransactionRecord};
+use crate::storage::AccountStore;
+
+/// Format of the transaction input.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum InputFormat {
+    /// CSV with a `type,client,tx,amount` header, plus the optional `reason`, `currency`, `target_currency`, `timestamp`,
+    /// `counterparty` and `original_tx` columns. `schema::COLUMNS` lists them in order, and the README's input schema table
+    /// the version that added each one.
+    #[default]
+    Csv,
+    /// One JSON object per line, with the same field names as the CSV header. Amounts are JSON strings or numbers,
+    /// both read from their exact text.
+    Jsonl,
+}
+
+/// Format of the account output.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// CSV with a `client,available,held,total,locked` header, plus a `currency` column when any account holds a named currency
+    /// and a `reserved` column when any account has funds reserved by authorizations.
+    #[default]
+    Csv,
+    /// One JSON object per line, with the same field names as the CSV header.
+    Jsonl,
+    /// A single JSON array of objects, with the same field names as the CSV header.
+    Json,
+}
+
+/// Compression of an input stream, detected from its first bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+impl Compression {
+    /// Detects the compression from the magic bytes at the start of a stream.
+    /// Plain CSV and JSON Lines always start with printable text, so they can never be mistaken for either format.
+    pub fn detect(start: &[u8]) -> Self {
+        if start.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if start.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Wraps `reader` in a streaming decompressor when it starts with gzip or zstd magic bytes, so compressed input is
+/// read without unpacking it to disk first. The file extension is not looked at, so this works for stdin too.
+/// Concatenated gzip members and zstd frames, as written by parallel compressors, are read as one stream.
+pub fn decompress<R: BufRead + 'static>(mut reader: R) -> Result<Box<dyn Read>, ApplicationError> {
+    // Read just enough to tell the format, even from a pipe that delivers it byte by byte,
+    // then put those bytes back in front of the stream for the decoder
+    let mut start = Vec::with_capacity(ZSTD_MAGIC.len());
+    (&mut reader).take(ZSTD_MAGIC.len() as u64).read_to_end(&mut start)?;
+    let compression = Compression::detect(&start);
+    let reader = Cursor::new(start).chain(reader);
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(MultiGzDecoder::new(reader)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(reader)?),
+    })
+}
+
+/// Parses one JSON Lines input row. A numeric amount is read from its exact digits, like an amount given as a string,
+/// so it never goes through a float.
+pub fn parse_json_record(line: &str) -> Result<TransactionRecord, serde_json::Error> {
+    let mut value: serde_json::Value = serde_json::from_str(line)?;
+    if let Some(amount) = value.get_mut("amount").filter(|amount| amount.is_number()) {
+        *amount = serde_json::Value::String(amount.to_string());
+    }
+    serde_json::from_value(value)
+}
+
+/// The balance of a client account in one currency as written to the output. Balances are formatted to exactly four decimal places,
+/// and kept as strings in JSON so no consumer reads them through a float.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct AccountRow {
+    pub client: u16,
+    pub available: String,
+    pub held: String,
+    pub total: String,
+    pub locked: bool,
+    /// Currency of the balance, None for the default currency.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    /// Funds reserved by open authorizations, part of total. None when nothing is reserved.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reserved: Option<String>,
+}
+
+impl AccountRow {
+    /// Row for the default currency balance of an account.
+    pub fn new(client_id: u16, account: &Account) -> Self {
+        Self::in_currency(client_id, account, None, account.balance(None))
+    }
+
+    /// Rows for every balance of an account, the default currency first, then named currencies by code.
+    /// An empty default currency balance is left out of accounts that only hold named currencies.
+    pub fn rows(client_id: u16, account: &Account) -> Vec<Self> {
+        account.balances()
+            .filter(|(currency, balance)| currency.is_some() || account.currencies.is_empty() || *balance != Balance::default())
+            .map(|(currency, balance)| Self::in_currency(client_id, account, currency, balance))
+            .collect()
+    }
+
+    fn in_currency(client_id: u16, account: &Account, currency: Option<&str>, balance: Balance) -> Self {
+        AccountRow {
+            client: client_id,
+            available: format!("{:.4}", balance.available),
+            held: format!("{:.4}", balance.held),
+            total: format!("{:.4}", balance.total),
+            locked: account.locked,
+            currency: currency.map(str::to_string),
+            reserved: Some(balance.reserved).filter(|reserved| !reserved.is_zero()).map(|reserved| format!("{:.4}", reserved)),
+        }
+    }
+}
+
+/// Writes every account in `accounts` to `writer` in the given format, sorted by client ID.
+pub fn write_accounts<A: AccountStore, W: Write>(accounts: &A, format: OutputFormat, writer: W) -> Result<(), ApplicationError> {
+    match format {
+        OutputFormat::Csv => {
+            // The header is written even when there are no accounts. The currency column is only added when some account
+            // holds a named currency, and the reserved column when some account has reserved funds, so output without
+            // them keeps its original shape.
+            let (with_currency, with_reserved) = optional_columns(accounts)?;
+            let mut writer = WriterBuilder::new().has_headers(false).from_writer(writer);
+            let mut header = vec!["client", "available", "held", "total", "locked"];
+            if with_currency {
+                header.push("currency");
+            }
+            if with_reserved {
+                header.push("reserved");
+            }
+            writer.write_record(&header)?;
+            visit_rows(accounts, |row| {
+                let client = row.client.to_string();
+                let locked = row.locked.to_string();
+                let mut record = vec![client.as_str(), &row.available, &row.held, &row.total, &locked];
+                if with_currency {
+                    record.push(row.currency.as_deref().unwrap_or_default());
+                }
+                if with_reserved {
+                    record.push(row.reserved.as_deref().unwrap_or("0.0000"));
+                }
+                Ok(writer.write_record(&record)?)
+            })?;
+            writer.flush()?;
+        }
+        OutputFormat::Jsonl => {
+            let mut writer = writer;
+            visit_rows(accounts, |row| {
+                serde_json::to_writer(&mut writer, &row)?;
+                writer.write_all(b"\n")?;
+                Ok(())
+            })?;
+            writer.flush()?;
+        }
+        OutputFormat::Json => {
+            // Streamed element by element, so the output never has to fit in memory at once
+            let mut writer = writer;
+            let mut first = true;
+            writer.write_all(b"[")?;
+            visit_rows(accounts, |row| {
+                if !std::mem::take(&mut first) {
+                    writer.write_all(b",")?;
+                }
+                serde_json::to_writer(&mut writer, &row)?;
+                Ok(())
+            })?;
+            writer.write_all(b"]\n")?;
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes a file through `write` so that `path` either keeps its previous content or holds the complete new content.
+/// The content goes to a hidden temporary file next to `path`, which is synced and then renamed over it.
+/// A failed write removes the temporary file; a killed process may leave it behind, but never a partial `path`.
+pub fn write_atomically<F>(path: &Path, write: F) -> Result<(), ApplicationError>
+where
+    F: FnOnce(&mut BufWriter<File>) -> Result<(), ApplicationError>,
+{
+    // Same directory as the target, since a rename is only atomic within one file system
+    let mut temp_name = OsString::from(".");
+    temp_name.push(path.file_name().unwrap_or_default());
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    let result = File::create(&temp_path).map_err(ApplicationError::from).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+// Stores return client IDs sorted for deterministic output
+fn visit_rows<A: AccountStore>(accounts: &A, mut visit: impl FnMut(AccountRow) -> Result<(), ApplicationError>) -> Result<(), ApplicationError> {
+    for client_id in accounts.client_ids()? {
+        if let Some(account) = accounts.get_account(client_id)? {
+            for row in AccountRow::rows(client_id, &account) {
+                visit(row)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Whether any account holds a named currency, and whether any has reserved funds.
+// Takes an extra pass over the store, since the CSV header has to be written before the first row
+fn optional_columns<A: AccountStore>(accounts: &A) -> Result<(bool, bool), ApplicationError> {
+    let (mut with_currency, mut with_reserved) = (false, false);
+    for client_id in accounts.client_ids()? {
+        if let Some(account) = accounts.get_account(client_id)? {
+            with_currency |= !account.currencies.is_empty();
+            with_reserved |= account.balances().any(|(_, balance)| !balance.reserved.is_zero());
+        }
+        if with_currency && with_reserved {
+            break;
+        }
+    }
+    Ok((with_currency, with_reserved))
+}
@@ -0,0 +1,210 @@
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+use csv::{ReaderBuilder, Trim, Writer, WriterBuilder};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use crate::engine::Engine;
+use crate::error::ApplicationError;
+use crate::model::Account;
+use crate::storage::{AccountStore, TransactionStore};
+
+/// Kind of state change recorded in the journal.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerEventKind {
+    DepositCredited,
+    WithdrawalDebited,
+    FundsHeld,
+    FundsReleased,
+    ChargebackPosted,
+    /// A disputed withdrawal held as a pending credit.
+    CreditPending,
+    /// A pending credit dropped by a Resolve.
+    CreditCancelled,
+    /// A pending credit paid back to available by a Chargeback.
+    CreditPosted,
+    AccountLocked,
+    /// A lock cleared by an administrative Unlock.
+    AccountUnlocked,
+    /// A lock set by an administrative Freeze.
+    AccountFrozen,
+    /// An account closed by an administrative Close.
+    AccountClosed,
+    /// Available funds credited or debited by an administrative Adjustment. The amount is signed.
+    BalanceAdjusted,
+    /// The amount of a Convert debited from its source currency. Carries the applied rate.
+    ConversionDebited,
+    /// The converted amount of a Convert credited to its target currency. Carries the applied rate.
+    ConversionCredited,
+    /// Funds of a charged back Convert credited back to its source currency, at the rate it was converted at.
+    ConversionReversed,
+    /// The amount of a Transfer debited from the sending client. Names the recipient as counterparty.
+    TransferDebited,
+    /// The amount of a Transfer credited to the recipient. Names the sending client as counterparty.
+    TransferCredited,
+    /// Funds of a charged back Transfer credited back to the sending client. Names the recipient as counterparty.
+    TransferReversed,
+    /// Available funds reserved by an Authorize. Carries when the authorization expires.
+    FundsReserved,
+    /// Reserved funds taken out of the account by a Capture, which turns them into a withdrawal.
+    ReservationCaptured,
+    /// Reserved funds released back to available by a Void, or by a Capture of less than was reserved.
+    ReservationReleased,
+    /// Reserved funds released back to available because the authorization expired.
+    ReservationExpired,
+    /// The amount of a Refund credited back to the client. Names the refunded withdrawal as original_tx.
+    RefundCredited,
+}
+
+impl fmt::Display for LedgerEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            LedgerEventKind::DepositCredited   => "DepositCredited",
+            LedgerEventKind::WithdrawalDebited => "WithdrawalDebited",
+            LedgerEventKind::FundsHeld         => "FundsHeld",
+            LedgerEventKind::FundsReleased     => "FundsReleased",
+            LedgerEventKind::ChargebackPosted  => "ChargebackPosted",
+            LedgerEventKind::CreditPending     => "CreditPending",
+            LedgerEventKind::CreditCancelled   => "CreditCancelled",
+            LedgerEventKind::CreditPosted      => "CreditPosted",
+            LedgerEventKind::AccountLocked     => "AccountLocked",
+            LedgerEventKind::AccountUnlocked   => "AccountUnlocked",
+            LedgerEventKind::AccountFrozen     => "AccountFrozen",
+            LedgerEventKind::AccountClosed     => "AccountClosed",
+            LedgerEventKind::BalanceAdjusted   => "BalanceAdjusted",
+            LedgerEventKind::ConversionDebited => "ConversionDebited",
+            LedgerEventKind::ConversionCredited => "ConversionCredited",
+            LedgerEventKind::ConversionReversed => "ConversionReversed",
+            LedgerEventKind::TransferDebited   => "TransferDebited",
+            LedgerEventKind::TransferCredited  => "TransferCredited",
+            LedgerEventKind::TransferReversed  => "TransferReversed",
+            LedgerEventKind::FundsReserved     => "FundsReserved",
+            LedgerEventKind::ReservationCaptured => "ReservationCaptured",
+            LedgerEventKind::ReservationReleased => "ReservationReleased",
+            LedgerEventKind::ReservationExpired => "ReservationExpired",
+            LedgerEventKind::RefundCredited    => "RefundCredited",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A single applied state change, along with the resulting state of the affected account.
+/// The resulting state lets replay verify that re-applying the event reproduces the original ledger.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LedgerEvent {
+    #[serde(rename = "seq")]
+    pub sequence: u64,
+    /// Engine record sequence number of the record that caused the change. Absent (0) in journals from older builds.
+    #[serde(default)]
+    pub record: u64,
+    #[serde(rename = "event")]
+    pub kind: LedgerEventKind,
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    #[serde(rename = "tx")]
+    pub transaction_id: u32,
+    // Decimals are read from their exact text, like TransactionRecord amounts, so replay reproduces large balances.
+    #[serde(with = "rust_decimal::serde::str_option")]
+    pub amount: Option<Decimal>,
+    #[serde(with = "rust_decimal::serde::str")]
+    pub available: Decimal,
+    #[serde(with = "rust_decimal::serde::str")]
+    pub held: Decimal,
+    #[serde(with = "rust_decimal::serde::str")]
+    pub total: Decimal,
+    pub locked: bool,
+    /// Absent (false) in journals from older builds, which could not close accounts.
+    #[serde(default)]
+    pub closed: bool,
+    /// Reason given by support staff for administrative events, kept as the audit trail.
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Currency of the balance the event changed, None for the default currency. Absent in journals from older builds.
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// Rate applied by a Convert, so replay restores the transaction with it. Absent in journals from older builds.
+    #[serde(default, with = "rust_decimal::serde::str_option")]
+    pub rate: Option<Decimal>,
+    /// Other client of a transfer event. Absent in journals from older builds.
+    #[serde(default)]
+    pub counterparty: Option<u16>,
+    /// Resulting funds reserved by open authorizations. Absent (zero) in journals from older builds.
+    #[serde(default, with = "rust_decimal::serde::str")]
+    pub reserved: Decimal,
+    /// Sequence number of the last record an authorization outlives, on FundsReserved events.
+    #[serde(default)]
+    pub expires_after: Option<u64>,
+    /// Timestamp an authorization expires at, on FundsReserved events.
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    /// Withdrawal a refund event pays back. Absent in journals from older builds.
+    #[serde(rename = "original_tx", default)]
+    pub original_transaction_id: Option<u32>,
+}
+
+impl LedgerEvent {
+    /// Returns true when `account` matches the resulting state recorded on this event, in the event's currency.
+    pub fn matches(&self, account: &Account) -> bool {
+        let balance = account.balance(self.currency.as_deref());
+        balance.available == self.available
+            && balance.held == self.held
+            && balance.total == self.total
+            && balance.reserved == self.reserved
+            && account.locked == self.locked
+            && account.closed == self.closed
+    }
+}
+
+/// Append-only CSV journal of LedgerEvents.
+pub struct JournalWriter<W: Write> {
+    writer: Writer<W>,
+}
+
+impl JournalWriter<File> {
+    /// Opens the journal at `path` for appending, creating it (with a header) if it doesn't exist yet.
+    pub fn open(path: &Path) -> Result<Self, ApplicationError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let is_empty = file.metadata()?.len() == 0;
+        Ok(JournalWriter {
+            writer: WriterBuilder::new().has_headers(is_empty).from_writer(file),
+        })
+    }
+}
+
+impl<W: Write> JournalWriter<W> {
+    pub fn from_writer(writer: W) -> Self {
+        JournalWriter { writer: Writer::from_writer(writer) }
+    }
+
+    /// Appends events and flushes them, so a crash never loses an event for an already processed record.
+    pub fn append(&mut self, events: &[LedgerEvent]) -> Result<(), ApplicationError> {
+        for event in events {
+            self.writer.serialize(event)?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Rebuilds an in-memory Engine from a journal, verifying every event against the state it recorded.
+pub fn replay<R: Read>(reader: R) -> Result<Engine, ApplicationError> {
+    let mut engine = Engine::new();
+    replay_into(&mut engine, reader)?;
+    Ok(engine)
+}
+
+/// Applies every event in a journal to `engine`, which must be empty or hold the state preceding the journal.
+pub fn replay_into<A: AccountStore, T: TransactionStore, R: Read>(engine: &mut Engine<A, T>, reader: R) -> Result<(), ApplicationError> {
+    let mut reader = ReaderBuilder::new().trim(Trim::All).from_reader(reader);
+    for result in reader.deserialize::<LedgerEvent>() {
+        engine.apply_event(&result?)?;
+    }
+    Ok(())
+}
+
+/// Rebuilds an Engine from the journal file at `path`. See `replay`.
+pub fn replay_path(path: &Path) -> Result<Engine, ApplicationError> {
+    replay(File::open(path)?)
+}
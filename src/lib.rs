@@ -1,3 +1,17 @@
 pub mod model;
 pub mod engine;
+pub mod authorization;
+pub mod config;
+pub mod currency;
+pub mod dispute;
 pub mod error;
+pub mod format;
+pub mod journal;
+pub mod mapping;
+pub mod parallel;
+pub mod rates;
+pub mod report;
+pub mod schema;
+pub mod snapshot;
+pub mod storage;
+pub mod validation;
@@ -1,87 +1,614 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use env_logger::Env;
-use log::info;
-use clap::Parser;
-use csv::{ReaderBuilder, Trim, Writer};
-use crate::model::TransactionRecord;
-use crate::engine::Engine;
-
-mod model;
-mod engine;
-mod error;
+use log::{error, info, warn};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+use csv::{ReaderBuilder, StringRecord, Trim};
+use payments_engine::config::{EngineConfig, PrecisionPolicy, ValidationConfig, WithdrawalDisputePolicy};
+use payments_engine::model::{RejectionReason, TransactionOutcome, TransactionRecord};
+use payments_engine::engine::Engine;
+use payments_engine::error::{ApplicationError, ExitStatus};
+use payments_engine::format::{self, InputFormat, OutputFormat};
+use payments_engine::journal::{self, JournalWriter};
+use payments_engine::mapping::InputMapping;
+use payments_engine::parallel::ShardedEngine;
+use payments_engine::report::{AuditReport, RejectsReport, PARSE_ERROR_CODE};
+use payments_engine::rates::RateTable;
+use payments_engine::schema::{self, LATEST_SCHEMA};
+use payments_engine::snapshot;
+use payments_engine::storage::{AccountStore, SqliteAccountStore, SqliteTransactionStore, TransactionStore};
+use payments_engine::validation;
 
 /// Simple Payments Engine
 #[derive(Parser)]
 #[clap(author, version, about)]
 struct Args {
-    /// Input CSV file of transactions
-    #[clap(value_parser)]
-    input: std::path::PathBuf,
+    /// Input files of transactions, processed in order. `-` reads from stdin.
+    #[clap(value_parser, required_unless_present = "replay")]
+    inputs: Vec<PathBuf>,
+
+    /// Format of the input and admin files
+    #[clap(long, value_enum, default_value_t = InputFormat::Csv)]
+    input_format: InputFormat,
+
+    /// TOML file mapping the columns, delimiter and transaction type names of partner CSV input onto the standard ones.
+    /// Applies to the input files, not the admin file.
+    #[clap(long, value_parser)]
+    mapping: Option<PathBuf>,
+
+    /// CSV input files have no header row. Columns are taken in the order of the schema a file declares, or the latest schema.
+    /// Applies to the input files, not the admin file.
+    #[clap(long)]
+    no_headers: bool,
+
+    /// Format of the accounts written to stdout
+    #[clap(long, value_enum, default_value_t = OutputFormat::Csv)]
+    output_format: OutputFormat,
+
+    /// Write accounts to this file instead of stdout. It is replaced atomically once the run succeeds,
+    /// so it never holds partial output.
+    #[clap(long, value_parser)]
+    output: Option<PathBuf>,
+
+    /// Write every skipped input row, with a reason code, to this CSV file
+    #[clap(long, value_parser)]
+    rejects: Option<PathBuf>,
+
+    /// Append every applied state change to this journal file, resuming from the events it already contains
+    #[clap(long, value_parser)]
+    journal: Option<PathBuf>,
+
+    /// File of administrative transactions (unlock, freeze, close, adjustment), applied after the inputs. `-` reads from stdin.
+    #[clap(long, value_parser)]
+    admin: Option<PathBuf>,
+
+    /// Write every applied administrative transaction, with its reason, to this CSV file
+    #[clap(long, value_parser)]
+    audit: Option<PathBuf>,
+
+    /// Rebuild accounts from a journal file instead of reading input, verifying every event along the way
+    #[clap(long, value_parser, conflicts_with_all = ["inputs", "rejects", "journal", "admin", "audit"])]
+    replay: Option<PathBuf>,
+
+    /// Keep accounts and transactions in a SQLite database at this path instead of in memory.
+    /// The database is scratch space for this run and is cleared on start.
+    #[clap(long, value_parser)]
+    store: Option<PathBuf>,
+
+    /// Start from the engine state saved in this snapshot file, instead of an empty engine
+    #[clap(long, value_parser)]
+    resume_from: Option<PathBuf>,
+
+    /// Save the final engine state to this snapshot file, so a later run can continue from it
+    #[clap(long, value_parser)]
+    save_snapshot: Option<PathBuf>,
+
+    /// Process clients in parallel on this many worker threads. Output is identical to a single-threaded run.
+    #[clap(long, value_parser = clap::value_parser!(u16).range(1..), conflicts_with_all = ["replay", "journal", "store"])]
+    workers: Option<u16>,
+
+    /// CSV file of currency conversion rates (`from,to,rate,effective_from`) that convert transactions are applied at
+    #[clap(long, value_parser)]
+    rates: Option<PathBuf>,
+
+    /// What a dispute on a withdrawal does: reject it, or hold the withdrawn amount as a pending credit
+    #[clap(long, value_enum, default_value_t = WithdrawalDisputePolicy::Reject)]
+    withdrawal_disputes: WithdrawalDisputePolicy,
+
+    /// How many times a single transaction can be disputed, counting resolved disputes. Unlimited by default.
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..))]
+    max_disputes: Option<u32>,
+
+    /// How many subsequent records an authorization outlives before its reserved funds are released. Never expires by default.
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    authorization_expiry_records: Option<u64>,
+
+    /// How many seconds after its timestamp an authorization expires, by the latest record timestamp. Never expires by default.
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    authorization_expiry_seconds: Option<u64>,
+
+    /// What to do with amounts that have more decimal places than their currency: reject the row, or round the amount
+    #[clap(long, value_enum, default_value_t = PrecisionPolicy::Reject)]
+    precision: PrecisionPolicy,
+
+    /// Verify the ledger invariants of every account after processing, failing the run if any is violated
+    #[clap(long)]
+    verify: bool,
+
+    /// Stop at the first rejected or unparsable row, failing the run without writing accounts
+    #[clap(long, conflicts_with = "workers")]
+    strict: bool,
+}
+
+impl Args {
+    fn engine_config(&self) -> EngineConfig {
+        EngineConfig {
+            withdrawal_disputes: self.withdrawal_disputes,
+            max_disputes: self.max_disputes,
+            authorization_expiry_records: self.authorization_expiry_records,
+            authorization_expiry_seconds: self.authorization_expiry_seconds,
+        }
+    }
+
+    fn validation_config(&self) -> ValidationConfig {
+        ValidationConfig { precision: self.precision }
+    }
+
+    fn rate_table(&self) -> Result<RateTable, ApplicationError> {
+        self.rates.as_deref().map(RateTable::load).transpose().map(Option::unwrap_or_default)
+    }
+
+    fn input_mapping(&self) -> Result<Option<InputMapping>, ApplicationError> {
+        self.mapping.as_deref().map(InputMapping::load).transpose()
+    }
+
+    /// How to read a file. Partner options, the mapping and headerless input, don't apply to the admin file.
+    fn input_options<'a>(&self, mapping: Option<&'a InputMapping>, admin_only: bool) -> InputOptions<'a> {
+        InputOptions {
+            format: self.input_format,
+            mapping: mapping.filter(|_| !admin_only),
+            headers: admin_only || !self.no_headers,
+            admin_only,
+            validation: self.validation_config(),
+        }
+    }
+
+    /// Files to read in order, each flagged when it may only hold administrative records.
+    fn files(&self) -> Vec<(&Path, bool)> {
+        let mut files: Vec<(&Path, bool)> = self.inputs.iter().map(|input| (input.as_path(), false)).collect();
+        if let Some(admin) = &self.admin {
+            files.push((admin.as_path(), true));
+        }
+        files
+    }
+}
+
+/// How to read one file of transactions.
+struct InputOptions<'a> {
+    format: InputFormat,
+    // Rearranges CSV rows into the standard columns before they are parsed
+    mapping: Option<&'a InputMapping>,
+    // False when CSV rows start on the first line, after any schema marker
+    headers: bool,
+    // Non-administrative rows are rejected
+    admin_only: bool,
+    validation: ValidationConfig,
 }
 
-fn main() -> Result<(), error::ApplicationError> {
+impl InputOptions<'_> {
+    /// Field delimiter of CSV rows, a comma unless the mapping changes it.
+    fn delimiter(&self) -> u8 {
+        self.mapping.map_or(b',', |mapping| mapping.delimiter as u8)
+    }
+}
+
+// (file_index, row_number, raw_line, error_message)
+type RowError = (usize, u64, String, String);
+
+// (file_index, row_number, raw_fields, reason_code, message)
+type RejectRow = (usize, u64, Option<StringRecord>, String, String);
+
+fn main() -> ExitCode {
     // Initialize logging
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
     info!("Starting payments engine");
 
     let args = Args::parse();
+    if args.files().iter().filter(|(path, _)| is_stdin(path)).count() > 1 {
+        Args::command().error(ErrorKind::ArgumentConflict, "stdin (`-`) can only be read once").exit();
+    }
+    if (args.mapping.is_some() || args.no_headers) && args.input_format != InputFormat::Csv {
+        Args::command().error(ErrorKind::ArgumentConflict, "--mapping and --no-headers only apply to CSV input").exit();
+    }
 
-    let mut engine = Engine::new();
-    let mut reader = ReaderBuilder::new()
-        .trim(Trim::All)
-        .flexible(true)
-        .from_path(&args.input)?;
+    let result = match (&args.store, args.workers) {
+        (_, Some(workers)) => run_sharded(&args, workers as usize),
+        (Some(path), None) => {
+            info!("Using SQLite store at {}", path.display());
+            open_sqlite_engine(&args, path).and_then(|engine| run(&args, engine))
+        }
+        (None, None) => run(&args, Engine::with_config(args.engine_config())),
+    };
+    match result {
+        Ok(status) => status.into(),
+        Err(e) => {
+            error!("{}", e);
+            e.exit_status().into()
+        }
+    }
+}
+
+fn open_sqlite_engine(args: &Args, path: &Path) -> Result<Engine<SqliteAccountStore, SqliteTransactionStore>, ApplicationError> {
+    let mut engine = Engine::with_stores(SqliteAccountStore::open(path)?, SqliteTransactionStore::open(path)?);
+    engine.config = args.engine_config();
+    Ok(engine)
+}
+
+/// Runs the engine in the mode selected by `args`, then writes accounts to stdout and collected errors to stderr.
+fn run<A: AccountStore, T: TransactionStore>(args: &Args, mut engine: Engine<A, T>) -> Result<ExitStatus, ApplicationError> {
+    engine.rates = args.rate_table()?;
+    if let Some(snapshot_path) = &args.resume_from {
+        snapshot::load_into(&mut engine, snapshot_path)?;
+        info!("Resumed from snapshot {}", snapshot_path.display());
+    }
+    let mapping = args.input_mapping()?;
+    let mut report = RunReport::new(args, mapping.as_ref())?;
+    match (&args.replay, args.inputs.is_empty()) {
+        (Some(journal_path), _) => {
+            journal::replay_into(&mut engine, File::open(journal_path)?)?;
+            info!("Replayed journal {} and verified every event", journal_path.display());
+        }
+        (None, false) => {
+            // Resume from an existing journal so it stays one continuous ledger
+            let mut journal = match &args.journal {
+                Some(journal_path) => {
+                    if journal_path.exists() {
+                        journal::replay_into(&mut engine, File::open(journal_path)?)?;
+                    }
+                    engine.enable_journal();
+                    Some(JournalWriter::open(journal_path)?)
+                }
+                None => None,
+            };
+            for (file, (path, admin_only)) in args.files().into_iter().enumerate() {
+                report.file = file;
+                read_input(path, &args.input_options(mapping.as_ref(), admin_only), &mut report, |report, row, raw, record| {
+                    let result = engine.process_transaction(record.clone());
+                    if let Some(journal) = journal.as_mut() {
+                        journal.append(&engine.take_events())?;
+                    }
+                    report.outcome(row, &raw, &record, result)
+                })?;
+            }
+        }
+        (None, true) => unreachable!("clap requires input unless replaying"),
+    }
+    finish(args, &engine, report)
+}
 
-    // Prepare a buffer to collect (row_index, raw_line, error_message)
-    let mut errors: Vec<(usize, String, String)> = Vec::new();
-    for (index, result) in reader.deserialize::<TransactionRecord>().enumerate() {
+/// Like run, but spreads clients across worker threads. Only supports the in-memory stores.
+fn run_sharded(args: &Args, workers: usize) -> Result<ExitStatus, ApplicationError> {
+    let mut engine = Engine::with_config(args.engine_config());
+    engine.rates = args.rate_table()?;
+    if let Some(snapshot_path) = &args.resume_from {
+        snapshot::load_into(&mut engine, snapshot_path)?;
+        info!("Resumed from snapshot {}", snapshot_path.display());
+    }
+    info!("Processing on {} worker shards", workers);
+    let mut sharded = ShardedEngine::new(engine, workers);
+    let mapping = args.input_mapping()?;
+    let mut report = RunReport::new(args, mapping.as_ref())?;
+    // Parse errors are found while reading but shard results only arrive at the end, so order them by row first
+    report.deferred = Some(Vec::new());
+    for (file, (path, admin_only)) in args.files().into_iter().enumerate() {
+        report.file = file;
+        read_input(path, &args.input_options(mapping.as_ref(), admin_only), &mut report, |_, row, raw, record| {
+            sharded.submit((file, row, raw), record)
+        })?;
+    }
+    let (engine, results) = sharded.finish()?;
+    for ((file, row, raw), record, result) in results {
+        report.file = file;
+        report.outcome(row, &raw, &record, result)?;
+    }
+    report.write_deferred()?;
+    // Applied administrative records are returned for the audit report too, so count applied records from the rest
+    report.applied = report.submitted - report.rejected - report.failed;
+    finish(args, &engine, report)
+}
+
+/// Saves the snapshot if requested, writes accounts to stdout and collected errors to stderr.
+/// Returns the exit status the outcomes of the run call for.
+fn finish<A: AccountStore, T: TransactionStore>(args: &Args, engine: &Engine<A, T>, mut report: RunReport) -> Result<ExitStatus, ApplicationError> {
+    if let Some(report) = report.rejects.as_mut() {
+        report.flush()?;
+    }
+    if let Some(audit) = report.audit.as_mut() {
+        audit.flush()?;
+    }
+    if args.replay.is_none() {
+        info!("Processed transactions: {} applied, {} rejected, {} errors", report.applied, report.rejected, report.errors.len());
+    }
+
+    if args.verify {
+        engine.check_invariants()?;
+        info!("Verified ledger invariants");
+    }
+
+    if let Some(snapshot_path) = &args.save_snapshot {
+        snapshot::save(engine, snapshot_path)?;
+        info!("Saved snapshot {}", snapshot_path.display());
+    }
+
+    match &args.output {
+        Some(output_path) => {
+            format::write_atomically(output_path, |writer| format::write_accounts(&engine.accounts, args.output_format, writer))?;
+            info!("Wrote accounts to {}", output_path.display());
+        }
+        None => format::write_accounts(&engine.accounts, args.output_format, std::io::stdout().lock())?,
+    }
+
+    // Emit collected errors to stderr
+    for (file, row, raw, msg) in &report.errors {
+        let file = &report.files[*file];
+        if raw.is_empty() {
+            eprintln!("Error at {}:{}: {}.", file, row, msg);
+        } else {
+            eprintln!("Error at {}:{} (record={}): {}", file, row, raw, msg);
+        }
+    }
+    Ok(report.exit_status())
+}
+
+/// Tallies record outcomes, writing skipped rows to the rejects report and collecting errors for stderr.
+struct RunReport {
+    rejects: Option<RejectsReport<File>>,
+    audit: Option<AuditReport<File>>,
+    // Names of the files read, in Args::files order, used to qualify row numbers in reports
+    files: Vec<String>,
+    // Field delimiter of each file, in Args::files order, used to join raw fields back up in the rejects report
+    delimiters: Vec<u8>,
+    // Index into files of the file rows currently come from. Row numbers are per file.
+    file: usize,
+    // When set, rejects report rows are held here until write_deferred, instead of written as they come
+    deferred: Option<Vec<RejectRow>>,
+    errors: Vec<RowError>,
+    // Fail on the first rejected, unparsable or failed row instead of tallying it
+    strict: bool,
+    submitted: usize,
+    applied: usize,
+    rejected: usize,
+    failed: usize,
+    malformed: usize,
+}
+
+impl RunReport {
+    fn new(args: &Args, mapping: Option<&InputMapping>) -> Result<Self, ApplicationError> {
+        let rejects = match &args.rejects {
+            Some(path) => Some(RejectsReport::create(path)?),
+            None => None,
+        };
+        let audit = match &args.audit {
+            Some(path) => Some(AuditReport::create(path)?),
+            None => None,
+        };
+        let files = args.files().into_iter().map(|(path, _)| file_name(path)).collect();
+        let delimiters = args.files().into_iter().map(|(_, admin_only)| args.input_options(mapping, admin_only).delimiter()).collect();
+        Ok(RunReport {
+            rejects,
+            audit,
+            files,
+            delimiters,
+            file: 0,
+            deferred: None,
+            errors: Vec::new(),
+            strict: args.strict,
+            submitted: 0,
+            applied: 0,
+            rejected: 0,
+            failed: 0,
+            malformed: 0,
+        })
+    }
+
+    /// Ledger errors outrank rejections, so a scheduler alerts on the more serious problem.
+    fn exit_status(&self) -> ExitStatus {
+        if self.failed > 0 {
+            ExitStatus::LedgerError
+        } else if self.rejected > 0 || self.malformed > 0 {
+            ExitStatus::Rejections
+        } else {
+            ExitStatus::Success
+        }
+    }
+
+    fn outcome(&mut self, row: u64, raw: &StringRecord, record: &TransactionRecord, result: Result<TransactionOutcome, ApplicationError>) -> Result<(), ApplicationError> {
         match result {
-            Ok(record) => {
-                // Try to process; on Err, collect and continue
-                if let Err(e) = engine.process_transaction(record.clone()) {
-                    errors.push((
-                        index,
-                        format!("{:?}", record),
-                        e.to_string(),
-                    ));
+            Ok(TransactionOutcome::Applied) => {
+                self.applied += 1;
+                if let (Some(audit), true) = (self.audit.as_mut(), record.transaction_type.is_administrative()) {
+                    audit.write(&self.files[self.file], row, record)?;
                 }
             }
+            Ok(TransactionOutcome::Rejected { reason, .. }) => {
+                self.rejected += 1;
+                self.reject(row, Some(raw), reason.code(), reason.to_string())?;
+                self.fail_if_strict(row, reason.to_string())?;
+            }
             Err(e) => {
-                // CSV parse error: collect and continue
-                errors.push((
-                    index,
-                    String::new(), // no record available
-                    format!("CSV parse error: {}", e),
+                self.failed += 1;
+                self.reject(row, Some(raw), e.code(), e.to_string())?;
+                if self.strict {
+                    return Err(e);
+                }
+                self.errors.push((
+                    self.file,
+                    row,
+                    format!("{:?}", record),
+                    e.to_string(),
                 ));
             }
         }
+        Ok(())
     }
 
-    // Output results to CSV on stdout
-    let mut writer = Writer::from_writer(std::io::stdout());
-    // Write header
-    writer.write_record(["client", "available", "held", "total", "locked"])?;
-    // Sort client IDs for deterministic output
-    let mut client_ids: Vec<u16> = engine.accounts.keys().cloned().collect();
-    client_ids.sort_unstable();
-    for client_id in client_ids {
-        if let Some(account) = engine.accounts.get(&client_id) {
-            writer.write_record(&[
-                client_id.to_string(),
-                format!("{:.4}", account.available),
-                format!("{:.4}", account.held),
-                format!("{:.4}", account.total),
-                account.locked.to_string(),
-            ])?;
+    fn parse_error(&mut self, row: u64, raw: Option<&StringRecord>, format: InputFormat, error: &dyn fmt::Display) -> Result<(), ApplicationError> {
+        self.malformed += 1;
+        self.reject(row, raw, PARSE_ERROR_CODE, error.to_string())?;
+        let format = match format {
+            InputFormat::Csv => "CSV",
+            InputFormat::Jsonl => "JSON",
+        };
+        self.fail_if_strict(row, format!("{} parse error: {}", format, error))?;
+        self.errors.push((
+            self.file,
+            row,
+            String::new(), // no record available
+            format!("{} parse error: {}", format, error),
+        ));
+        Ok(())
+    }
+
+    /// In strict mode, stops the run at the given row. The rejects report still gets the row, as it is written first.
+    fn fail_if_strict(&mut self, row: u64, message: String) -> Result<(), ApplicationError> {
+        if !self.strict {
+            return Ok(());
         }
+        if let Some(report) = self.rejects.as_mut() {
+            report.flush()?;
+        }
+        Err(ApplicationError::StrictRejection { file: self.files[self.file].clone(), row, message })
     }
-    writer.flush()?;
 
-    // Emit collected errors to stderr
-    for (row, raw, msg) in errors {
-        if raw.is_empty() {
-            eprintln!("Error at row {}: {}.", row, msg);
-        } else {
-            eprintln!("Error at row {} (record={}): {}", row, raw, msg);
+    fn reject(&mut self, row: u64, raw: Option<&StringRecord>, reason: &str, message: String) -> Result<(), ApplicationError> {
+        match (self.deferred.as_mut(), self.rejects.as_mut()) {
+            (Some(deferred), Some(_)) => deferred.push((self.file, row, raw.cloned(), reason.to_string(), message)),
+            (None, Some(report)) => report.write(&self.files[self.file], row, raw, self.delimiters[self.file], reason, &message)?,
+            (_, None) => {}
+        }
+        Ok(())
+    }
+
+    /// Writes deferred rejects report rows, and orders collected errors, by file and row number.
+    fn write_deferred(&mut self) -> Result<(), ApplicationError> {
+        let mut deferred = self.deferred.take().unwrap_or_default();
+        deferred.sort_by_key(|(file, row, ..)| (*file, *row));
+        if let Some(report) = self.rejects.as_mut() {
+            for (file, row, raw, reason, message) in deferred {
+                report.write(&self.files[file], row, raw.as_ref(), self.delimiters[file], &reason, &message)?;
+            }
+        }
+        self.errors.sort_by_key(|(file, row, ..)| (*file, *row));
+        Ok(())
+    }
+}
+
+fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Name of an input file as shown in reports.
+fn file_name(path: &Path) -> String {
+    if is_stdin(path) {
+        "<stdin>".to_string()
+    } else {
+        path.display().to_string()
+    }
+}
+
+/// Opens an input file for reading, or stdin for `-`, decompressing gzip and zstd input on the fly.
+fn open_input(path: &Path) -> Result<Box<dyn Read>, ApplicationError> {
+    if is_stdin(path) {
+        format::decompress(std::io::stdin().lock())
+    } else {
+        format::decompress(BufReader::new(File::open(path)?))
+    }
+}
+
+/// Streams an input file, handing every parsed and validated record to `process` along with its row number and raw fields.
+/// Rows that fail to parse or validate are reported and skipped, as are non-administrative rows for the admin file.
+///
+/// A CSV file may start with a `#schema=<version>` line, against which its header is then checked.
+/// Row numbers are line numbers in the file. A JSON Lines row is kept as a single raw field holding the whole line.
+fn read_input<F>(input: &Path, options: &InputOptions, report: &mut RunReport, mut process: F) -> Result<(), ApplicationError>
+where
+    F: FnMut(&mut RunReport, u64, StringRecord, TransactionRecord) -> Result<(), ApplicationError>,
+{
+    let mut accept = |report: &mut RunReport, row: u64, raw: StringRecord, mut record: TransactionRecord| {
+        report.submitted += 1;
+        if options.admin_only && !record.transaction_type.is_administrative() {
+            warn!("{} transaction {} in the admin file is not administrative. Skipping.", record.transaction_type, record.transaction_id);
+            return report.outcome(row, &raw, &record, Ok(TransactionOutcome::rejected(&record, RejectionReason::NotAdministrative)));
+        }
+        match validation::validate(&mut record, &options.validation) {
+            Ok(()) => process(report, row, raw, record),
+            Err(reason) => report.outcome(row, &raw, &record, Ok(TransactionOutcome::rejected(&record, reason))),
+        }
+    };
+
+    let format = options.format;
+    match format {
+        InputFormat::Csv => {
+            let mut input = BufReader::new(open_input(input)?);
+            let (marker, version) = schema::read_marker(&mut input)?;
+            let mut builder = ReaderBuilder::new();
+            builder.trim(Trim::All).flexible(true).has_headers(options.headers);
+            if !marker.is_empty() {
+                // Hand the marker back so line numbers stay right, but skip it and any further comment lines
+                builder.comment(Some(b'#'));
+            }
+            builder.delimiter(options.delimiter());
+            if let Some(mapping) = options.mapping {
+                builder.quote(mapping.quote as u8);
+            }
+            let mut reader = builder.from_reader(Cursor::new(marker).chain(input));
+            let headers = match options.headers {
+                true => reader.headers()?.clone(),
+                false => StringRecord::from(schema::columns(version.unwrap_or(LATEST_SCHEMA))?.to_vec()),
+            };
+            let mut columns = options.mapping.map(|mapping| mapping.resolve(&headers)).transpose()?;
+            match (version, &columns) {
+                (Some(version), Some(columns)) => columns.check_schema(version)?,
+                (Some(version), None) => schema::check_header(version, &headers)?,
+                (None, _) => {}
+            }
+            // Without a mapping, the columns of a declared schema are all a row may have
+            let width = version.filter(|_| columns.is_none()).map(|_| headers.len());
+
+            for result in reader.records() {
+                let raw = match result {
+                    Ok(raw) => raw,
+                    // A broken stream, e.g. a truncated archive, can't be skipped past like a bad row
+                    Err(e) if e.is_io_error() => return Err(e.into()),
+                    Err(e) => {
+                        // Unreadable row: collect and continue
+                        let row = e.position().map(|position| position.line()).unwrap_or_default();
+                        report.parse_error(row, None, format, &e)?;
+                        continue;
+                    }
+                };
+                let row = raw.position().map(|position| position.line()).unwrap_or_default();
+                if let Some(width) = width.filter(|width| raw.len() > *width) {
+                    report.parse_error(row, Some(&raw), format, &format!("row has {} fields, but its schema has {} columns", raw.len(), width))?;
+                    continue;
+                }
+                let parsed = match columns.as_mut() {
+                    Some(columns) => columns.deserialize(&raw),
+                    None => raw.deserialize::<TransactionRecord>(Some(&headers)),
+                };
+                match parsed {
+                    Ok(record) => accept(report, row, raw, record)?,
+                    // CSV parse error: collect and continue
+                    Err(e) => report.parse_error(row, Some(&raw), format, &e)?,
+                }
+            }
+        }
+        InputFormat::Jsonl => {
+            for (index, line) in BufReader::new(open_input(input)?).split(b'\n').enumerate() {
+                let row = index as u64 + 1;
+                let line = match String::from_utf8(line?) {
+                    Ok(line) => line,
+                    Err(e) => {
+                        // Unreadable row: collect and continue
+                        report.parse_error(row, None, format, &e)?;
+                        continue;
+                    }
+                };
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                let raw = StringRecord::from(vec![line]);
+                match format::parse_json_record(line) {
+                    Ok(record) => accept(report, row, raw, record)?,
+                    // JSON parse error: collect and continue
+                    Err(e) => report.parse_error(row, Some(&raw), format, &e)?,
+                }
+            }
         }
     }
     Ok(())
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use csv::StringRecord;
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::Deserialize;
+use crate::error::ApplicationError;
+use crate::model::{TransactionRecord, TransactionType};
+use crate::schema::{self, COLUMNS};
+
+/// Where to find one field of a TransactionRecord in a partner's CSV rows.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum Column {
+    /// The column at this position, counting from 1.
+    Position(usize),
+    /// The column with this header.
+    Header(String),
+    /// The first column whose header is any of these.
+    Headers(Vec<String>),
+}
+
+/// How a partner's CSV input maps onto the standard `type,client,tx,amount,reason,currency,target_currency,timestamp,counterparty,original_tx` columns. Loaded from a TOML file:
+///
+/// ```toml
+/// delimiter = ";"
+///
+/// [columns]
+/// type = "TxnType"
+/// client = ["Client", "ClientId"]
+/// tx = 3
+///
+/// [types]
+/// DEP = "deposit"
+/// WDL = "withdrawal"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputMapping {
+    /// Field delimiter. Defaults to a comma.
+    pub delimiter: char,
+    /// Quote character. Defaults to a double quote.
+    pub quote: char,
+    /// Column of each field, keyed by field name. A field left out is found by its own name in the header.
+    pub columns: HashMap<String, Column>,
+    /// Transaction type names used by the partner, mapped to ours. Names not listed are read as they are.
+    pub types: HashMap<String, String>,
+}
+
+impl Default for InputMapping {
+    fn default() -> Self {
+        InputMapping { delimiter: ',', quote: '"', columns: HashMap::new(), types: HashMap::new() }
+    }
+}
+
+fn invalid(reason: impl Into<String>) -> ApplicationError {
+    ApplicationError::InvalidMapping { reason: reason.into() }
+}
+
+impl InputMapping {
+    /// Parses and checks a mapping from TOML text.
+    pub fn from_toml(text: &str) -> Result<Self, ApplicationError> {
+        let mapping: InputMapping = toml::from_str(text).map_err(|e| invalid(e.message().to_string()))?;
+        for (name, character) in [("delimiter", mapping.delimiter), ("quote", mapping.quote)] {
+            if !character.is_ascii() {
+                return Err(invalid(format!("`{}` must be a single ASCII character", name)));
+            }
+        }
+        for (field, column) in &mapping.columns {
+            if !COLUMNS.contains(&field.as_str()) {
+                return Err(invalid(format!("unknown field `{}` in columns, expected one of {}", field, COLUMNS.join(", "))));
+            }
+            if *column == Column::Position(0) {
+                return Err(invalid(format!("column positions count from 1, but `{}` is at 0", field)));
+            }
+        }
+        for (alias, name) in &mapping.types {
+            if TransactionType::deserialize(StrDeserializer::<ValueError>::new(name)).is_err() {
+                return Err(invalid(format!("type alias `{}` maps to unknown type `{}`", alias, name)));
+            }
+        }
+        Ok(mapping)
+    }
+
+    /// Loads a mapping from the TOML file at `path`.
+    pub fn load(path: &Path) -> Result<Self, ApplicationError> {
+        Self::from_toml(&fs::read_to_string(path)?)
+    }
+
+    /// Resolves the column of every field against the header of a file. Headerless files resolve against the columns of their schema.
+    /// Fails when a column configured by header name is not in it, since every row would fail to parse otherwise.
+    pub fn resolve(&self, headers: &StringRecord) -> Result<ColumnMap<'_>, ApplicationError> {
+        let find = |name: &str| headers.iter().position(|header| header == name);
+        let mut indices = [None; COLUMNS.len()];
+        for (index, field) in indices.iter_mut().zip(COLUMNS) {
+            *index = match self.columns.get(field) {
+                Some(Column::Position(position)) => Some(position - 1),
+                Some(Column::Header(name)) => Some(find(name).ok_or_else(|| invalid(format!("the header has no `{}` column for `{}`", name, field)))?),
+                Some(Column::Headers(names)) => Some(
+                    names.iter().find_map(|name| find(name)).ok_or_else(|| invalid(format!("the header has none of the columns {:?} for `{}`", names, field)))?,
+                ),
+                None => find(field),
+            };
+        }
+        Ok(ColumnMap { mapping: self, indices, headers: StringRecord::new(), fields: StringRecord::new() })
+    }
+}
+
+/// An InputMapping resolved against the header of one file, ready to deserialize its rows.
+pub struct ColumnMap<'a> {
+    mapping: &'a InputMapping,
+    // Column index of each entry of COLUMNS, None when the file does not have it
+    indices: [Option<usize>; COLUMNS.len()],
+    // Reused for every row to save allocations
+    headers: StringRecord,
+    fields: StringRecord,
+}
+
+impl ColumnMap<'_> {
+    /// Checks the file against the schema it declares: every column of that version must be found, and none of a later one.
+    pub fn check_schema(&self, version: u32) -> Result<(), ApplicationError> {
+        let width = schema::columns(version)?.len();
+        for (position, (index, field)) in self.indices.iter().zip(COLUMNS).enumerate() {
+            match (index, position < width) {
+                (None, true) => return Err(ApplicationError::InvalidSchema { reason: format!("no column for `{}`, which schema {} requires", field, version) }),
+                (Some(_), false) => return Err(ApplicationError::InvalidSchema { reason: format!("column for `{}`, which is not part of schema {}", field, version) }),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Rearranges a row into the standard columns, renaming its transaction type, and deserializes it.
+    /// A column missing from a short row is left out, like a missing trailing field in standard input.
+    pub fn deserialize(&mut self, raw: &StringRecord) -> Result<TransactionRecord, csv::Error> {
+        self.headers.clear();
+        self.fields.clear();
+        for (index, field) in self.indices.iter().zip(COLUMNS) {
+            if let Some(value) = index.and_then(|index| raw.get(index)) {
+                let value = match field {
+                    "type" => self.mapping.types.get(value).map(String::as_str).unwrap_or(value),
+                    _ => value,
+                };
+                self.headers.push_field(field);
+                self.fields.push_field(value);
+            }
+        }
+        self.fields.deserialize(Some(&self.headers))
+    }
+}
@@ -1,8 +1,10 @@
+use std::collections::BTreeMap;
 use std::fmt;
 use rust_decimal::Decimal;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use crate::currency;
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum TransactionType {
     Deposit,
@@ -10,6 +12,33 @@ pub enum TransactionType {
     Dispute,
     Resolve,
     Chargeback,
+    /// Administrative: clears the lock of an account, e.g. after a chargeback was settled with the client.
+    Unlock,
+    /// Administrative: locks an account, so it takes no deposits or withdrawals until unlocked.
+    Freeze,
+    /// Administrative: permanently closes an account with a zero balance.
+    Close,
+    /// Administrative: credits (positive amount) or debits (negative amount) available funds by hand. Requires a reason.
+    Adjustment,
+    /// Converts an amount from one currency of an account into another, at the rate in effect at the record's timestamp.
+    Convert,
+    /// Moves an amount from the client's account to the account of its counterparty.
+    Transfer,
+    /// Reserves an amount of the available funds, to be captured or voided later.
+    Authorize,
+    /// Turns the funds reserved by an Authorize into a withdrawal, fully or partially. The rest is released.
+    Capture,
+    /// Releases the funds reserved by an Authorize, fully or partially, back to available.
+    Void,
+    /// Credits the client back part or all of an earlier withdrawal, named by its original transaction ID.
+    Refund,
+}
+
+impl TransactionType {
+    /// Returns true for the types issued by support staff rather than by card partners.
+    pub fn is_administrative(&self) -> bool {
+        matches!(self, TransactionType::Unlock | TransactionType::Freeze | TransactionType::Close | TransactionType::Adjustment)
+    }
 }
 
 // Used for stringifying TransactionType in ApplicationError messages.
@@ -22,12 +51,22 @@ impl fmt::Display for TransactionType {
             TransactionType::Dispute    => "Dispute",
             TransactionType::Resolve    => "Resolve",
             TransactionType::Chargeback => "Chargeback",
+            TransactionType::Unlock     => "Unlock",
+            TransactionType::Freeze     => "Freeze",
+            TransactionType::Close      => "Close",
+            TransactionType::Adjustment => "Adjustment",
+            TransactionType::Convert    => "Convert",
+            TransactionType::Transfer   => "Transfer",
+            TransactionType::Authorize  => "Authorize",
+            TransactionType::Capture    => "Capture",
+            TransactionType::Void       => "Void",
+            TransactionType::Refund     => "Refund",
         };
         write!(f, "{}", s)
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TransactionRecord {
     #[serde(rename = "type")]
     pub transaction_type: TransactionType,
@@ -35,15 +74,93 @@ pub struct TransactionRecord {
     pub client_id: u16,
     #[serde(rename = "tx")]
     pub transaction_id: u32,
+    // Parsed from the exact text. By default CSV numbers go through f64, which loses digits on large amounts.
+    // Defaults to None when the field is left out entirely, as JSON Lines input does for disputes.
+    #[serde(default, with = "rust_decimal::serde::str_option")]
     pub amount: Option<Decimal>,
+    // Why support staff issued an administrative record. Optional column, so inputs without it still parse.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    // ISO 4217 code of the amount. Optional column; None is the default currency, which every input used before currencies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    // Currency a Convert credits. Optional column, like every column added after the first schema.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_currency: Option<String>,
+    // When the transaction happened, in seconds since the Unix epoch. Picks the rate a Convert applies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    // Rate the engine applied to a Convert, stored with the transaction so disputes reverse it at that rate.
+    // Not an input column: validation drops any rate given in the input.
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "rust_decimal::serde::str_option")]
+    pub rate: Option<Decimal>,
+    // Client a Transfer moves funds to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counterparty: Option<u16>,
+    // Withdrawal a Refund pays back.
+    #[serde(rename = "original_tx", default, skip_serializing_if = "Option::is_none")]
+    pub original_transaction_id: Option<u32>,
+    // Amount refunded so far against a Withdrawal, stored with the transaction so refunds never add up to more than it.
+    // Not an input column: validation drops any refunded amount given in the input.
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "rust_decimal::serde::str_option")]
+    pub refunded: Option<Decimal>,
 }
 
-#[derive(Debug)]
+impl TransactionRecord {
+    /// Creates a record with none of the optional columns set.
+    pub fn new(transaction_type: TransactionType, client_id: u16, transaction_id: u32, amount: Option<Decimal>) -> Self {
+        TransactionRecord { transaction_type, client_id, transaction_id, amount, reason: None, currency: None, target_currency: None, timestamp: None, rate: None, counterparty: None, original_transaction_id: None, refunded: None }
+    }
+
+    /// Currency and amount this transaction moved into or out of the account, which is what disputes act on.
+    /// For a Convert that is the converted amount credited in the target currency.
+    pub fn disputable_funds(&self) -> (Option<&str>, Option<Decimal>) {
+        match self.transaction_type {
+            TransactionType::Convert => (self.target_currency.as_deref(), self.converted_amount()),
+            _ => (self.currency.as_deref(), self.amount),
+        }
+    }
+
+    /// Client whose funds a dispute of this transaction holds: the recipient of a Transfer, otherwise the client itself.
+    pub fn holding_client_id(&self) -> u16 {
+        match (self.transaction_type, self.counterparty) {
+            (TransactionType::Transfer, Some(counterparty)) => counterparty,
+            _ => self.client_id,
+        }
+    }
+
+    /// Amount a Convert credits in the target currency at its applied rate. None until the engine applied it.
+    pub fn converted_amount(&self) -> Option<Decimal> {
+        currency::convert(self.amount?, self.rate?, self.target_currency.as_deref())
+    }
+}
+
+/// Funds of an account in one currency.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Balance {
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+    /// Funds reserved by open authorizations. Part of total, like held.
+    #[serde(default)]
+    pub reserved: Decimal,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Account {
     pub available: Decimal,
     pub held: Decimal,
     pub total: Decimal,
     pub locked: bool,
+    /// Funds reserved by open authorizations in the default currency. Absent (zero) in snapshots from older builds.
+    #[serde(default)]
+    pub reserved: Decimal,
+    /// Set by an administrative Close. A closed account is also locked, and can never be unlocked.
+    #[serde(default)]
+    pub closed: bool,
+    /// Funds in named currencies, keyed by currency code. The fields above hold the default currency.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub currencies: BTreeMap<String, Balance>,
 }
 
 impl Account {
@@ -54,6 +171,243 @@ impl Account {
             held: Decimal::ZERO,
             total: Decimal::ZERO,
             locked: false,
+            reserved: Decimal::ZERO,
+            closed: false,
+            currencies: BTreeMap::new(),
+        }
+    }
+
+    /// Funds in `currency`, or in the default currency for None. Zero for a currency the account never held.
+    pub fn balance(&self, currency: Option<&str>) -> Balance {
+        match currency {
+            None => Balance { available: self.available, held: self.held, total: self.total, reserved: self.reserved },
+            Some(currency) => self.currencies.get(currency).copied().unwrap_or_default(),
+        }
+    }
+
+    /// Replaces the funds in `currency`, or in the default currency for None.
+    pub fn set_balance(&mut self, currency: Option<&str>, balance: Balance) {
+        match currency {
+            None => {
+                self.available = balance.available;
+                self.held = balance.held;
+                self.total = balance.total;
+                self.reserved = balance.reserved;
+            }
+            Some(currency) => {
+                self.currencies.insert(currency.to_string(), balance);
+            }
         }
     }
+
+    /// Funds in every currency: the default currency first, then named currencies by code.
+    pub fn balances(&self) -> impl Iterator<Item = (Option<&str>, Balance)> + '_ {
+        std::iter::once((None, self.balance(None)))
+            .chain(self.currencies.iter().map(|(currency, balance)| (Some(currency.as_str()), *balance)))
+    }
+}
+
+impl Default for Account {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Why the engine skipped a record. These represent bad input rather than a broken engine state,
+/// which is reported through ApplicationError instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectionReason {
+    /// Deposit or Withdrawal without an amount.
+    MissingAmount,
+    /// The client account is locked.
+    AccountLocked,
+    /// The client account was closed.
+    AccountClosed,
+    /// Unlock of an account that is not locked.
+    AccountNotLocked,
+    /// Close of an account that still holds funds.
+    NonZeroBalance,
+    /// Adjustment without a reason.
+    MissingReason,
+    /// Non-administrative record in the admin file.
+    NotAdministrative,
+    /// The transaction ID has already been used.
+    DuplicateTransaction,
+    /// Withdrawal for a client that has no account.
+    UnknownAccount,
+    /// Withdrawal larger than the available funds.
+    InsufficientFunds,
+    /// Dispute referencing a transaction that was never stored.
+    UnknownTransaction,
+    /// Dispute on a transaction that is already disputed.
+    AlreadyDisputed,
+    /// Dispute on a transaction that was already charged back.
+    AlreadyChargedBack,
+    /// Dispute on a transaction that was already disputed as many times as the engine allows.
+    DisputeLimitReached,
+    /// Dispute amount larger than what is left to dispute of the referenced transaction.
+    ExceedsDisputableAmount,
+    /// Resolve or Chargeback amount larger than what the open dispute holds.
+    ExceedsHeldAmount,
+    /// Resolve or Chargeback on a transaction that is not disputed.
+    NotDisputed,
+    /// Dispute, Resolve or Chargeback whose client does not own the referenced transaction.
+    ClientMismatch { owner_client_id: u16 },
+    /// Dispute on a withdrawal while the withdrawal dispute policy rejects them.
+    WithdrawalNotDisputable,
+    /// Record with a zero or negative amount.
+    NonPositiveAmount,
+    /// Adjustment with a zero amount.
+    ZeroAmount,
+    /// Amount with more decimal places than its currency has, while the precision policy rejects them.
+    ExcessPrecision,
+    /// Currency code the engine has no minor units for.
+    UnknownCurrency,
+    /// Dispute, Resolve or Chargeback in a different currency than the referenced transaction.
+    CurrencyMismatch,
+    /// Convert without both a currency and a different target currency.
+    InvalidConversion,
+    /// Convert without a timestamp to pick its rate by.
+    MissingTimestamp,
+    /// Convert between currencies that have no rate in effect at its timestamp.
+    UnknownRate,
+    /// Transfer without a counterparty, or to the client itself.
+    InvalidCounterparty,
+    /// Transfer to a client that has no account.
+    UnknownCounterparty,
+    /// Transfer to a locked account.
+    CounterpartyLocked,
+    /// Transfer to a closed account.
+    CounterpartyClosed,
+    /// Capture or Void of a transaction that is not an open authorization.
+    NotAuthorized,
+    /// Capture or Void amount larger than the funds the authorization reserves.
+    ExceedsReservedAmount,
+    /// Dispute on an authorization that was never captured.
+    NotCaptured,
+    /// Refund of a transaction that is not a withdrawal.
+    NotRefundable,
+    /// Refund amount larger than what is left of the withdrawal after earlier refunds, chargebacks and open disputes.
+    ExceedsRefundableAmount,
+    /// Dispute on a withdrawal that was refunded in full.
+    AlreadyRefunded,
+}
+
+impl fmt::Display for RejectionReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectionReason::MissingAmount        => write!(f, "transaction is missing an amount"),
+            RejectionReason::AccountLocked        => write!(f, "account is locked"),
+            RejectionReason::AccountClosed        => write!(f, "account is closed"),
+            RejectionReason::AccountNotLocked     => write!(f, "account is not locked"),
+            RejectionReason::NonZeroBalance       => write!(f, "account still holds funds"),
+            RejectionReason::MissingReason        => write!(f, "adjustment is missing a reason"),
+            RejectionReason::NotAdministrative    => write!(f, "only administrative transactions are allowed in the admin file"),
+            RejectionReason::DuplicateTransaction => write!(f, "transaction ID has already been processed"),
+            RejectionReason::UnknownAccount       => write!(f, "account does not exist"),
+            RejectionReason::InsufficientFunds    => write!(f, "insufficient available funds"),
+            RejectionReason::UnknownTransaction   => write!(f, "referenced transaction does not exist"),
+            RejectionReason::AlreadyDisputed      => write!(f, "transaction is already disputed"),
+            RejectionReason::AlreadyChargedBack   => write!(f, "transaction was already charged back"),
+            RejectionReason::DisputeLimitReached  => write!(f, "transaction cannot be disputed again"),
+            RejectionReason::ExceedsDisputableAmount => write!(f, "amount exceeds the disputable amount of the transaction"),
+            RejectionReason::ExceedsHeldAmount    => write!(f, "amount exceeds the amount held by the dispute"),
+            RejectionReason::NotDisputed          => write!(f, "transaction is not disputed"),
+            RejectionReason::ClientMismatch { owner_client_id } => write!(f, "referenced transaction belongs to client {}", owner_client_id),
+            RejectionReason::WithdrawalNotDisputable => write!(f, "withdrawals cannot be disputed"),
+            RejectionReason::NonPositiveAmount    => write!(f, "amount must be greater than zero"),
+            RejectionReason::ZeroAmount           => write!(f, "amount must not be zero"),
+            RejectionReason::ExcessPrecision      => write!(f, "amount has more decimal places than its currency"),
+            RejectionReason::UnknownCurrency      => write!(f, "currency is not supported"),
+            RejectionReason::CurrencyMismatch     => write!(f, "currency does not match the referenced transaction"),
+            RejectionReason::InvalidConversion    => write!(f, "conversion needs a currency and a different target currency"),
+            RejectionReason::MissingTimestamp     => write!(f, "transaction is missing a timestamp"),
+            RejectionReason::UnknownRate          => write!(f, "no conversion rate is in effect for the currencies"),
+            RejectionReason::InvalidCounterparty  => write!(f, "transfer needs a counterparty other than the client"),
+            RejectionReason::UnknownCounterparty  => write!(f, "counterparty account does not exist"),
+            RejectionReason::CounterpartyLocked   => write!(f, "counterparty account is locked"),
+            RejectionReason::CounterpartyClosed   => write!(f, "counterparty account is closed"),
+            RejectionReason::NotAuthorized        => write!(f, "transaction is not an open authorization"),
+            RejectionReason::ExceedsReservedAmount => write!(f, "amount exceeds the funds reserved by the authorization"),
+            RejectionReason::NotCaptured          => write!(f, "authorization was never captured"),
+            RejectionReason::NotRefundable        => write!(f, "only withdrawals can be refunded"),
+            RejectionReason::ExceedsRefundableAmount => write!(f, "amount exceeds what is left to refund of the withdrawal"),
+            RejectionReason::AlreadyRefunded      => write!(f, "withdrawal was already refunded in full"),
+        }
+    }
+}
+
+impl RejectionReason {
+    /// Stable machine-readable code, used in the rejected-records report.
+    pub fn code(&self) -> &'static str {
+        match self {
+            RejectionReason::MissingAmount          => "missing_amount",
+            RejectionReason::AccountLocked          => "account_locked",
+            RejectionReason::AccountClosed          => "account_closed",
+            RejectionReason::AccountNotLocked       => "account_not_locked",
+            RejectionReason::NonZeroBalance         => "non_zero_balance",
+            RejectionReason::MissingReason          => "missing_reason",
+            RejectionReason::NotAdministrative      => "not_administrative",
+            RejectionReason::DuplicateTransaction   => "duplicate_transaction",
+            RejectionReason::UnknownAccount         => "unknown_account",
+            RejectionReason::InsufficientFunds      => "insufficient_funds",
+            RejectionReason::UnknownTransaction     => "unknown_transaction",
+            RejectionReason::AlreadyDisputed        => "already_disputed",
+            RejectionReason::AlreadyChargedBack     => "already_charged_back",
+            RejectionReason::DisputeLimitReached    => "dispute_limit_reached",
+            RejectionReason::ExceedsDisputableAmount => "exceeds_disputable_amount",
+            RejectionReason::ExceedsHeldAmount      => "exceeds_held_amount",
+            RejectionReason::NotDisputed            => "not_disputed",
+            RejectionReason::ClientMismatch { .. }  => "client_mismatch",
+            RejectionReason::WithdrawalNotDisputable => "withdrawal_not_disputable",
+            RejectionReason::NonPositiveAmount      => "non_positive_amount",
+            RejectionReason::ZeroAmount             => "zero_amount",
+            RejectionReason::ExcessPrecision        => "excess_precision",
+            RejectionReason::UnknownCurrency        => "unknown_currency",
+            RejectionReason::CurrencyMismatch       => "currency_mismatch",
+            RejectionReason::InvalidConversion      => "invalid_conversion",
+            RejectionReason::MissingTimestamp       => "missing_timestamp",
+            RejectionReason::UnknownRate            => "unknown_rate",
+            RejectionReason::InvalidCounterparty    => "invalid_counterparty",
+            RejectionReason::UnknownCounterparty    => "unknown_counterparty",
+            RejectionReason::CounterpartyLocked     => "counterparty_locked",
+            RejectionReason::CounterpartyClosed     => "counterparty_closed",
+            RejectionReason::NotAuthorized          => "not_authorized",
+            RejectionReason::ExceedsReservedAmount  => "exceeds_reserved_amount",
+            RejectionReason::NotCaptured            => "not_captured",
+            RejectionReason::NotRefundable          => "not_refundable",
+            RejectionReason::ExceedsRefundableAmount => "exceeds_refundable_amount",
+            RejectionReason::AlreadyRefunded        => "already_refunded",
+        }
+    }
+}
+
+/// Result of processing a single TransactionRecord.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionOutcome {
+    /// The record changed engine state.
+    Applied,
+    /// The record was skipped and engine state is unchanged.
+    Rejected {
+        client_id: u16,
+        transaction_id: u32,
+        transaction_type: TransactionType,
+        reason: RejectionReason,
+    },
+}
+
+impl TransactionOutcome {
+    /// Builds a Rejected outcome for the given record.
+    pub fn rejected(record: &TransactionRecord, reason: RejectionReason) -> Self {
+        TransactionOutcome::Rejected {
+            client_id: record.client_id,
+            transaction_id: record.transaction_id,
+            transaction_type: record.transaction_type,
+            reason,
+        }
+    }
+
+    pub fn is_applied(&self) -> bool {
+        matches!(self, TransactionOutcome::Applied)
+    }
 }
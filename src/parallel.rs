@@ -0,0 +1,323 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::thread::{self, JoinHandle};
+use log::error;
+use crate::config::EngineConfig;
+use crate::engine::Engine;
+use crate::error::ApplicationError;
+use crate::model::{Account, TransactionOutcome, TransactionRecord, TransactionType};
+use crate::rates::RateTable;
+use crate::storage::TransactionStore;
+
+// Records buffered per shard before the router blocks, keeping memory flat when one shard falls behind.
+const SHARD_QUEUE_DEPTH: usize = 4096;
+
+/// A record that was not applied (or was administrative), returned with the tag it was submitted with.
+pub type ShardResult<Tag> = (Tag, TransactionRecord, Result<TransactionOutcome, ApplicationError>);
+
+/// Transaction store of a single shard.
+/// `foreign` holds IDs stored by other shards, so reusing them is rejected exactly as in a single engine.
+#[derive(Default)]
+struct ShardTransactions {
+    local: HashMap<u32, TransactionRecord>,
+    foreign: HashSet<u32>,
+}
+
+impl TransactionStore for ShardTransactions {
+    fn get_transaction(&self, transaction_id: u32) -> Result<Option<TransactionRecord>, ApplicationError> {
+        self.local.get_transaction(transaction_id)
+    }
+
+    fn contains_transaction(&self, transaction_id: u32) -> Result<bool, ApplicationError> {
+        Ok(self.local.contains_key(&transaction_id) || self.foreign.contains(&transaction_id))
+    }
+
+    fn put_transaction(&mut self, record: TransactionRecord) -> Result<(), ApplicationError> {
+        self.local.put_transaction(record)
+    }
+
+    fn visit_transactions(&self, visit: &mut dyn FnMut(TransactionRecord) -> Result<(), ApplicationError>) -> Result<(), ApplicationError> {
+        self.local.visit_transactions(visit)
+    }
+}
+
+type ShardEngine = Engine<HashMap<u16, Account>, ShardTransactions>;
+
+enum ShardMessage<Tag> {
+    /// A record to process, with the latest timestamp submitted so far, which authorizations expire by.
+    Process { submitted: u64, clock: Option<u64>, tag: Tag, record: TransactionRecord },
+    /// Asks whether the shard has stored the transaction. Answered after every previously sent record is processed.
+    IsStored { transaction_id: u32, reply: Sender<bool> },
+    /// Marks a transaction ID as stored by another shard.
+    MarkForeign { transaction_id: u32 },
+}
+
+/// Processes records on `workers` threads, each owning the accounts of the clients assigned to it by client ID.
+///
+/// Records for a client are always processed in submission order by the same shard, so the result is identical to a
+/// single Engine. The router (the thread calling submit) keeps the two cross-client rules of the engine intact:
+/// * Transaction IDs are unique across all clients. The router remembers which shard last claimed each ID and, when
+///   another shard's client reuses it, asks that shard whether it actually stored it before forwarding the record.
+/// * Dispute, Resolve and Chargeback are sent to the shard holding the referenced transaction, which is where a single
+///   engine would look it up. A mismatched client is rejected without touching that client's account. Capture and Void
+///   are routed the same way, to the shard holding the authorization.
+/// * A Refund claims its own transaction ID on its client's shard, which also holds the refunded withdrawal. When the
+///   withdrawal was claimed by another shard, the refund is sent there instead, where it is rejected as a single engine
+///   would: the withdrawal is either unknown or belongs to another client.
+///
+/// Authorizations expire by record count and by the latest timestamp across all clients. Shards are told the latest
+/// timestamp with every record, and on finish the shards are swept once more, so the same authorizations expire as in a
+/// single engine.
+///
+/// A Transfer between clients of the same shard is processed there like any other record. One between clients of two
+/// shards, or a dispute of one resumed from a snapshot, would touch the accounts of both, so the shards are stopped and
+/// merged back into a single Engine, which processes that record and every record after it on the calling thread.
+pub struct ShardedEngine<Tag> {
+    senders: Vec<SyncSender<ShardMessage<Tag>>>,
+    handles: Vec<JoinHandle<ShardEngine>>,
+    results: Receiver<(u64, ShardResult<Tag>)>,
+    submitted: u64,
+    // Shard that last claimed each transaction ID with a Deposit, Withdrawal, Convert, Authorize, Transfer or Refund. Only that shard can have stored it.
+    claims: HashMap<u32, usize>,
+    // Transfers of the engine the shards were split from whose clients live on two shards. Disputing one touches both.
+    transfers: HashSet<u32>,
+    journal_sequence: u64,
+    // Record sequence of the engine the shards were split from. Shards number records from here in submission order.
+    record_sequence: u64,
+    // Latest timestamp submitted so far
+    clock: Option<u64>,
+    // Shard the last record was sent to
+    last_shard: Option<usize>,
+    config: EngineConfig,
+    rates: RateTable,
+    // Single engine the shards were merged into once a record touched clients of two shards. Processes every record from then on.
+    merged: Option<Engine>,
+    // Results of the shards once merged, and of the merged engine, numbered by submission
+    returned: Vec<(u64, ShardResult<Tag>)>,
+}
+
+impl<Tag: Send + 'static> ShardedEngine<Tag> {
+    /// Splits `engine` (usually empty, or resumed from a snapshot) across `workers` shards and starts them.
+    pub fn new(engine: Engine, workers: usize) -> Self {
+        let workers = workers.max(1);
+        let journal_sequence = engine.journal_sequence();
+        let record_sequence = engine.record_sequence();
+        let clock = engine.clock();
+        let config = engine.config;
+        let rates = engine.rates;
+        let mut shards: Vec<ShardEngine> = (0..workers)
+            .map(|_| {
+                let mut shard = Engine::with_stores(HashMap::new(), ShardTransactions::default());
+                shard.config = config;
+                shard.rates = rates.clone();
+                shard.clock = clock;
+                shard
+            })
+            .collect();
+        let mut claims = HashMap::new();
+        let mut transfers = HashSet::new();
+        for (client_id, account) in engine.accounts {
+            shards[shard_of(client_id, workers)].accounts.insert(client_id, account);
+        }
+        for (transaction_id, record) in engine.transactions {
+            let shard = shard_of(record.client_id, workers);
+            if record.transaction_type == TransactionType::Transfer && record.counterparty.map(|recipient_id| shard_of(recipient_id, workers)) != Some(shard) {
+                transfers.insert(transaction_id);
+            }
+            claims.insert(transaction_id, shard);
+            shards[shard].transactions.local.insert(transaction_id, record);
+        }
+        for (transaction_id, history) in engine.disputes.into_histories() {
+            if let Some(shard) = claims.get(&transaction_id) {
+                shards[*shard].disputes.insert_history(transaction_id, history);
+            }
+        }
+        for (transaction_id, authorization) in engine.authorizations.into_authorizations() {
+            shards[shard_of(authorization.client_id, workers)].authorizations.open(transaction_id, authorization);
+        }
+
+        let (result_sender, results) = mpsc::channel();
+        let mut senders = Vec::with_capacity(workers);
+        let mut handles = Vec::with_capacity(workers);
+        for shard in shards {
+            let (sender, receiver) = mpsc::sync_channel(SHARD_QUEUE_DEPTH);
+            let result_sender = result_sender.clone();
+            senders.push(sender);
+            handles.push(thread::spawn(move || run_shard(shard, record_sequence, receiver, result_sender)));
+        }
+
+        ShardedEngine {
+            senders,
+            handles,
+            results,
+            submitted: 0,
+            claims,
+            transfers,
+            journal_sequence,
+            record_sequence,
+            clock,
+            last_shard: None,
+            config,
+            rates,
+            merged: None,
+            returned: Vec::new(),
+        }
+    }
+
+    /// Routes a record to its shard. Rejected records, errors and applied administrative records are returned by finish, with `tag`.
+    pub fn submit(&mut self, tag: Tag, record: TransactionRecord) -> Result<(), ApplicationError> {
+        if self.merged.is_none() && self.spans_shards(&record) {
+            let engine = self.merge()?;
+            self.merged = Some(engine);
+        }
+        if let Some(engine) = self.merged.as_mut() {
+            self.submitted += 1;
+            let result = engine.process_transaction(record.clone());
+            if is_returned(&record, &result) {
+                self.returned.push((self.submitted, (tag, record, result)));
+            }
+            return Ok(());
+        }
+
+        let workers = self.senders.len();
+        let client_shard = shard_of(record.client_id, workers);
+        let shard = match record.transaction_type {
+            // A transfer that got this far is between clients of the same shard, or is rejected for naming no other client
+            TransactionType::Deposit | TransactionType::Withdrawal | TransactionType::Convert | TransactionType::Authorize | TransactionType::Transfer => {
+                self.claim(record.transaction_id, client_shard)?
+            }
+            TransactionType::Refund => {
+                let original_shard = record.original_transaction_id.and_then(|original_id| self.claims.get(&original_id).copied());
+                match original_shard {
+                    Some(original_shard) if original_shard != client_shard => original_shard,
+                    _ => self.claim(record.transaction_id, client_shard)?,
+                }
+            }
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback | TransactionType::Capture | TransactionType::Void => {
+                self.claims.get(&record.transaction_id).copied().unwrap_or(client_shard)
+            }
+            // Administrative records only touch the client's own account
+            TransactionType::Unlock | TransactionType::Freeze | TransactionType::Close | TransactionType::Adjustment => client_shard,
+        };
+        self.submitted += 1;
+        self.clock = self.clock.max(record.timestamp);
+        self.last_shard = Some(shard);
+        self.send(shard, ShardMessage::Process { submitted: self.submitted, clock: self.clock, tag, record })
+    }
+
+    /// Waits for every shard to drain, then merges them back into a single Engine.
+    /// Returns the engine along with every record that was not applied, and every applied administrative record for the
+    /// audit report, in submission order.
+    pub fn finish(mut self) -> Result<(Engine, Vec<ShardResult<Tag>>), ApplicationError> {
+        let engine = match self.merged.take() {
+            Some(engine) => engine,
+            None => self.merge()?,
+        };
+        self.returned.sort_by_key(|(submitted, _)| *submitted);
+        Ok((engine, self.returned.into_iter().map(|(_, result)| result).collect()))
+    }
+
+    // True for a record that touches the accounts of clients on two shards.
+    fn spans_shards(&self, record: &TransactionRecord) -> bool {
+        let workers = self.senders.len();
+        match record.transaction_type {
+            TransactionType::Transfer => record.counterparty.is_some_and(|recipient_id| shard_of(recipient_id, workers) != shard_of(record.client_id, workers)),
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => self.transfers.contains(&record.transaction_id),
+            _ => false,
+        }
+    }
+
+    // Waits for every shard to drain and stops it, then merges the shards into a single Engine, keeping their results.
+    fn merge(&mut self) -> Result<Engine, ApplicationError> {
+        self.senders.clear();
+        let mut engine = Engine::with_config(self.config);
+        engine.next_sequence = self.journal_sequence;
+        engine.record_sequence = self.record_sequence + self.submitted;
+        engine.clock = self.clock;
+        engine.rates = self.rates.clone();
+        for (index, handle) in std::mem::take(&mut self.handles).into_iter().enumerate() {
+            let mut shard = handle.join().map_err(|_| shard_stopped())?;
+            // A single engine last swept expired authorizations before the last record, so the other shards catch up on that
+            // sweep. The shard that got the last record already did it, and a second sweep would expire what that record authorized.
+            if self.last_shard != Some(index) {
+                shard.record_sequence = engine.record_sequence;
+                shard.clock = engine.clock;
+                shard.expire_authorizations()?;
+            }
+            engine.accounts.extend(shard.accounts);
+            engine.transactions.extend(shard.transactions.local);
+            for (transaction_id, history) in shard.disputes.into_histories() {
+                engine.disputes.insert_history(transaction_id, history);
+            }
+            for (transaction_id, authorization) in shard.authorizations.into_authorizations() {
+                engine.authorizations.open(transaction_id, authorization);
+            }
+        }
+        // Every shard has stopped, so every result it sent is in the channel
+        self.returned.extend(self.results.try_iter());
+        Ok(engine)
+    }
+
+    // Claims the transaction ID for the client's shard, unless another shard stored it, which the client's shard is told
+    // so it rejects the ID as used. Returns the client's shard.
+    fn claim(&mut self, transaction_id: u32, client_shard: usize) -> Result<usize, ApplicationError> {
+        match self.claims.get(&transaction_id).copied() {
+            Some(claim_shard) if claim_shard == client_shard => {}
+            Some(claim_shard) if self.is_stored(claim_shard, transaction_id)? => {
+                self.send(client_shard, ShardMessage::MarkForeign { transaction_id })?;
+            }
+            _ => {
+                self.claims.insert(transaction_id, client_shard);
+            }
+        }
+        Ok(client_shard)
+    }
+
+    fn is_stored(&self, shard: usize, transaction_id: u32) -> Result<bool, ApplicationError> {
+        let (reply, answer) = mpsc::channel();
+        self.send(shard, ShardMessage::IsStored { transaction_id, reply })?;
+        answer.recv().map_err(|_| shard_stopped())
+    }
+
+    fn send(&self, shard: usize, message: ShardMessage<Tag>) -> Result<(), ApplicationError> {
+        self.senders[shard].send(message).map_err(|_| shard_stopped())
+    }
+}
+
+fn shard_of(client_id: u16, workers: usize) -> usize {
+    client_id as usize % workers
+}
+
+// Records that were not applied are returned by finish, as are applied administrative records for the audit report.
+fn is_returned(record: &TransactionRecord, result: &Result<TransactionOutcome, ApplicationError>) -> bool {
+    !matches!(result, Ok(TransactionOutcome::Applied)) || record.transaction_type.is_administrative()
+}
+
+fn shard_stopped() -> ApplicationError {
+    error!("A worker shard stopped unexpectedly.");
+    ApplicationError::ShardStopped
+}
+
+fn run_shard<Tag>(mut engine: ShardEngine, record_sequence: u64, receiver: Receiver<ShardMessage<Tag>>, results: Sender<(u64, ShardResult<Tag>)>) -> ShardEngine {
+    for message in receiver {
+        match message {
+            ShardMessage::Process { submitted, clock, tag, record } => {
+                // Number the record as a single engine would, so dispute history matches a single-threaded run
+                engine.record_sequence = record_sequence + submitted - 1;
+                engine.clock = clock;
+                let result = engine.process_transaction(record.clone());
+                if is_returned(&record, &result) {
+                    // Only fails when the ShardedEngine was dropped without calling finish, so nobody is listening
+                    let _ = results.send((submitted, (tag, record, result)));
+                }
+            }
+            ShardMessage::IsStored { transaction_id, reply } => {
+                let _ = reply.send(engine.transactions.local.contains_key(&transaction_id));
+            }
+            ShardMessage::MarkForeign { transaction_id } => {
+                engine.transactions.foreign.insert(transaction_id);
+            }
+        }
+    }
+    engine
+}
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use csv::{ReaderBuilder, Trim};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use crate::currency;
+use crate::error::ApplicationError;
+
+/// One row of a rate file: `amount` of `from` converts to `amount * rate` of `to`, from `effective_from` on.
+#[derive(Debug, Deserialize)]
+struct RateRow {
+    from: String,
+    to: String,
+    #[serde(with = "rust_decimal::serde::str")]
+    rate: Decimal,
+    effective_from: u64,
+}
+
+/// Conversion rates between pairs of named currencies. Each rate is in effect from its timestamp (seconds since the
+/// Unix epoch) until the next rate of the same pair. Rates only apply in the direction they are listed.
+/// Loaded from a CSV file with a `from,to,rate,effective_from` header.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RateTable {
+    // Rates of each (from, to) pair, sorted by the timestamp they take effect at
+    rates: HashMap<(String, String), Vec<(u64, Decimal)>>,
+}
+
+fn invalid(reason: impl Into<String>) -> ApplicationError {
+    ApplicationError::InvalidRates { reason: reason.into() }
+}
+
+impl RateTable {
+    /// Reads and checks a rate table from CSV. Currency codes are uppercased like those of transactions.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, ApplicationError> {
+        let mut table = RateTable::default();
+        let mut reader = ReaderBuilder::new().trim(Trim::All).from_reader(reader);
+        for (index, row) in reader.deserialize::<RateRow>().enumerate() {
+            // Line numbers count the header
+            let line = index + 2;
+            let row = row.map_err(|e| invalid(format!("line {}: {}", line, e)))?;
+            let (from, to) = (row.from.to_ascii_uppercase(), row.to.to_ascii_uppercase());
+            if let Some(unknown) = [&from, &to].into_iter().find(|currency| currency::minor_units(Some(currency)).is_none()) {
+                return Err(invalid(format!("line {}: currency `{}` is not supported", line, unknown)));
+            }
+            if from == to {
+                return Err(invalid(format!("line {}: rate converts {} into itself", line, from)));
+            }
+            if row.rate <= Decimal::ZERO {
+                return Err(invalid(format!("line {}: rate {} must be greater than zero", line, row.rate)));
+            }
+            if !table.insert(&from, &to, row.effective_from, row.rate) {
+                return Err(invalid(format!("line {}: {} to {} already has a rate effective from {}", line, from, to, row.effective_from)));
+            }
+        }
+        Ok(table)
+    }
+
+    /// Loads a rate table from the CSV file at `path`.
+    pub fn load(path: &Path) -> Result<Self, ApplicationError> {
+        Self::from_reader(File::open(path)?)
+    }
+
+    /// Adds a rate for converting `from` into `to`, effective from `effective_from`.
+    /// Returns false, leaving the table unchanged, when the pair already has a rate effective from that exact timestamp.
+    pub fn insert(&mut self, from: &str, to: &str, effective_from: u64, rate: Decimal) -> bool {
+        let rates = self.rates.entry((from.to_string(), to.to_string())).or_default();
+        match rates.binary_search_by_key(&effective_from, |(effective_from, _)| *effective_from) {
+            Ok(_) => false,
+            Err(index) => {
+                rates.insert(index, (effective_from, rate));
+                true
+            }
+        }
+    }
+
+    /// Rate for converting `from` into `to` at `timestamp`: the latest one effective at or before it.
+    pub fn rate(&self, from: &str, to: &str, timestamp: u64) -> Option<Decimal> {
+        let rates = self.rates.get(&(from.to_string(), to.to_string()))?;
+        let effective = rates.partition_point(|(effective_from, _)| *effective_from <= timestamp);
+        effective.checked_sub(1).map(|index| rates[index].1)
+    }
+}
@@ -0,0 +1,85 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use csv::{StringRecord, Writer};
+use crate::error::ApplicationError;
+use crate::model::TransactionRecord;
+
+/// Reason code used for rows that could not be parsed into a TransactionRecord.
+pub const PARSE_ERROR_CODE: &str = "parse_error";
+
+/// CSV report of every input row that was skipped, so bad partner files can be triaged without reading logs.
+/// Columns are `file,row,raw,reason,message`, where `row` is the line number in the input file named by `file`.
+pub struct RejectsReport<W: Write> {
+    writer: Writer<W>,
+}
+
+impl RejectsReport<File> {
+    /// Creates (or truncates) the report file at `path` and writes the header.
+    pub fn create(path: &Path) -> Result<Self, ApplicationError> {
+        Self::from_writer(File::create(path)?)
+    }
+}
+
+impl<W: Write> RejectsReport<W> {
+    pub fn from_writer(writer: W) -> Result<Self, ApplicationError> {
+        let mut writer = Writer::from_writer(writer);
+        writer.write_record(["file", "row", "raw", "reason", "message"])?;
+        Ok(RejectsReport { writer })
+    }
+
+    /// Appends a skipped row. `raw` is the row as read from the input, if it could be read at all, and is written with its fields
+    /// joined by `delimiter`, the field delimiter of the input it came from.
+    pub fn write(&mut self, file: &str, row: u64, raw: Option<&StringRecord>, delimiter: u8, reason: &str, message: &str) -> Result<(), ApplicationError> {
+        let raw = raw
+            .map(|record| record.iter().collect::<Vec<_>>().join(&char::from(delimiter).to_string()))
+            .unwrap_or_default();
+        self.writer.write_record([file, row.to_string().as_str(), raw.as_str(), reason, message])?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), ApplicationError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// CSV report of every applied administrative record, so manual changes to accounts can be reviewed after the run.
+/// Columns are `file,row,type,client,tx,amount,reason`, where `row` is the line number in the file named by `file`.
+pub struct AuditReport<W: Write> {
+    writer: Writer<W>,
+}
+
+impl AuditReport<File> {
+    /// Creates (or truncates) the report file at `path` and writes the header.
+    pub fn create(path: &Path) -> Result<Self, ApplicationError> {
+        Self::from_writer(File::create(path)?)
+    }
+}
+
+impl<W: Write> AuditReport<W> {
+    pub fn from_writer(writer: W) -> Result<Self, ApplicationError> {
+        let mut writer = Writer::from_writer(writer);
+        writer.write_record(["file", "row", "type", "client", "tx", "amount", "reason"])?;
+        Ok(AuditReport { writer })
+    }
+
+    /// Appends an applied administrative record.
+    pub fn write(&mut self, file: &str, row: u64, record: &TransactionRecord) -> Result<(), ApplicationError> {
+        self.writer.write_record([
+            file.to_string(),
+            row.to_string(),
+            record.transaction_type.to_string().to_lowercase(),
+            record.client_id.to_string(),
+            record.transaction_id.to_string(),
+            record.amount.map(|amount| format!("{:.4}", amount)).unwrap_or_default(),
+            record.reason.clone().unwrap_or_default(),
+        ])?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), ApplicationError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
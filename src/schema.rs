@@ -0,0 +1,65 @@
+use std::io::BufRead;
+use csv::StringRecord;
+use crate::error::ApplicationError;
+
+/// Standard CSV input columns, in order. They are the fields of a TransactionRecord.
+pub const COLUMNS: [&str; 10] = ["type", "client", "tx", "amount", "reason", "currency", "target_currency", "timestamp", "counterparty", "original_tx"];
+
+/// Newest input schema version this build reads.
+pub const LATEST_SCHEMA: u32 = 6;
+
+// Number of leading COLUMNS in each schema version, oldest first. A new version may only append columns,
+// so files of an older version keep parsing by position, with the newer columns taking their defaults.
+const SCHEMA_WIDTHS: [usize; LATEST_SCHEMA as usize] = [
+    4, // 1: type,client,tx,amount
+    5, // 2: adds reason, for administrative records
+    6, // 3: adds currency
+    8, // 4: adds target_currency and timestamp, for conversions
+    9, // 5: adds counterparty, for transfers
+    10, // 6: adds original_tx, for refunds
+];
+
+fn invalid(reason: impl Into<String>) -> ApplicationError {
+    ApplicationError::InvalidSchema { reason: reason.into() }
+}
+
+/// Returns the columns of schema `version`.
+pub fn columns(version: u32) -> Result<&'static [&'static str], ApplicationError> {
+    match version.checked_sub(1).and_then(|index| SCHEMA_WIDTHS.get(index as usize)) {
+        Some(width) => Ok(&COLUMNS[..*width]),
+        None => Err(invalid(format!("unknown schema version {}, this build reads versions 1 to {}", version, LATEST_SCHEMA))),
+    }
+}
+
+/// Parses a schema marker line such as `#schema=2`. Returns None for any other line, comments included.
+pub fn parse_marker(line: &str) -> Option<Result<u32, ApplicationError>> {
+    let (key, value) = line.trim().strip_prefix('#')?.split_once('=')?;
+    if key.trim() != "schema" {
+        return None;
+    }
+    Some(value.trim().parse().map_err(|_| invalid(format!("schema marker `{}` does not name a version", line.trim()))))
+}
+
+/// Reads the first line of a CSV stream when it is a `#` comment, returning it along with the schema version it declares.
+/// The line is returned so the caller can hand it back to the CSV reader, keeping line numbers intact.
+pub fn read_marker<R: BufRead>(reader: &mut R) -> Result<(Vec<u8>, Option<u32>), ApplicationError> {
+    if reader.fill_buf()?.first() != Some(&b'#') {
+        return Ok((Vec::new(), None));
+    }
+    let mut line = Vec::new();
+    reader.read_until(b'\n', &mut line)?;
+    let version = parse_marker(&String::from_utf8_lossy(&line)).transpose()?;
+    if let Some(version) = version {
+        columns(version)?;
+    }
+    Ok((line, version))
+}
+
+/// Checks the header of a file against the schema it declares: it must hold exactly the columns of that version.
+pub fn check_header(version: u32, headers: &StringRecord) -> Result<(), ApplicationError> {
+    let expected = columns(version)?;
+    if !headers.iter().eq(expected.iter().copied()) {
+        return Err(invalid(format!("header `{}` does not match schema {} (`{}`)", headers.iter().collect::<Vec<_>>().join(","), version, expected.join(","))));
+    }
+    Ok(())
+}
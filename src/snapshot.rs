@@ -0,0 +1,184 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use crate::authorization::Authorization;
+use crate::dispute::{Dispute, DisputeState};
+use crate::engine::Engine;
+use crate::error::ApplicationError;
+use crate::format;
+use crate::model::{Account, TransactionRecord};
+use crate::storage::{AccountStore, TransactionStore};
+
+/// Snapshot format version written by this build. Snapshots of older versions load too, so a run can resume from one
+/// saved before an upgrade: fields added since take their defaults. Newer versions are refused.
+pub const SNAPSHOT_VERSION: u32 = 8;
+
+// First version whose disputes carry the amounts they hold. Disputes of older snapshots always covered the whole
+// transaction, and version 1 only listed the open ones, without a history.
+const DISPUTE_AMOUNTS_VERSION: u32 = 3;
+
+/// One line of a snapshot file. Snapshots are JSON Lines so engines larger than memory can be streamed in and out.
+/// A snapshot is a Header, then every Account, Transaction, dispute history and open authorization, then a Trailer with the entry counts
+/// so a truncated file is detected instead of silently loading a partial ledger.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SnapshotEntry {
+    Header {
+        version: u32,
+        journal_sequence: u64,
+        #[serde(default)]
+        record_sequence: u64,
+        clock: Option<u64>,
+    },
+    Account { client_id: u16, account: Account },
+    Transaction(TransactionRecord),
+    Dispute {
+        transaction_id: u32,
+        #[serde(default)]
+        history: Vec<Dispute>,
+    },
+    Authorization { transaction_id: u32, authorization: Authorization },
+    Trailer {
+        accounts: u64,
+        transactions: u64,
+        disputes: u64,
+        #[serde(default)]
+        authorizations: u64,
+    },
+}
+
+fn write_entry<W: Write>(writer: &mut W, entry: &SnapshotEntry) -> Result<(), ApplicationError> {
+    serde_json::to_writer(&mut *writer, entry)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+fn invalid(reason: impl Into<String>) -> ApplicationError {
+    ApplicationError::InvalidSnapshot { reason: reason.into() }
+}
+
+/// Writes the complete state of `engine` (accounts, stored transactions, dispute history and open authorizations) as a snapshot.
+pub fn write_snapshot<A: AccountStore, T: TransactionStore, W: Write>(engine: &Engine<A, T>, mut writer: W) -> Result<(), ApplicationError> {
+    write_entry(&mut writer, &SnapshotEntry::Header {
+        version: SNAPSHOT_VERSION,
+        journal_sequence: engine.journal_sequence(),
+        record_sequence: engine.record_sequence(),
+        clock: engine.clock(),
+    })?;
+
+    let mut accounts: u64 = 0;
+    for client_id in engine.accounts.client_ids()? {
+        if let Some(account) = engine.accounts.get_account(client_id)? {
+            write_entry(&mut writer, &SnapshotEntry::Account { client_id, account })?;
+            accounts += 1;
+        }
+    }
+
+    let mut transactions: u64 = 0;
+    engine.transactions.visit_transactions(&mut |record| {
+        transactions += 1;
+        write_entry(&mut writer, &SnapshotEntry::Transaction(record))
+    })?;
+
+    // Disputes come sorted by transaction ID for a deterministic file
+    let disputes = engine.disputes.transaction_ids();
+    for transaction_id in &disputes {
+        let history = engine.disputes.history(*transaction_id).to_vec();
+        write_entry(&mut writer, &SnapshotEntry::Dispute { transaction_id: *transaction_id, history })?;
+    }
+
+    // Authorizations too, for the same reason
+    let authorizations = engine.authorizations.transaction_ids();
+    for transaction_id in &authorizations {
+        if let Some(authorization) = engine.authorizations.get(*transaction_id).cloned() {
+            write_entry(&mut writer, &SnapshotEntry::Authorization { transaction_id: *transaction_id, authorization })?;
+        }
+    }
+
+    write_entry(&mut writer, &SnapshotEntry::Trailer { accounts, transactions, disputes: disputes.len() as u64, authorizations: authorizations.len() as u64 })?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Loads a snapshot into `engine`, which is expected to be empty.
+pub fn load_snapshot_into<A: AccountStore, T: TransactionStore, R: BufRead>(engine: &mut Engine<A, T>, reader: R) -> Result<(), ApplicationError> {
+    let mut lines = reader.lines();
+    let version = match lines.next().transpose()?.map(|line| serde_json::from_str::<SnapshotEntry>(&line)).transpose()? {
+        Some(SnapshotEntry::Header { version, journal_sequence, record_sequence, clock }) => {
+            if version > SNAPSHOT_VERSION {
+                return Err(ApplicationError::UnsupportedSnapshotVersion { found: version, supported: SNAPSHOT_VERSION });
+            }
+            engine.next_sequence = journal_sequence;
+            engine.record_sequence = record_sequence;
+            engine.clock = clock;
+            version
+        }
+        _ => return Err(invalid("Snapshot does not start with a header")),
+    };
+
+    let (mut accounts, mut transactions, mut disputes, mut authorizations): (u64, u64, u64, u64) = (0, 0, 0, 0);
+    for line in lines {
+        match serde_json::from_str::<SnapshotEntry>(&line?)? {
+            SnapshotEntry::Account { client_id, account } => {
+                engine.accounts.put_account(client_id, account)?;
+                accounts += 1;
+            }
+            SnapshotEntry::Transaction(record) => {
+                engine.transactions.put_transaction(record)?;
+                transactions += 1;
+            }
+            SnapshotEntry::Dispute { transaction_id, mut history } => {
+                if version < DISPUTE_AMOUNTS_VERSION {
+                    history = whole_transaction_disputes(engine, transaction_id, history)?;
+                }
+                engine.disputes.insert_history(transaction_id, history);
+                disputes += 1;
+            }
+            SnapshotEntry::Authorization { transaction_id, authorization } => {
+                engine.authorizations.open(transaction_id, authorization);
+                authorizations += 1;
+            }
+            SnapshotEntry::Trailer { accounts: expected_accounts, transactions: expected_transactions, disputes: expected_disputes, authorizations: expected_authorizations } => {
+                if (accounts, transactions, disputes, authorizations) != (expected_accounts, expected_transactions, expected_disputes, expected_authorizations) {
+                    return Err(invalid("Snapshot entry counts do not match its trailer"));
+                }
+                return Ok(());
+            }
+            SnapshotEntry::Header { .. } => return Err(invalid("Snapshot contains more than one header")),
+        }
+    }
+    Err(invalid("Snapshot is truncated (no trailer)"))
+}
+
+// Fills in the amounts of a dispute history from a snapshot older than DISPUTE_AMOUNTS_VERSION, where every dispute held
+// the whole transaction. An empty history is the single open dispute version 1 listed.
+fn whole_transaction_disputes<A: AccountStore, T: TransactionStore>(engine: &Engine<A, T>, transaction_id: u32, history: Vec<Dispute>) -> Result<Vec<Dispute>, ApplicationError> {
+    let amount = engine.transactions.get_transaction(transaction_id)?
+        .and_then(|record| record.amount)
+        .ok_or_else(|| invalid(format!("Snapshot has a dispute of unknown transaction {}", transaction_id)))?;
+    let history = match history.is_empty() {
+        true => vec![Dispute { state: DisputeState::Open, amount, released: Decimal::ZERO, charged_back: Decimal::ZERO, opened_at: 0, closed_at: None }],
+        false => history,
+    };
+    Ok(history
+        .into_iter()
+        .map(|dispute| Dispute {
+            amount,
+            released: if dispute.state == DisputeState::Resolved { amount } else { Decimal::ZERO },
+            charged_back: if dispute.state == DisputeState::ChargedBack { amount } else { Decimal::ZERO },
+            ..dispute
+        })
+        .collect())
+}
+
+/// Writes a snapshot of `engine` to the file at `path`, replacing it only once the snapshot is complete.
+pub fn save<A: AccountStore, T: TransactionStore>(engine: &Engine<A, T>, path: &Path) -> Result<(), ApplicationError> {
+    format::write_atomically(path, |writer| write_snapshot(engine, writer))
+}
+
+/// Loads the snapshot file at `path` into `engine`. See `load_snapshot_into`.
+pub fn load_into<A: AccountStore, T: TransactionStore>(engine: &mut Engine<A, T>, path: &Path) -> Result<(), ApplicationError> {
+    load_snapshot_into(engine, BufReader::new(File::open(path)?))
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::path::Path;
+use rusqlite::{params, Connection, OptionalExtension};
+use crate::error::ApplicationError;
+use crate::model::{Account, TransactionRecord};
+
+/// Storage for client accounts.
+/// Accounts are loaded and saved by value so implementations are free to keep them outside of memory.
+pub trait AccountStore {
+    fn get_account(&self, client_id: u16) -> Result<Option<Account>, ApplicationError>;
+    fn put_account(&mut self, client_id: u16, account: Account) -> Result<(), ApplicationError>;
+    /// All client IDs with an account, sorted ascending.
+    fn client_ids(&self) -> Result<Vec<u16>, ApplicationError>;
+}
+
+/// Storage for applied transactions, keyed by transaction ID, so disputes can reference them.
+pub trait TransactionStore {
+    fn get_transaction(&self, transaction_id: u32) -> Result<Option<TransactionRecord>, ApplicationError>;
+    fn contains_transaction(&self, transaction_id: u32) -> Result<bool, ApplicationError>;
+    fn put_transaction(&mut self, record: TransactionRecord) -> Result<(), ApplicationError>;
+    /// Calls `visit` with every stored transaction, in ascending transaction ID order.
+    fn visit_transactions(&self, visit: &mut dyn FnMut(TransactionRecord) -> Result<(), ApplicationError>) -> Result<(), ApplicationError>;
+}
+
+// In-memory stores. These are the default for Engine.
+
+impl AccountStore for HashMap<u16, Account> {
+    fn get_account(&self, client_id: u16) -> Result<Option<Account>, ApplicationError> {
+        Ok(self.get(&client_id).cloned())
+    }
+
+    fn put_account(&mut self, client_id: u16, account: Account) -> Result<(), ApplicationError> {
+        self.insert(client_id, account);
+        Ok(())
+    }
+
+    fn client_ids(&self) -> Result<Vec<u16>, ApplicationError> {
+        let mut client_ids: Vec<u16> = self.keys().cloned().collect();
+        client_ids.sort_unstable();
+        Ok(client_ids)
+    }
+}
+
+impl TransactionStore for HashMap<u32, TransactionRecord> {
+    fn get_transaction(&self, transaction_id: u32) -> Result<Option<TransactionRecord>, ApplicationError> {
+        Ok(self.get(&transaction_id).cloned())
+    }
+
+    fn contains_transaction(&self, transaction_id: u32) -> Result<bool, ApplicationError> {
+        Ok(self.contains_key(&transaction_id))
+    }
+
+    fn put_transaction(&mut self, record: TransactionRecord) -> Result<(), ApplicationError> {
+        self.insert(record.transaction_id, record);
+        Ok(())
+    }
+
+    fn visit_transactions(&self, visit: &mut dyn FnMut(TransactionRecord) -> Result<(), ApplicationError>) -> Result<(), ApplicationError> {
+        let mut transaction_ids: Vec<u32> = self.keys().cloned().collect();
+        transaction_ids.sort_unstable();
+        for transaction_id in transaction_ids {
+            visit(self[&transaction_id].clone())?;
+        }
+        Ok(())
+    }
+}
+
+// On-disk stores backed by SQLite, for inputs whose transactions don't fit in memory.
+// Rows hold the JSON encoding of the stored value, so new fields don't require a schema migration.
+// The database is scratch space for a single run: opening a store clears anything left in it.
+
+/// Opens `path` with settings tuned for a single-writer scratch database.
+fn open_scratch_database(path: &Path, table: &str, key: &str) -> Result<Connection, ApplicationError> {
+    let connection = Connection::open(path)?;
+    connection.pragma_update(None, "journal_mode", "WAL")?;
+    connection.pragma_update(None, "synchronous", "OFF")?;
+    connection.busy_timeout(std::time::Duration::from_secs(5))?;
+    connection.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {table} ({key} INTEGER PRIMARY KEY, data TEXT NOT NULL); DELETE FROM {table};"
+    ))?;
+    Ok(connection)
+}
+
+pub struct SqliteAccountStore {
+    connection: Connection,
+}
+
+impl SqliteAccountStore {
+    pub fn open(path: &Path) -> Result<Self, ApplicationError> {
+        Ok(SqliteAccountStore { connection: open_scratch_database(path, "accounts", "client")? })
+    }
+}
+
+impl AccountStore for SqliteAccountStore {
+    fn get_account(&self, client_id: u16) -> Result<Option<Account>, ApplicationError> {
+        let data: Option<String> = self.connection
+            .prepare_cached("SELECT data FROM accounts WHERE client = ?1")?
+            .query_row(params![client_id], |row| row.get(0))
+            .optional()?;
+        Ok(data.map(|data| serde_json::from_str(&data)).transpose()?)
+    }
+
+    fn put_account(&mut self, client_id: u16, account: Account) -> Result<(), ApplicationError> {
+        self.connection
+            .prepare_cached("INSERT OR REPLACE INTO accounts (client, data) VALUES (?1, ?2)")?
+            .execute(params![client_id, serde_json::to_string(&account)?])?;
+        Ok(())
+    }
+
+    fn client_ids(&self) -> Result<Vec<u16>, ApplicationError> {
+        let mut statement = self.connection.prepare_cached("SELECT client FROM accounts ORDER BY client")?;
+        let client_ids = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<u16>, _>>()?;
+        Ok(client_ids)
+    }
+}
+
+pub struct SqliteTransactionStore {
+    connection: Connection,
+}
+
+impl SqliteTransactionStore {
+    pub fn open(path: &Path) -> Result<Self, ApplicationError> {
+        Ok(SqliteTransactionStore { connection: open_scratch_database(path, "transactions", "tx")? })
+    }
+}
+
+impl TransactionStore for SqliteTransactionStore {
+    fn get_transaction(&self, transaction_id: u32) -> Result<Option<TransactionRecord>, ApplicationError> {
+        let data: Option<String> = self.connection
+            .prepare_cached("SELECT data FROM transactions WHERE tx = ?1")?
+            .query_row(params![transaction_id], |row| row.get(0))
+            .optional()?;
+        Ok(data.map(|data| serde_json::from_str(&data)).transpose()?)
+    }
+
+    fn contains_transaction(&self, transaction_id: u32) -> Result<bool, ApplicationError> {
+        let found: Option<u32> = self.connection
+            .prepare_cached("SELECT tx FROM transactions WHERE tx = ?1")?
+            .query_row(params![transaction_id], |row| row.get(0))
+            .optional()?;
+        Ok(found.is_some())
+    }
+
+    fn put_transaction(&mut self, record: TransactionRecord) -> Result<(), ApplicationError> {
+        self.connection
+            .prepare_cached("INSERT OR REPLACE INTO transactions (tx, data) VALUES (?1, ?2)")?
+            .execute(params![record.transaction_id, serde_json::to_string(&record)?])?;
+        Ok(())
+    }
+
+    fn visit_transactions(&self, visit: &mut dyn FnMut(TransactionRecord) -> Result<(), ApplicationError>) -> Result<(), ApplicationError> {
+        let mut statement = self.connection.prepare_cached("SELECT data FROM transactions ORDER BY tx")?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            let data: String = row.get(0)?;
+            visit(serde_json::from_str(&data)?)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use log::warn;
+use rust_decimal::{Decimal, RoundingStrategy};
+use crate::config::{PrecisionPolicy, ValidationConfig};
+use crate::currency;
+use crate::model::{RejectionReason, TransactionRecord, TransactionType};
+
+/// Decimal places amounts in the default currency are specified with, and balances are kept to.
+pub const AMOUNT_DECIMAL_PLACES: u32 = 4;
+
+/// Checks the currency and amount of a freshly deserialized record before it is handed to the engine.
+/// Rounds the amount in place when the policy allows it, or returns why the record must be skipped.
+///
+/// The same rules apply to every transaction type, since Dispute, Resolve and Chargeback rows may name a partial amount,
+/// except that Adjustment amounts may be negative.
+/// Amounts may have as many decimal places as the minor units of their currency. Currency codes are uppercased.
+/// Missing amounts are left to the engine, which owns the rules for each transaction type.
+/// A conversion rate and a refunded amount are only ever set by the engine, so any given in the input are dropped.
+pub fn validate(record: &mut TransactionRecord, config: &ValidationConfig) -> Result<(), RejectionReason> {
+    let transaction_id = record.transaction_id;
+    record.rate = None;
+    record.refunded = None;
+    for currency in [record.currency.as_mut(), record.target_currency.as_mut()].into_iter().flatten() {
+        currency.make_ascii_uppercase();
+    }
+
+    // Warn and skip currencies the engine does not know the minor units of.
+    if let Some(currency) = record.target_currency.as_deref().filter(|currency| currency::minor_units(Some(currency)).is_none()) {
+        warn!("{} transaction {} has unsupported target currency {}. Skipping.", record.transaction_type, transaction_id, currency);
+        return Err(RejectionReason::UnknownCurrency);
+    }
+    let decimal_places = match currency::minor_units(record.currency.as_deref()) {
+        Some(decimal_places) => decimal_places,
+        None => {
+            warn!("{} transaction {} has unsupported currency {}. Skipping.", record.transaction_type, transaction_id, record.currency.as_deref().unwrap_or_default());
+            return Err(RejectionReason::UnknownCurrency);
+        }
+    };
+
+    let amount = match record.amount {
+        Some(amount) => amount,
+        None => return Ok(()),
+    };
+
+    // Warn and skip amounts with more decimal places than their currency has, unless rounding them.
+    let amount = if amount.normalize().scale() > decimal_places {
+        match config.precision {
+            PrecisionPolicy::Reject => {
+                warn!("{} transaction {} amount {} has more than {} decimal places. Skipping.", record.transaction_type, transaction_id, amount, decimal_places);
+                return Err(RejectionReason::ExcessPrecision);
+            }
+            PrecisionPolicy::Round => amount.round_dp_with_strategy(decimal_places, RoundingStrategy::MidpointAwayFromZero),
+        }
+    } else {
+        amount
+    };
+
+    // Adjustments credit or debit by sign, so only a zero amount is meaningless for them.
+    if record.transaction_type == TransactionType::Adjustment {
+        if amount == Decimal::ZERO {
+            warn!("{} transaction {} has a zero amount. Skipping.", record.transaction_type, transaction_id);
+            return Err(RejectionReason::ZeroAmount);
+        }
+        record.amount = Some(amount);
+        return Ok(());
+    }
+
+    // Warn and skip non-positive amounts. A negative deposit would otherwise act as a withdrawal that bypasses the funds check,
+    // and a negative partial dispute would release funds that were never held.
+    // Checked after rounding, so an amount that rounds to zero is skipped as well.
+    if amount <= Decimal::ZERO {
+        warn!("{} transaction {} has non-positive amount {}. Skipping.", record.transaction_type, transaction_id, amount);
+        return Err(RejectionReason::NonPositiveAmount);
+    }
+    record.amount = Some(amount);
+    Ok(())
+}
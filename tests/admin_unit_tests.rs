@@ -0,0 +1,120 @@
+use payments_engine::engine::Engine;
+use payments_engine::model::{RejectionReason, TransactionOutcome, TransactionRecord, TransactionType};
+use rstest::rstest;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+/// Helper to create an administrative TransactionRecord easily
+fn admin(transaction_type: TransactionType, client_id: u16, transaction_id: u32, amount: Option<Decimal>, reason: Option<&str>) -> TransactionRecord {
+    TransactionRecord { reason: reason.map(str::to_string), ..TransactionRecord::new(transaction_type, client_id, transaction_id, amount) }
+}
+
+/// Helper to create a regular TransactionRecord easily
+fn transaction(transaction_type: TransactionType, client_id: u16, transaction_id: u32, amount: Option<Decimal>) -> TransactionRecord {
+    admin(transaction_type, client_id, transaction_id, amount, None)
+}
+
+/// Helper to build the Rejected outcome expected for a skipped record
+fn rejected(transaction_type: TransactionType, client_id: u16, transaction_id: u32, reason: RejectionReason) -> TransactionOutcome {
+    TransactionOutcome::Rejected { client_id, transaction_id, transaction_type, reason }
+}
+
+/// Helper to build an engine where client 1 deposited 10 (tx 1) and 4 (tx 2), and tx 2 was charged back
+fn locked_engine() -> Engine {
+    let mut engine = Engine::new();
+    for record in [
+        transaction(TransactionType::Deposit, 1, 1, Some(dec!(10.0000))),
+        transaction(TransactionType::Deposit, 1, 2, Some(dec!(4.0000))),
+        transaction(TransactionType::Dispute, 1, 2, None),
+        transaction(TransactionType::Chargeback, 1, 2, None),
+    ] {
+        engine.process_transaction(record).unwrap();
+    }
+    assert!(engine.accounts.get(&1).unwrap().locked);
+    engine
+}
+
+#[rstest]
+fn test_unlock_allows_transactions_again() {
+    let mut engine = locked_engine();
+    assert_eq!(engine.process_transaction(admin(TransactionType::Unlock, 1, 100, None, Some("settled"))).unwrap(), TransactionOutcome::Applied);
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Deposit, 1, 3, Some(dec!(1.0000)))).unwrap(), TransactionOutcome::Applied);
+
+    let account = engine.accounts.get(&1).unwrap();
+    assert!(!account.locked);
+    assert_eq!(account.available, dec!(11.0000));
+
+    // Unlocking an unlocked account is rejected
+    assert_eq!(engine.process_transaction(admin(TransactionType::Unlock, 1, 101, None, None)).unwrap(), rejected(TransactionType::Unlock, 1, 101, RejectionReason::AccountNotLocked));
+}
+
+#[rstest]
+fn test_freeze_locks_account() {
+    let mut engine = Engine::new();
+    engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(10.0000)))).unwrap();
+    assert_eq!(engine.process_transaction(admin(TransactionType::Freeze, 1, 100, None, Some("fraud review"))).unwrap(), TransactionOutcome::Applied);
+
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(1.0000)))).unwrap(), rejected(TransactionType::Withdrawal, 1, 2, RejectionReason::AccountLocked));
+    assert_eq!(engine.process_transaction(admin(TransactionType::Freeze, 1, 101, None, None)).unwrap(), rejected(TransactionType::Freeze, 1, 101, RejectionReason::AccountLocked));
+    assert_eq!(engine.process_transaction(admin(TransactionType::Freeze, 2, 102, None, None)).unwrap(), rejected(TransactionType::Freeze, 2, 102, RejectionReason::UnknownAccount));
+}
+
+#[rstest]
+fn test_adjustment_credits_and_debits_available() {
+    let mut engine = locked_engine();
+    // Adjustments are allowed on locked accounts, so support can correct them before unlocking
+    engine.process_transaction(admin(TransactionType::Adjustment, 1, 100, Some(dec!(2.5000)), Some("goodwill credit"))).unwrap();
+    engine.process_transaction(admin(TransactionType::Adjustment, 1, 101, Some(dec!(-0.5000)), Some("fee"))).unwrap();
+
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.available, dec!(12.0000));
+    assert_eq!(account.total, dec!(12.0000));
+    engine.check_invariants().unwrap();
+}
+
+#[rstest]
+#[case(None, Some("reason"), RejectionReason::MissingAmount)]
+#[case(Some(dec!(1.0000)), None, RejectionReason::MissingReason)]
+#[case(Some(dec!(1.0000)), Some("  "), RejectionReason::MissingReason)]
+#[case(Some(dec!(-10.0001)), Some("reason"), RejectionReason::InsufficientFunds)]
+fn test_invalid_adjustment_rejected(#[case] amount: Option<Decimal>, #[case] reason: Option<&str>, #[case] expected: RejectionReason) {
+    let mut engine = locked_engine();
+    let before = engine.accounts.get(&1).cloned();
+    assert_eq!(engine.process_transaction(admin(TransactionType::Adjustment, 1, 100, amount, reason)).unwrap(), rejected(TransactionType::Adjustment, 1, 100, expected));
+    assert_eq!(engine.accounts.get(&1).cloned(), before);
+}
+
+#[rstest]
+fn test_close_requires_zero_balance_and_is_final() {
+    let mut engine = locked_engine();
+    assert_eq!(engine.process_transaction(admin(TransactionType::Close, 1, 100, None, None)).unwrap(), rejected(TransactionType::Close, 1, 100, RejectionReason::NonZeroBalance));
+
+    engine.process_transaction(admin(TransactionType::Adjustment, 1, 101, Some(dec!(-10.0000)), Some("balance paid out"))).unwrap();
+    assert_eq!(engine.process_transaction(admin(TransactionType::Close, 1, 102, None, Some("requested by client"))).unwrap(), TransactionOutcome::Applied);
+
+    let account = engine.accounts.get(&1).unwrap();
+    assert!(account.closed);
+    assert!(account.locked);
+    for record in [
+        admin(TransactionType::Unlock, 1, 103, None, None),
+        admin(TransactionType::Adjustment, 1, 104, Some(dec!(1.0000)), Some("reason")),
+        transaction(TransactionType::Deposit, 1, 105, Some(dec!(1.0000))),
+        transaction(TransactionType::Dispute, 1, 1, None),
+    ] {
+        let transaction_type = record.transaction_type;
+        let transaction_id = record.transaction_id;
+        assert_eq!(engine.process_transaction(record).unwrap(), rejected(transaction_type, 1, transaction_id, RejectionReason::AccountClosed));
+    }
+    engine.check_invariants().unwrap();
+}
+
+#[rstest]
+fn test_administrative_transaction_ids_are_not_stored() {
+    let mut engine = locked_engine();
+    engine.process_transaction(admin(TransactionType::Adjustment, 1, 100, Some(dec!(1.0000)), Some("reason"))).unwrap();
+
+    // The ID only references the request, so it can't be disputed and doesn't block a later deposit
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Dispute, 1, 100, None)).unwrap(), rejected(TransactionType::Dispute, 1, 100, RejectionReason::UnknownTransaction));
+    engine.process_transaction(admin(TransactionType::Unlock, 1, 100, None, None)).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Deposit, 1, 100, Some(dec!(1.0000)))).unwrap(), TransactionOutcome::Applied);
+}
@@ -0,0 +1,184 @@
+use payments_engine::config::{EngineConfig, WithdrawalDisputePolicy};
+use payments_engine::engine::Engine;
+use payments_engine::model::{RejectionReason, TransactionOutcome, TransactionRecord, TransactionType};
+use rstest::rstest;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+/// Helper to create a TransactionRecord easily
+fn transaction(transaction_type: TransactionType, client_id: u16, transaction_id: u32, amount: Option<Decimal>) -> TransactionRecord {
+    TransactionRecord::new(transaction_type, client_id, transaction_id, amount)
+}
+
+/// Helper to build the Rejected outcome expected for a skipped record
+fn rejected(transaction_type: TransactionType, client_id: u16, transaction_id: u32, reason: RejectionReason) -> TransactionOutcome {
+    TransactionOutcome::Rejected { client_id, transaction_id, transaction_type, reason }
+}
+
+/// Helper to build an engine with `config` where client 1 deposited 100 as tx 1 and authorized 40 of it as tx 2
+fn authorized_engine(config: EngineConfig) -> Engine {
+    let mut engine = Engine::with_config(config);
+    engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(100)))).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Authorize, 1, 2, Some(dec!(40)))).unwrap(), TransactionOutcome::Applied);
+    engine
+}
+
+/// Helper to read the (available, reserved, total) default currency balance of client 1
+fn balance(engine: &Engine) -> (Decimal, Decimal, Decimal) {
+    let account = engine.accounts.get(&1).unwrap();
+    (account.available, account.reserved, account.total)
+}
+
+#[rstest]
+fn test_authorize_reserves_available_funds() {
+    let engine = authorized_engine(EngineConfig::default());
+    assert_eq!(balance(&engine), (dec!(60), dec!(40), dec!(100)));
+    assert!(engine.authorizations.is_open(2));
+    engine.check_invariants().unwrap();
+}
+
+#[rstest]
+#[case::missing_amount(transaction(TransactionType::Authorize, 1, 3, None), RejectionReason::MissingAmount)]
+#[case::unknown_account(transaction(TransactionType::Authorize, 2, 3, Some(dec!(1))), RejectionReason::UnknownAccount)]
+#[case::duplicate(transaction(TransactionType::Authorize, 1, 1, Some(dec!(1))), RejectionReason::DuplicateTransaction)]
+// Reserved funds are no longer available
+#[case::insufficient_funds(transaction(TransactionType::Authorize, 1, 3, Some(dec!(60.0001))), RejectionReason::InsufficientFunds)]
+#[case::withdrawal_of_reserved_funds(transaction(TransactionType::Withdrawal, 1, 3, Some(dec!(60.0001))), RejectionReason::InsufficientFunds)]
+#[case::capture_unknown(transaction(TransactionType::Capture, 1, 3, None), RejectionReason::NotAuthorized)]
+#[case::capture_deposit(transaction(TransactionType::Capture, 1, 1, None), RejectionReason::NotAuthorized)]
+#[case::capture_too_much(transaction(TransactionType::Capture, 1, 2, Some(dec!(40.0001))), RejectionReason::ExceedsReservedAmount)]
+#[case::capture_other_client(transaction(TransactionType::Capture, 2, 2, None), RejectionReason::ClientMismatch { owner_client_id: 1 })]
+#[case::capture_other_currency(TransactionRecord { currency: Some("USD".to_string()), ..transaction(TransactionType::Capture, 1, 2, None) }, RejectionReason::CurrencyMismatch)]
+#[case::void_too_much(transaction(TransactionType::Void, 1, 2, Some(dec!(41))), RejectionReason::ExceedsReservedAmount)]
+#[case::void_other_client(transaction(TransactionType::Void, 2, 2, None), RejectionReason::ClientMismatch { owner_client_id: 1 })]
+// Nothing left the account, so there is nothing to dispute
+#[case::dispute_uncaptured(transaction(TransactionType::Dispute, 1, 2, None), RejectionReason::NotCaptured)]
+fn test_invalid_authorization_records_rejected(#[case] record: TransactionRecord, #[case] reason: RejectionReason) {
+    let mut engine = authorized_engine(EngineConfig::default());
+    let before = engine.accounts.clone();
+    let (transaction_type, client_id, transaction_id) = (record.transaction_type, record.client_id, record.transaction_id);
+    assert_eq!(engine.process_transaction(record).unwrap(), rejected(transaction_type, client_id, transaction_id, reason));
+    assert_eq!(engine.accounts, before);
+    assert!(engine.authorizations.is_open(2));
+}
+
+#[rstest]
+#[case::full(None, (dec!(60), dec!(0), dec!(60)))]
+// The rest of a partial capture is released
+#[case::partial(Some(dec!(15)), (dec!(85), dec!(0), dec!(85)))]
+fn test_capture_turns_reserved_funds_into_withdrawal(#[case] amount: Option<Decimal>, #[case] expected: (Decimal, Decimal, Decimal)) {
+    let mut engine = authorized_engine(EngineConfig::default());
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Capture, 1, 2, amount)).unwrap(), TransactionOutcome::Applied);
+    assert_eq!(balance(&engine), expected);
+    assert!(!engine.authorizations.is_open(2));
+
+    let withdrawal = engine.transactions.get(&2).unwrap();
+    assert_eq!((withdrawal.transaction_type, withdrawal.amount), (TransactionType::Withdrawal, Some(dec!(100) - expected.2)));
+    engine.check_invariants().unwrap();
+
+    // An authorization is captured once
+    assert_eq!(
+        engine.process_transaction(transaction(TransactionType::Capture, 1, 2, None)).unwrap(),
+        rejected(TransactionType::Capture, 1, 2, RejectionReason::NotAuthorized)
+    );
+}
+
+#[rstest]
+fn test_captured_authorization_disputed_as_withdrawal() {
+    let config = EngineConfig { withdrawal_disputes: WithdrawalDisputePolicy::CreditPending, ..EngineConfig::default() };
+    let mut engine = authorized_engine(config);
+    engine.process_transaction(transaction(TransactionType::Capture, 1, 2, Some(dec!(30)))).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Dispute, 1, 2, None)).unwrap(), TransactionOutcome::Applied);
+    engine.process_transaction(transaction(TransactionType::Chargeback, 1, 2, None)).unwrap();
+
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!((account.available, account.held, account.total), (dec!(100), dec!(0), dec!(100)));
+    engine.check_invariants().unwrap();
+}
+
+#[rstest]
+fn test_capture_on_locked_account_rejected_but_void_allowed() {
+    let mut engine = authorized_engine(EngineConfig::default());
+    engine.process_transaction(transaction(TransactionType::Freeze, 1, 100, None)).unwrap();
+    assert_eq!(
+        engine.process_transaction(transaction(TransactionType::Capture, 1, 2, None)).unwrap(),
+        rejected(TransactionType::Capture, 1, 2, RejectionReason::AccountLocked)
+    );
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Void, 1, 2, None)).unwrap(), TransactionOutcome::Applied);
+    assert_eq!(balance(&engine), (dec!(100), dec!(0), dec!(100)));
+}
+
+#[rstest]
+fn test_partial_voids_release_until_nothing_is_reserved() {
+    let mut engine = authorized_engine(EngineConfig::default());
+    engine.process_transaction(transaction(TransactionType::Void, 1, 2, Some(dec!(10)))).unwrap();
+    assert_eq!(balance(&engine), (dec!(70), dec!(30), dec!(100)));
+    assert_eq!(engine.authorizations.get(2).unwrap().amount, dec!(30));
+    engine.check_invariants().unwrap();
+
+    engine.process_transaction(transaction(TransactionType::Void, 1, 2, Some(dec!(30)))).unwrap();
+    assert_eq!(balance(&engine), (dec!(100), dec!(0), dec!(100)));
+    assert!(!engine.authorizations.is_open(2));
+    // The transaction ID stays used
+    assert_eq!(
+        engine.process_transaction(transaction(TransactionType::Deposit, 1, 2, Some(dec!(1)))).unwrap(),
+        rejected(TransactionType::Deposit, 1, 2, RejectionReason::DuplicateTransaction)
+    );
+}
+
+#[rstest]
+fn test_close_rejected_while_funds_are_reserved() {
+    let mut engine = authorized_engine(EngineConfig::default());
+    engine.process_transaction(transaction(TransactionType::Withdrawal, 1, 3, Some(dec!(60)))).unwrap();
+    assert_eq!(
+        engine.process_transaction(transaction(TransactionType::Close, 1, 100, None)).unwrap(),
+        rejected(TransactionType::Close, 1, 100, RejectionReason::NonZeroBalance)
+    );
+}
+
+#[rstest]
+fn test_authorization_expires_after_configured_records() {
+    let mut engine = authorized_engine(EngineConfig { authorization_expiry_records: Some(2), ..EngineConfig::default() });
+    // Records of any client count, rejected ones too
+    engine.process_transaction(transaction(TransactionType::Deposit, 2, 3, Some(dec!(1)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Withdrawal, 9, 4, Some(dec!(1)))).unwrap();
+    assert!(engine.authorizations.is_open(2));
+
+    assert_eq!(
+        engine.process_transaction(transaction(TransactionType::Capture, 1, 2, None)).unwrap(),
+        rejected(TransactionType::Capture, 1, 2, RejectionReason::NotAuthorized)
+    );
+    assert_eq!(balance(&engine), (dec!(100), dec!(0), dec!(100)));
+    engine.check_invariants().unwrap();
+}
+
+#[rstest]
+fn test_authorization_expires_by_latest_timestamp() {
+    let mut engine = Engine::with_config(EngineConfig { authorization_expiry_seconds: Some(60), ..EngineConfig::default() });
+    engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(100)))).unwrap();
+    let at = |timestamp: u64, record: TransactionRecord| TransactionRecord { timestamp: Some(timestamp), ..record };
+    engine.process_transaction(at(1_000, transaction(TransactionType::Authorize, 1, 2, Some(dec!(40))))).unwrap();
+    // Without a timestamp an authorization only expires by count
+    engine.process_transaction(transaction(TransactionType::Authorize, 1, 3, Some(dec!(10)))).unwrap();
+
+    engine.process_transaction(at(1_059, transaction(TransactionType::Deposit, 2, 4, Some(dec!(1))))).unwrap();
+    assert!(engine.authorizations.is_open(2));
+    // Timestamps that go back don't turn the clock back
+    engine.process_transaction(at(1_060, transaction(TransactionType::Deposit, 2, 5, Some(dec!(1))))).unwrap();
+    engine.process_transaction(at(5, transaction(TransactionType::Deposit, 2, 6, Some(dec!(1))))).unwrap();
+    assert_eq!(engine.clock(), Some(1_060));
+
+    assert!(!engine.authorizations.is_open(2));
+    assert!(engine.authorizations.is_open(3));
+    assert_eq!(balance(&engine), (dec!(90), dec!(10), dec!(100)));
+    engine.check_invariants().unwrap();
+}
+
+#[rstest]
+fn test_expiry_is_fixed_when_funds_are_reserved() {
+    let mut engine = authorized_engine(EngineConfig { authorization_expiry_records: Some(1), ..EngineConfig::default() });
+    engine.config.authorization_expiry_records = None;
+    engine.process_transaction(transaction(TransactionType::Deposit, 2, 3, Some(dec!(1)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Deposit, 2, 4, Some(dec!(1)))).unwrap();
+    assert!(!engine.authorizations.is_open(2));
+}
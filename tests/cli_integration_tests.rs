@@ -7,7 +7,7 @@ fn test_cli_integration_basic() -> Result<(), Box<dyn std::error::Error>> {
     let mut cmd = Command::cargo_bin("payments_engine")?;
     cmd.arg("tests/fixtures/basic.csv")
        .assert()
-       .success()
+       .code(1)
        .stdout(predicate::str::contains("client,available,held,total,locked"))
        .stdout(predicate::str::contains("1,1.5000,0.0000,1.5000,false"))
        .stdout(predicate::str::contains("2,2.0000,0.0000,2.0000,false"))
@@ -22,7 +22,7 @@ fn test_cli_integration_complex() -> Result<(), Box<dyn std::error::Error>> {
     let mut cmd = Command::cargo_bin("payments_engine")?;
     cmd.arg("tests/fixtures/disputes.csv")
        .assert()
-       .success()
+       .code(1)
        // Header check
        .stdout(predicate::str::contains("client,available,held,total,locked"))
        // Client 0: deposit 1.2345, then dispute -> 1.2345 held
@@ -39,3 +39,707 @@ fn test_cli_integration_complex() -> Result<(), Box<dyn std::error::Error>> {
        .stdout(predicate::str::contains("5,0.5000,0.0000,0.5000,false"));
     Ok(())
 }
+
+#[test]
+fn test_cli_integration_rejects_report() -> Result<(), Box<dyn std::error::Error>> {
+    // Runs the binary with --rejects and checks every skipped row lands in the report with its reason code
+    let rejects_path = std::env::temp_dir().join(format!("payments_engine_rejects_{}.csv", std::process::id()));
+    let mut cmd = Command::cargo_bin("payments_engine")?;
+    cmd.arg("tests/fixtures/rejects.csv")
+       .arg("--rejects")
+       .arg(&rejects_path)
+       .assert()
+       .code(1)
+       .stdout(predicate::str::contains("1,4.0000,0.0000,4.0000,false"));
+
+    let report = std::fs::read_to_string(&rejects_path)?;
+    std::fs::remove_file(&rejects_path)?;
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines[0], "file,row,raw,reason,message");
+    assert_eq!(lines[1], "tests/fixtures/rejects.csv,3,\"withdrawal,1,2,9.0000\",insufficient_funds,insufficient available funds");
+    assert_eq!(lines[2], "tests/fixtures/rejects.csv,4,\"deposit,1,1,1.0000\",duplicate_transaction,transaction ID has already been processed");
+    assert_eq!(lines[3], "tests/fixtures/rejects.csv,5,\"deposit,2,3,\",missing_amount,transaction is missing an amount");
+    assert!(lines[4].starts_with("tests/fixtures/rejects.csv,6,\"bogus,1,4,1.0000\",parse_error,"));
+    assert_eq!(lines[5], "tests/fixtures/rejects.csv,7,\"dispute,2,1,\",client_mismatch,referenced transaction belongs to client 1");
+    assert_eq!(lines.len(), 6);
+    Ok(())
+}
+
+#[test]
+fn test_cli_integration_journal_replay() -> Result<(), Box<dyn std::error::Error>> {
+    // Processes a fixture with --journal, then checks --replay of that journal reproduces the same accounts
+    let journal_path = std::env::temp_dir().join(format!("payments_engine_journal_{}.csv", std::process::id()));
+    let _ = std::fs::remove_file(&journal_path);
+    let processed = Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/disputes.csv")
+        .arg("--journal")
+        .arg(&journal_path)
+        .assert()
+        .code(1);
+    let replayed = Command::cargo_bin("payments_engine")?
+        .arg("--replay")
+        .arg(&journal_path)
+        .assert()
+        .success();
+    std::fs::remove_file(&journal_path)?;
+
+    assert_eq!(processed.get_output().stdout, replayed.get_output().stdout);
+    assert!(String::from_utf8(replayed.get_output().stdout.clone())?.contains("3,0.0000,0.0000,0.0000,true"));
+    Ok(())
+}
+
+#[test]
+fn test_cli_integration_sqlite_store() -> Result<(), Box<dyn std::error::Error>> {
+    // Runs the complex fixture against the SQLite store and checks the output matches the in-memory run
+    let store_path = std::env::temp_dir().join(format!("payments_engine_store_{}.db", std::process::id()));
+    let in_memory = Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/disputes.csv")
+        .assert()
+        .code(1);
+    let on_disk = Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/disputes.csv")
+        .arg("--store")
+        .arg(&store_path)
+        .assert()
+        .code(1);
+    let _ = std::fs::remove_file(&store_path);
+
+    assert_eq!(in_memory.get_output().stdout, on_disk.get_output().stdout);
+    Ok(())
+}
+
+#[test]
+fn test_cli_integration_snapshot_resume() -> Result<(), Box<dyn std::error::Error>> {
+    // Day one saves a snapshot; day two resumes from it and disputes / charges back day one deposits
+    let snapshot_path = std::env::temp_dir().join(format!("payments_engine_snapshot_{}.jsonl", std::process::id()));
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/day1.csv")
+        .arg("--save-snapshot")
+        .arg(&snapshot_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2,1.0000,4.0000,5.0000,false"));
+    let day_two = Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/day2.csv")
+        .arg("--resume-from")
+        .arg(&snapshot_path)
+        .assert();
+    std::fs::remove_file(&snapshot_path)?;
+
+    day_two
+        .code(1)
+        // Client 1: day one deposit disputed on day two; reused tx 1 rejected
+        .stdout(predicate::str::contains("1,0.0000,10.0000,10.0000,false"))
+        // Client 2: day one dispute charged back on day two, so the withdrawal is rejected
+        .stdout(predicate::str::contains("2,1.0000,0.0000,1.0000,true"));
+    Ok(())
+}
+
+#[test]
+fn test_cli_integration_workers_match_single_threaded() -> Result<(), Box<dyn std::error::Error>> {
+    // Runs fixtures with and without --workers and checks accounts and rejects reports are identical
+    for fixture in ["tests/fixtures/basic.csv", "tests/fixtures/disputes.csv", "tests/fixtures/rejects.csv"] {
+        let serial_rejects = std::env::temp_dir().join(format!("payments_engine_serial_rejects_{}.csv", std::process::id()));
+        let sharded_rejects = std::env::temp_dir().join(format!("payments_engine_sharded_rejects_{}.csv", std::process::id()));
+        let serial = Command::cargo_bin("payments_engine")?
+            .arg(fixture)
+            .arg("--rejects")
+            .arg(&serial_rejects)
+            .assert()
+            .code(1);
+        let sharded = Command::cargo_bin("payments_engine")?
+            .arg(fixture)
+            .arg("--rejects")
+            .arg(&sharded_rejects)
+            .arg("--workers")
+            .arg("4")
+            .assert()
+            .code(1);
+
+        assert_eq!(serial.get_output().stdout, sharded.get_output().stdout, "accounts differ for {}", fixture);
+        assert_eq!(std::fs::read_to_string(&serial_rejects)?, std::fs::read_to_string(&sharded_rejects)?, "rejects differ for {}", fixture);
+        std::fs::remove_file(&serial_rejects)?;
+        std::fs::remove_file(&sharded_rejects)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_cli_integration_withdrawal_dispute_policy() -> Result<(), Box<dyn std::error::Error>> {
+    // Disputes on withdrawals are rejected by default
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/withdrawal_disputes.csv")
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("1,6.0000,0.0000,6.0000,false"))
+        .stdout(predicate::str::contains("2,3.0000,0.0000,3.0000,false"));
+
+    // With credit-pending, the disputed withdrawal is held and a chargeback credits it back
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/withdrawal_disputes.csv")
+        .arg("--withdrawal-disputes")
+        .arg("credit-pending")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1,6.0000,4.0000,10.0000,false"))
+        .stdout(predicate::str::contains("2,5.0000,0.0000,5.0000,true"));
+    Ok(())
+}
+
+#[test]
+fn test_cli_integration_partial_disputes() -> Result<(), Box<dyn std::error::Error>> {
+    // Dispute rows with an amount hold, release and charge back only that portion
+    let rejects_path = std::env::temp_dir().join(format!("payments_engine_partial_rejects_{}.csv", std::process::id()));
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/partial_disputes.csv")
+        .arg("--rejects")
+        .arg(&rejects_path)
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("1,8.0000,0.0000,8.0000,true"))
+        .stdout(predicate::str::contains("2,0.0000,5.0000,5.0000,false"));
+
+    let report = std::fs::read_to_string(&rejects_path)?;
+    std::fs::remove_file(&rejects_path)?;
+    let reasons: Vec<&str> = report.lines().skip(1).map(|line| line.split(',').rev().nth(1).unwrap()).collect();
+    assert_eq!(reasons, vec!["exceeds_disputable_amount", "exceeds_held_amount"]);
+    Ok(())
+}
+
+#[test]
+fn test_cli_integration_admin_file_and_audit() -> Result<(), Box<dyn std::error::Error>> {
+    // Administrative records apply after the input, and every applied one lands in the audit report, also with --workers
+    let mut reports = Vec::new();
+    for workers in [None, Some("3")] {
+        let audit_path = std::env::temp_dir().join(format!("payments_engine_audit_{}_{}.csv", workers.is_some(), std::process::id()));
+        let rejects_path = std::env::temp_dir().join(format!("payments_engine_admin_rejects_{}_{}.csv", workers.is_some(), std::process::id()));
+        let mut cmd = Command::cargo_bin("payments_engine")?;
+        cmd.arg("tests/fixtures/admin_input.csv")
+            .arg("--admin")
+            .arg("tests/fixtures/admin.csv")
+            .arg("--audit")
+            .arg(&audit_path)
+            .arg("--rejects")
+            .arg(&rejects_path);
+        if let Some(workers) = workers {
+            cmd.arg("--workers").arg(workers);
+        }
+        cmd.assert()
+            .code(1)
+            // Client 1: 14.0000 - 4.0000 charged back, unlocked, then credited 2.5000; the deposit while locked is skipped
+            .stdout(predicate::str::contains("1,2.5000,0.0000,2.5000,false"))
+            // Client 2: 5.0000 - 1.0000 adjusted, then frozen
+            .stdout(predicate::str::contains("2,4.0000,0.0000,4.0000,true"))
+            // Client 3: paid out and closed
+            .stdout(predicate::str::contains("3,0.0000,0.0000,0.0000,true"));
+
+        let audit = std::fs::read_to_string(&audit_path)?;
+        let rejects = std::fs::read_to_string(&rejects_path)?;
+        std::fs::remove_file(&audit_path)?;
+        std::fs::remove_file(&rejects_path)?;
+        reports.push((audit, rejects));
+    }
+
+    let (audit, rejects) = &reports[0];
+    let audit: Vec<&str> = audit.lines().collect();
+    assert_eq!(audit, vec![
+        "file,row,type,client,tx,amount,reason",
+        "tests/fixtures/admin_input.csv,6,adjustment,2,3,-1.0000,reversed promotional credit",
+        "tests/fixtures/admin.csv,2,unlock,1,100,,chargeback settled with client",
+        "tests/fixtures/admin.csv,4,adjustment,1,102,2.5000,goodwill credit",
+        "tests/fixtures/admin.csv,6,freeze,2,104,,fraud review",
+        "tests/fixtures/admin.csv,7,adjustment,3,105,-1.0000,closing balance paid out",
+        "tests/fixtures/admin.csv,8,close,3,106,,requested by client",
+    ]);
+    let reasons: Vec<&str> = rejects.lines().skip(1).map(|line| line.split(',').rev().nth(1).unwrap()).collect();
+    assert_eq!(reasons, vec!["account_locked", "not_administrative", "missing_reason", "account_closed"]);
+    assert_eq!(reports[0], reports[1]);
+    Ok(())
+}
+
+#[test]
+fn test_cli_integration_jsonl_input_and_json_output() -> Result<(), Box<dyn std::error::Error>> {
+    // JSON Lines input is processed like CSV, with unparseable lines reported by line number
+    let rejects_path = std::env::temp_dir().join(format!("payments_engine_jsonl_rejects_{}.csv", std::process::id()));
+    let output = Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/transactions.jsonl")
+        .arg("--input-format")
+        .arg("jsonl")
+        .arg("--output-format")
+        .arg("json")
+        .arg("--rejects")
+        .arg(&rejects_path)
+        .assert()
+        .code(1)
+        .get_output()
+        .stdout
+        .clone();
+    let accounts: serde_json::Value = serde_json::from_slice(&output)?;
+    assert_eq!(accounts, serde_json::json!([
+        {"client": 1, "available": "7.5000", "held": "0.0000", "total": "7.5000", "locked": false},
+        {"client": 2, "available": "4.5000", "held": "0.0000", "total": "4.5000", "locked": false},
+    ]));
+
+    let mut report = csv::Reader::from_path(&rejects_path)?;
+    let rows: Vec<(String, String)> = report.records()
+        .map(|record| record.map(|record| (record[1].to_string(), record[3].to_string())))
+        .collect::<Result<_, _>>()?;
+    std::fs::remove_file(&rejects_path)?;
+    // Amounts given as JSON numbers are accepted like strings, so only the line that isn't JSON is unreadable
+    assert_eq!(rows, vec![
+        ("6".to_string(), "parse_error".to_string()),
+        ("9".to_string(), "insufficient_funds".to_string()),
+    ]);
+    Ok(())
+}
+
+#[test]
+fn test_cli_integration_multiple_inputs_and_stdin() -> Result<(), Box<dyn std::error::Error>> {
+    // Inputs are processed in order into one engine, so day two can dispute day one's deposits like a resumed run.
+    // Rejects are reported with the file they came from, stdin included.
+    let rejects_path = std::env::temp_dir().join(format!("payments_engine_multi_rejects_{}.csv", std::process::id()));
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/day1.csv")
+        .arg("-")
+        .arg("--rejects")
+        .arg(&rejects_path)
+        .write_stdin(std::fs::read("tests/fixtures/day2.csv")?)
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("1,0.0000,10.0000,10.0000,false"))
+        .stdout(predicate::str::contains("2,1.0000,0.0000,1.0000,true"));
+
+    let report = std::fs::read_to_string(&rejects_path)?;
+    std::fs::remove_file(&rejects_path)?;
+    let rows: Vec<&str> = report.lines().skip(1).map(|line| line.split_once(",\"").unwrap().0).collect();
+    assert_eq!(rows, vec!["<stdin>,4", "<stdin>,5"]);
+
+    // stdin can only be read once
+    Command::cargo_bin("payments_engine")?
+        .arg("-")
+        .arg("--admin")
+        .arg("-")
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("stdin"));
+    Ok(())
+}
+
+#[test]
+fn test_cli_integration_compressed_inputs() -> Result<(), Box<dyn std::error::Error>> {
+    // gzip and zstd input is detected by its magic bytes and decompressed on the fly, from files and from stdin
+    let gzip_path = std::env::temp_dir().join(format!("payments_engine_day1_{}.csv.gz", std::process::id()));
+    let mut encoder = flate2::write::GzEncoder::new(std::fs::File::create(&gzip_path)?, flate2::Compression::default());
+    std::io::Write::write_all(&mut encoder, &std::fs::read("tests/fixtures/day1.csv")?)?;
+    encoder.finish()?;
+    let day_two = zstd::encode_all(std::fs::File::open("tests/fixtures/day2.csv")?, 0)?;
+
+    let assert = Command::cargo_bin("payments_engine")?
+        .arg(&gzip_path)
+        .arg("-")
+        .write_stdin(day_two)
+        .assert();
+    std::fs::remove_file(&gzip_path)?;
+    assert
+        .code(1)
+        .stdout(predicate::str::contains("1,0.0000,10.0000,10.0000,false"))
+        .stdout(predicate::str::contains("2,1.0000,0.0000,1.0000,true"));
+
+    // A truncated archive fails the run instead of being read as bad rows
+    let mut truncated = zstd::encode_all(std::fs::File::open("tests/fixtures/basic.csv")?, 0)?;
+    truncated.truncate(truncated.len() / 2);
+    Command::cargo_bin("payments_engine")?
+        .arg("-")
+        .write_stdin(truncated)
+        .assert()
+        .code(3);
+    Ok(())
+}
+
+#[test]
+fn test_cli_integration_output_file() -> Result<(), Box<dyn std::error::Error>> {
+    // With --output, accounts go to the file and nothing but logs goes to stdout and stderr
+    let output_path = std::env::temp_dir().join(format!("payments_engine_output_{}.csv", std::process::id()));
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/basic.csv")
+        .arg("--output")
+        .arg(&output_path)
+        .assert()
+        .code(1)
+        .stdout(predicate::str::is_empty());
+    let accounts = std::fs::read_to_string(&output_path)?;
+    assert!(accounts.starts_with("client,available,held,total,locked\n"));
+    assert!(accounts.contains("3,2.1000,5.4321,7.5321,false"));
+
+    // A run that fails leaves the previous output in place
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/missing.csv")
+        .arg("--output")
+        .arg(&output_path)
+        .assert()
+        .code(3);
+    assert_eq!(std::fs::read_to_string(&output_path)?, accounts);
+    std::fs::remove_file(&output_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_cli_integration_amount_validation() -> Result<(), Box<dyn std::error::Error>> {
+    // Non-positive amounts, including on disputes, and over-precise amounts are rejected with distinct reasons
+    let rejects_path = std::env::temp_dir().join(format!("payments_engine_amount_rejects_{}.csv", std::process::id()));
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/amounts.csv")
+        .arg("--rejects")
+        .arg(&rejects_path)
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("1,10.0000,0.0000,10.0000,false"));
+
+    let report = std::fs::read_to_string(&rejects_path)?;
+    std::fs::remove_file(&rejects_path)?;
+    let reasons: Vec<&str> = report.lines().skip(1).map(|line| line.split(',').rev().nth(1).unwrap()).collect();
+    assert_eq!(reasons, vec!["non_positive_amount", "non_positive_amount", "excess_precision", "non_positive_amount", "excess_precision"]);
+
+    // With --precision round, over-precise amounts are rounded to four places instead
+    // 10.0000 + 1.1235 - 1.0001 = 10.1234
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/amounts.csv")
+        .arg("--precision")
+        .arg("round")
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("1,10.1234,0.0000,10.1234,false"));
+    Ok(())
+}
+
+#[test]
+fn test_cli_integration_balance_overflow() -> Result<(), Box<dyn std::error::Error>> {
+    // An overflowing deposit is reported for its row while the rest of the run carries on, exiting with the ledger error status
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/overflow.csv")
+        .assert()
+        .code(4)
+        .stdout(predicate::str::contains("1,7922816251426433759354395.0335,0.0000,7922816251426433759354395.0335,false"))
+        .stdout(predicate::str::contains("2,1.0000,0.0000,1.0000,false"))
+        .stderr(predicate::str::contains("Error at tests/fixtures/overflow.csv:3"))
+        .stderr(predicate::str::contains("Balance Overflow"));
+    Ok(())
+}
+
+#[test]
+fn test_cli_integration_verify() -> Result<(), Box<dyn std::error::Error>> {
+    // A consistent ledger passes verification and is written as usual
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/disputes.csv")
+        .arg("--verify")
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("3,0.0000,0.0000,0.0000,true"))
+        .stderr(predicate::str::contains("Verified ledger invariants"));
+
+    // Client 1 in this snapshot holds funds without any open dispute
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/single_deposit.csv")
+        .arg("--resume-from")
+        .arg("tests/fixtures/inconsistent.snapshot")
+        .arg("--verify")
+        .assert()
+        .code(4)
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("Ledger invariant violated for client 1"));
+    Ok(())
+}
+
+#[test]
+fn test_cli_integration_strict_mode() -> Result<(), Box<dyn std::error::Error>> {
+    // A clean input passes --strict as usual
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/day1.csv")
+        .arg("--strict")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2,1.0000,4.0000,5.0000,false"));
+
+    // The first rejection stops the run without writing accounts, and is the only row in the rejects report
+    let rejects_path = std::env::temp_dir().join(format!("payments_engine_strict_rejects_{}.csv", std::process::id()));
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/rejects.csv")
+        .arg("--strict")
+        .arg("--rejects")
+        .arg(&rejects_path)
+        .assert()
+        .code(1)
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("Row 3 of tests/fixtures/rejects.csv was rejected: insufficient available funds"));
+    let report = std::fs::read_to_string(&rejects_path)?;
+    std::fs::remove_file(&rejects_path)?;
+    assert_eq!(report.lines().count(), 2);
+
+    // A ledger error stops the run with its own status
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/overflow.csv")
+        .arg("--strict")
+        .assert()
+        .code(4)
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("Balance Overflow"));
+
+    // Shard results only arrive at the end of the run, so --strict can't be combined with --workers
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/day1.csv")
+        .arg("--strict")
+        .arg("--workers")
+        .arg("2")
+        .assert()
+        .code(2);
+    Ok(())
+}
+
+#[test]
+fn test_cli_integration_input_mapping() -> Result<(), Box<dyn std::error::Error>> {
+    // A partner file with its own delimiter, quoting, column and type names is read through a mapping file
+    let audit_path = std::env::temp_dir().join(format!("payments_engine_mapping_audit_{}.csv", std::process::id()));
+    let rejects_path = std::env::temp_dir().join(format!("payments_engine_mapping_rejects_{}.csv", std::process::id()));
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/partner.csv")
+        .arg("--mapping")
+        .arg("tests/fixtures/partner_mapping.toml")
+        .arg("--audit")
+        .arg(&audit_path)
+        .arg("--rejects")
+        .arg(&rejects_path)
+        .assert()
+        .code(1)
+        // Client 1: 10.5000 - 2.5000, with the deposit disputed and resolved
+        .stdout(predicate::str::contains("1,8.0000,0.0000,8.0000,false"))
+        // Client 2: 3.0000 + 1.0000 adjusted
+        .stdout(predicate::str::contains("2,4.0000,0.0000,4.0000,false"));
+
+    let audit = std::fs::read_to_string(&audit_path)?;
+    let rejects: Vec<(String, String)> = csv::Reader::from_path(&rejects_path)?
+        .records()
+        .map(|record| record.map(|record| (record[2].to_string(), record[3].to_string())))
+        .collect::<Result<_, _>>()?;
+    std::fs::remove_file(&audit_path)?;
+    std::fs::remove_file(&rejects_path)?;
+    assert!(audit.contains("tests/fixtures/partner.csv,9,adjustment,2,6,1.0000,goodwill; approved"));
+    // Raw rows keep the partner's delimiter, so they match the source file
+    assert_eq!(rejects, vec![
+        ("WDL;2;4;9.0;".to_string(), "insufficient_funds".to_string()),
+        ("XFER;1;5;1.0;".to_string(), "parse_error".to_string()),
+    ]);
+
+    // Without the mapping, no row of the partner file can be read
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/partner.csv")
+        .assert()
+        .code(1)
+        .stdout(predicate::str::diff("client,available,held,total,locked\n"));
+
+    // A mapped header missing from the file fails the run up front
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/basic.csv")
+        .arg("--mapping")
+        .arg("tests/fixtures/partner_mapping.toml")
+        .assert()
+        .code(3)
+        .stderr(predicate::str::contains("Invalid Input Mapping"));
+    Ok(())
+}
+
+#[test]
+fn test_cli_integration_schema_versions_and_headerless_input() -> Result<(), Box<dyn std::error::Error>> {
+    // A file declaring schema 1 parses with defaults for later columns, and rows wider than the schema are rejected
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/schema_v1.csv")
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("1,3.5000,0.0000,3.5000,false"))
+        .stderr(predicate::str::contains("Error at tests/fixtures/schema_v1.csv:5: CSV parse error: row has 5 fields, but its schema has 4 columns"));
+
+    // Headerless rows take the columns of their declared schema by position
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/headerless.csv")
+        .arg("--no-headers")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1,0.0000,0.0000,0.0000,false"))
+        .stdout(predicate::str::contains("2,4.0000,0.0000,4.0000,false"));
+
+    // Read with a header, its first row does not match the declared schema
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/headerless.csv")
+        .assert()
+        .code(3)
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("Invalid Input Schema"));
+    Ok(())
+}
+
+#[test]
+fn test_cli_integration_currencies() -> Result<(), Box<dyn std::error::Error>> {
+    // Each currency is its own balance row, and rows breaking currency rules are rejected with their reason
+    let rejects_path = std::env::temp_dir().join(format!("payments_engine_currencies_rejects_{}.csv", std::process::id()));
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/currencies.csv")
+        .arg("--rejects")
+        .arg(&rejects_path)
+        .assert()
+        .code(1)
+        .stdout(predicate::eq(concat!(
+            "client,available,held,total,locked,currency\n",
+            "1,7.5000,0.0000,7.5000,false,\n",
+            "1,0.0000,5000.0000,5000.0000,false,JPY\n",
+            "1,79.5000,0.0000,79.5000,false,USD\n",
+            "2,1.5000,0.0000,1.5000,false,EUR\n",
+        )));
+
+    let reasons: Vec<String> = csv::Reader::from_path(&rejects_path)?.records().map(|record| record.map(|record| record[3].to_string())).collect::<Result<_, _>>()?;
+    std::fs::remove_file(&rejects_path)?;
+    assert_eq!(reasons, ["currency_mismatch", "excess_precision", "unknown_currency", "insufficient_funds"]);
+    Ok(())
+}
+
+#[test]
+fn test_cli_integration_conversions() -> Result<(), Box<dyn std::error::Error>> {
+    // Conversions apply the rate in effect at their timestamp, and a chargeback reverses one at its original rate
+    let rejects_path = std::env::temp_dir().join(format!("payments_engine_conversions_rejects_{}.csv", std::process::id()));
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/conversions.csv")
+        .arg("--rates")
+        .arg("tests/fixtures/rates.csv")
+        .arg("--rejects")
+        .arg(&rejects_path)
+        .assert()
+        .code(1)
+        .stdout(predicate::eq(concat!(
+            "client,available,held,total,locked,currency\n",
+            "1,18.2000,0.0000,18.2000,true,EUR\n",
+            "1,0.0000,0.0000,0.0000,true,JPY\n",
+            "1,80.0000,0.0000,80.0000,true,USD\n",
+        )));
+
+    let reasons: Vec<String> = csv::Reader::from_path(&rejects_path)?.records().map(|record| record.map(|record| record[3].to_string())).collect::<Result<_, _>>()?;
+    std::fs::remove_file(&rejects_path)?;
+    assert_eq!(reasons, ["unknown_rate", "missing_timestamp"]);
+
+    // Without a rate table every conversion is rejected, and a broken one fails the run up front
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/conversions.csv")
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("1,100.0000,0.0000,100.0000,false,USD"));
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/conversions.csv")
+        .arg("--rates")
+        .arg("tests/fixtures/conversions.csv")
+        .assert()
+        .code(3)
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("Invalid Rate Table"));
+    Ok(())
+}
+
+#[test]
+fn test_cli_integration_transfers() -> Result<(), Box<dyn std::error::Error>> {
+    // A charged back transfer pays the sender back and locks the recipient, which then takes no more transfers
+    let rejects_path = std::env::temp_dir().join(format!("payments_engine_transfers_rejects_{}.csv", std::process::id()));
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/transfers.csv")
+        .arg("--rejects")
+        .arg(&rejects_path)
+        .assert()
+        .code(1)
+        .stdout(predicate::eq(concat!(
+            "client,available,held,total,locked\n",
+            "1,50.0000,0.0000,50.0000,false\n",
+            "2,5.0000,0.0000,5.0000,true\n",
+        )));
+
+    let reasons: Vec<String> = csv::Reader::from_path(&rejects_path)?.records().map(|record| record.map(|record| record[3].to_string())).collect::<Result<_, _>>()?;
+    std::fs::remove_file(&rejects_path)?;
+    assert_eq!(reasons, ["insufficient_funds", "unknown_counterparty", "counterparty_locked"]);
+
+    // Worker shards keep clients apart, so the first transfer between two of them merges the shards into one engine
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/transfers.csv")
+        .arg("--workers")
+        .arg("2")
+        .assert()
+        .code(1)
+        .stdout(predicate::eq(concat!(
+            "client,available,held,total,locked\n",
+            "1,50.0000,0.0000,50.0000,false\n",
+            "2,5.0000,0.0000,5.0000,true\n",
+        )));
+    Ok(())
+}
+
+#[test]
+fn test_cli_integration_authorizations() -> Result<(), Box<dyn std::error::Error>> {
+    // Tx 4 expires after three more records and tx 6 sixty seconds after it was authorized, so only tx 8 is still reserved
+    let rejects_path = std::env::temp_dir().join(format!("payments_engine_authorizations_rejects_{}.csv", std::process::id()));
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/authorizations.csv")
+        .arg("--authorization-expiry-records")
+        .arg("3")
+        .arg("--authorization-expiry-seconds")
+        .arg("60")
+        .arg("--rejects")
+        .arg(&rejects_path)
+        .assert()
+        .code(1)
+        .stdout(predicate::eq(concat!(
+            "client,available,held,total,locked,reserved\n",
+            "1,70.0000,0.0000,76.0000,false,6.0000\n",
+            "2,50.0000,0.0000,50.0000,false,0.0000\n",
+        )));
+
+    let reasons: Vec<String> = csv::Reader::from_path(&rejects_path)?.records().map(|record| record.map(|record| record[3].to_string())).collect::<Result<_, _>>()?;
+    std::fs::remove_file(&rejects_path)?;
+    assert_eq!(reasons, ["insufficient_funds", "not_authorized"]);
+
+    // Without expiry tx 4 is still open to be captured, and tx 6 stays reserved
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/authorizations.csv")
+        .assert()
+        .code(1)
+        .stdout(predicate::eq(concat!(
+            "client,available,held,total,locked,reserved\n",
+            "1,70.0000,0.0000,76.0000,false,6.0000\n",
+            "2,25.0000,0.0000,35.0000,false,10.0000\n",
+        )));
+    Ok(())
+}
+
+#[test]
+fn test_cli_integration_refunds() -> Result<(), Box<dyn std::error::Error>> {
+    // Tx 2 is refunded in full by tx 4 and 8, and the charged back refund takes its funds back and locks the account
+    let expected = concat!(
+        "client,available,held,total,locked\n",
+        "1,75.0000,0.0000,75.0000,true\n",
+        "2,10.0000,0.0000,10.0000,false\n",
+    );
+    let rejects_path = std::env::temp_dir().join(format!("payments_engine_refunds_rejects_{}.csv", std::process::id()));
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/refunds.csv")
+        .arg("--rejects")
+        .arg(&rejects_path)
+        .assert()
+        .code(1)
+        .stdout(predicate::eq(expected));
+
+    let reasons: Vec<String> = csv::Reader::from_path(&rejects_path)?.records().map(|record| record.map(|record| record[3].to_string())).collect::<Result<_, _>>()?;
+    std::fs::remove_file(&rejects_path)?;
+    assert_eq!(reasons, ["exceeds_refundable_amount", "client_mismatch", "not_refundable"]);
+
+    // A refund only touches its own client, so worker shards give the same result
+    Command::cargo_bin("payments_engine")?
+        .arg("tests/fixtures/refunds.csv")
+        .arg("--workers")
+        .arg("2")
+        .assert()
+        .code(1)
+        .stdout(predicate::eq(expected));
+    Ok(())
+}
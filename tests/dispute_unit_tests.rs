@@ -0,0 +1,174 @@
+use payments_engine::config::EngineConfig;
+use payments_engine::dispute::{Dispute, DisputeState};
+use payments_engine::engine::Engine;
+use payments_engine::model::{RejectionReason, TransactionOutcome, TransactionRecord, TransactionType};
+use payments_engine::snapshot;
+use rstest::rstest;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+/// Helper to create a TransactionRecord easily
+fn transaction(transaction_type: TransactionType, client_id: u16, transaction_id: u32, amount: Option<Decimal>) -> TransactionRecord {
+    TransactionRecord::new(transaction_type, client_id, transaction_id, amount)
+}
+
+/// Helper to build the Rejected outcome expected for a skipped record
+fn rejected(transaction_type: TransactionType, client_id: u16, transaction_id: u32, reason: RejectionReason) -> TransactionOutcome {
+    TransactionOutcome::Rejected { client_id, transaction_id, transaction_type, reason }
+}
+
+/// Helper to build an engine allowing `max_disputes` disputes per transaction, with a deposit of 5 as tx 1 (record 1)
+fn engine_with_deposit(max_disputes: Option<u32>) -> Engine {
+    let mut engine = Engine::with_config(EngineConfig { max_disputes, ..EngineConfig::default() });
+    engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(5.0000)))).unwrap();
+    engine
+}
+
+/// Helper to build the Dispute expected right after opening it
+fn open_dispute(amount: Decimal, opened_at: u64) -> Dispute {
+    Dispute { state: DisputeState::Open, amount, released: Decimal::ZERO, charged_back: Decimal::ZERO, opened_at, closed_at: None }
+}
+
+#[rstest]
+fn test_history_records_every_transition() {
+    let mut engine = engine_with_deposit(None);
+    assert!(engine.dispute_history(1).is_empty());
+
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap();
+    assert_eq!(engine.dispute_history(1), [open_dispute(dec!(5.0000), 2)]);
+    assert!(engine.disputes.is_open(1));
+
+    // Rejected records still advance the record sequence
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 99, None)).unwrap();
+    engine.process_transaction(transaction(TransactionType::Resolve, 1, 1, None)).unwrap();
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap();
+    engine.process_transaction(transaction(TransactionType::Chargeback, 1, 1, None)).unwrap();
+
+    assert_eq!(engine.dispute_history(1), [
+        Dispute { state: DisputeState::Resolved, released: dec!(5.0000), closed_at: Some(4), ..open_dispute(dec!(5.0000), 2) },
+        Dispute { state: DisputeState::ChargedBack, charged_back: dec!(5.0000), closed_at: Some(6), ..open_dispute(dec!(5.0000), 5) },
+    ]);
+    assert!(!engine.disputes.is_open(1));
+    assert_eq!(engine.record_sequence(), 6);
+}
+
+#[rstest]
+fn test_charged_back_transaction_cannot_be_disputed_again() {
+    let mut engine = engine_with_deposit(None);
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap();
+    engine.process_transaction(transaction(TransactionType::Chargeback, 1, 1, None)).unwrap();
+
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap(), rejected(TransactionType::Dispute, 1, 1, RejectionReason::AlreadyChargedBack));
+    assert_eq!(engine.dispute_history(1).len(), 1);
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.held, dec!(0.0000));
+    assert_eq!(account.total, dec!(0.0000));
+}
+
+#[rstest]
+#[case(None, 5)]
+#[case(Some(1), 1)]
+#[case(Some(3), 3)]
+fn test_max_disputes_limits_redisputes(#[case] max_disputes: Option<u32>, #[case] expected_disputes: usize) {
+    let mut engine = engine_with_deposit(max_disputes);
+    for _ in 0..5 {
+        let outcome = engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap();
+        if !outcome.is_applied() {
+            assert_eq!(outcome, rejected(TransactionType::Dispute, 1, 1, RejectionReason::DisputeLimitReached));
+            continue;
+        }
+        engine.process_transaction(transaction(TransactionType::Resolve, 1, 1, None)).unwrap();
+    }
+
+    assert_eq!(engine.dispute_history(1).len(), expected_disputes);
+    assert!(engine.dispute_history(1).iter().all(|dispute| dispute.state == DisputeState::Resolved));
+    assert_eq!(engine.accounts.get(&1).unwrap().available, dec!(5.0000));
+}
+
+#[rstest]
+fn test_snapshot_keeps_dispute_history() {
+    let mut engine = engine_with_deposit(Some(2));
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap();
+    engine.process_transaction(transaction(TransactionType::Resolve, 1, 1, None)).unwrap();
+
+    let mut buffer = Vec::new();
+    snapshot::write_snapshot(&engine, &mut buffer).unwrap();
+    let mut restored = Engine::with_config(engine.config);
+    snapshot::load_snapshot_into(&mut restored, buffer.as_slice()).unwrap();
+
+    assert_eq!(restored.disputes, engine.disputes);
+    assert_eq!(restored.record_sequence(), 3);
+    // The limit still counts the dispute from before the snapshot
+    restored.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap();
+    assert_eq!(restored.process_transaction(transaction(TransactionType::Resolve, 1, 1, None)).unwrap(), TransactionOutcome::Applied);
+    assert_eq!(restored.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap(), rejected(TransactionType::Dispute, 1, 1, RejectionReason::DisputeLimitReached));
+    assert_eq!(restored.dispute_history(1)[1], Dispute { state: DisputeState::Resolved, released: dec!(5.0000), closed_at: Some(5), ..open_dispute(dec!(5.0000), 4) });
+}
+
+#[rstest]
+fn test_partial_dispute_holds_only_its_amount() {
+    let mut engine = engine_with_deposit(None);
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, Some(dec!(2.0000)))).unwrap();
+
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.available, dec!(3.0000));
+    assert_eq!(account.held, dec!(2.0000));
+    assert_eq!(account.total, dec!(5.0000));
+    assert_eq!(engine.dispute_history(1), [open_dispute(dec!(2.0000), 2)]);
+    engine.check_invariants().unwrap();
+}
+
+#[rstest]
+fn test_partial_resolve_keeps_dispute_open_until_nothing_is_held() {
+    let mut engine = engine_with_deposit(None);
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, Some(dec!(4.0000)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Resolve, 1, 1, Some(dec!(1.5000)))).unwrap();
+
+    assert!(engine.disputes.is_open(1));
+    assert_eq!(engine.dispute_history(1)[0].held(), dec!(2.5000));
+    assert_eq!(engine.accounts.get(&1).unwrap().held, dec!(2.5000));
+
+    // A Resolve without an amount releases whatever is still held
+    engine.process_transaction(transaction(TransactionType::Resolve, 1, 1, None)).unwrap();
+    assert_eq!(engine.dispute_history(1), [Dispute { state: DisputeState::Resolved, released: dec!(4.0000), closed_at: Some(4), ..open_dispute(dec!(4.0000), 2) }]);
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.available, dec!(5.0000));
+    assert_eq!(account.held, dec!(0.0000));
+}
+
+#[rstest]
+fn test_partial_chargeback_leaves_the_rest_disputable() {
+    let mut engine = engine_with_deposit(None);
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, Some(dec!(3.0000)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Chargeback, 1, 1, Some(dec!(2.0000)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Resolve, 1, 1, None)).unwrap();
+
+    assert_eq!(engine.dispute_history(1), [
+        Dispute { state: DisputeState::ChargedBack, released: dec!(1.0000), charged_back: dec!(2.0000), closed_at: Some(4), ..open_dispute(dec!(3.0000), 2) },
+    ]);
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.available, dec!(3.0000));
+    assert_eq!(account.total, dec!(3.0000));
+    assert!(account.locked);
+
+    // Only the 3.0000 that was not charged back can be disputed again
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, Some(dec!(3.0001)))).unwrap(), rejected(TransactionType::Dispute, 1, 1, RejectionReason::ExceedsDisputableAmount));
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap();
+    engine.process_transaction(transaction(TransactionType::Chargeback, 1, 1, None)).unwrap();
+    assert_eq!(engine.disputes.charged_back(1), dec!(5.0000));
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, Some(dec!(1.0000)))).unwrap(), rejected(TransactionType::Dispute, 1, 1, RejectionReason::AlreadyChargedBack));
+    assert_eq!(engine.accounts.get(&1).unwrap().total, dec!(0.0000));
+    engine.check_invariants().unwrap();
+}
+
+#[rstest]
+#[case(TransactionType::Resolve)]
+#[case(TransactionType::Chargeback)]
+fn test_amount_exceeding_held_amount_rejected(#[case] transaction_type: TransactionType) {
+    let mut engine = engine_with_deposit(None);
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, Some(dec!(2.0000)))).unwrap();
+
+    assert_eq!(engine.process_transaction(transaction(transaction_type, 1, 1, Some(dec!(2.0001)))).unwrap(), rejected(transaction_type, 1, 1, RejectionReason::ExceedsHeldAmount));
+    assert_eq!(engine.dispute_history(1), [open_dispute(dec!(2.0000), 2)]);
+    assert_eq!(engine.accounts.get(&1).unwrap().held, dec!(2.0000));
+}
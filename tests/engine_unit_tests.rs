@@ -1,19 +1,26 @@
-use payments_engine::engine::Engine;
-use payments_engine::model::{TransactionRecord, TransactionType};
+use payments_engine::config::{EngineConfig, WithdrawalDisputePolicy};
+use payments_engine::engine::{Engine, MAX_BALANCE};
+use payments_engine::error::ApplicationError;
+use payments_engine::model::{RejectionReason, TransactionOutcome, TransactionRecord, TransactionType};
 use rstest::rstest;
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
 
 /// Helper to create a TransactionRecord easily
 fn transaction(transaction_type: TransactionType, client_id: u16, transaction_id: u32, amount: Option<Decimal>) -> TransactionRecord {
-    TransactionRecord { transaction_type: transaction_type, client_id, transaction_id: transaction_id, amount }
+    TransactionRecord::new(transaction_type, client_id, transaction_id, amount)
+}
+
+/// Helper to build the Rejected outcome expected for a skipped record
+fn rejected(transaction_type: TransactionType, client_id: u16, transaction_id: u32, reason: RejectionReason) -> TransactionOutcome {
+    TransactionOutcome::Rejected { client_id, transaction_id, transaction_type, reason }
 }
 
 #[rstest]
 fn test_deposit_then_dispute_moves_to_held() {
     let mut engine = Engine::new();
-    engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(10.0000)))).unwrap();
-    engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(10.0000)))).unwrap(), TransactionOutcome::Applied);
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap(), TransactionOutcome::Applied);
 
     let account = engine.accounts.get(&1).unwrap();
     assert_eq!(account.available, dec!(0.0000));
@@ -24,7 +31,7 @@ fn test_deposit_then_dispute_moves_to_held() {
 #[rstest]
 fn test_dispute_on_nonexistent_tx_is_ignored() {
     let mut engine = Engine::new();
-    engine.process_transaction(transaction(TransactionType::Dispute, 1, 99, None)).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Dispute, 1, 99, None)).unwrap(), rejected(TransactionType::Dispute, 1, 99, RejectionReason::UnknownTransaction));
     assert!(engine.accounts.is_empty());
 }
 
@@ -45,7 +52,7 @@ fn test_resolve_moves_from_held_to_available() {
 fn test_resolve_on_non_disputed_tx_is_ignored() {
     let mut engine = Engine::new();
     engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(3.0000)))).unwrap();
-    engine.process_transaction(transaction(TransactionType::Resolve, 1, 1, None)).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Resolve, 1, 1, None)).unwrap(), rejected(TransactionType::Resolve, 1, 1, RejectionReason::NotDisputed));
 
     let account = engine.accounts.get(&1).unwrap();
     assert_eq!(account.available, dec!(3.0000));
@@ -70,7 +77,7 @@ fn test_chargeback_locks_and_subtracts() {
 fn test_chargeback_on_non_disputed_tx_is_ignored() {
     let mut engine = Engine::new();
     engine.process_transaction(transaction(TransactionType::Deposit, 2, 5, Some(dec!(4.0000)))).unwrap();
-    engine.process_transaction(transaction(TransactionType::Chargeback, 2, 5, None)).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Chargeback, 2, 5, None)).unwrap(), rejected(TransactionType::Chargeback, 2, 5, RejectionReason::NotDisputed));
 
     let account = engine.accounts.get(&2).unwrap();
     assert!(!account.locked);
@@ -96,8 +103,8 @@ fn test_multiple_operations_sequence() {
 fn test_failed_withdrawal_then_dispute_ignored() {
     let mut engine = Engine::new();
     engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(5.5432)))).unwrap();
-    engine.process_transaction(transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(10.0000)))).unwrap();
-    engine.process_transaction(transaction(TransactionType::Dispute, 1, 2, None)).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(10.0000)))).unwrap(), rejected(TransactionType::Withdrawal, 1, 2, RejectionReason::InsufficientFunds));
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Dispute, 1, 2, None)).unwrap(), rejected(TransactionType::Dispute, 1, 2, RejectionReason::UnknownTransaction));
 
     let account = engine.accounts.get(&1).unwrap();
     // Excess withdrawal ignored, so original available remains
@@ -110,7 +117,7 @@ fn test_double_dispute_ignored() {
     let mut engine = Engine::new();
     engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(3.0000)))).unwrap();
     engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap();
-    engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap(), rejected(TransactionType::Dispute, 1, 1, RejectionReason::AlreadyDisputed));
 
     let account = engine.accounts.get(&1).unwrap();
     assert_eq!(account.available, dec!(0.0000));
@@ -123,7 +130,7 @@ fn test_double_resolve_ignored() {
     engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(4.0000)))).unwrap();
     engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap();
     engine.process_transaction(transaction(TransactionType::Resolve, 1, 1, None)).unwrap();
-    engine.process_transaction(transaction(TransactionType::Resolve, 1, 1, None)).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Resolve, 1, 1, None)).unwrap(), rejected(TransactionType::Resolve, 1, 1, RejectionReason::NotDisputed));
 
     let account = engine.accounts.get(&1).unwrap();
     assert_eq!(account.available, dec!(4.0000));
@@ -181,7 +188,7 @@ fn test_deposit_on_locked_account_is_ignored() {
     engine.process_transaction(transaction(TransactionType::Chargeback, 1, 1, None)).unwrap();
 
     // Attempt deposit after lock
-    engine.process_transaction(transaction(TransactionType::Deposit, 1, 2, Some(dec!(3.0000)))).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Deposit, 1, 2, Some(dec!(3.0000)))).unwrap(), rejected(TransactionType::Deposit, 1, 2, RejectionReason::AccountLocked));
     let acct = engine.accounts.get(&1).unwrap();
     assert!(acct.locked);
     assert_eq!(acct.available, dec!(0.0000));
@@ -191,7 +198,7 @@ fn test_deposit_on_locked_account_is_ignored() {
 #[rstest]
 fn test_deposit_missing_amount_ignored() {
     let mut engine = Engine::new();
-    engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, None)).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, None)).unwrap(), rejected(TransactionType::Deposit, 1, 1, RejectionReason::MissingAmount));
     assert!(engine.accounts.is_empty(), "Account created on missing-amount deposit");
 }
 
@@ -200,7 +207,7 @@ fn test_withdrawal_missing_amount_ignored() {
     let mut engine = Engine::new();
     // seed with initial deposit
     engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(5.0000)))).unwrap();
-    engine.process_transaction(transaction(TransactionType::Withdrawal, 1, 2, None)).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Withdrawal, 1, 2, None)).unwrap(), rejected(TransactionType::Withdrawal, 1, 2, RejectionReason::MissingAmount));
     let acct = engine.accounts.get(&1).unwrap();
     assert_eq!(acct.available, dec!(5.0000));
     assert_eq!(acct.total, dec!(5.0000));
@@ -210,7 +217,7 @@ fn test_withdrawal_missing_amount_ignored() {
 fn test_duplicate_deposit_ignored() {
     let mut engine = Engine::new();
     engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(3.0000)))).unwrap();
-    engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(3.0000)))).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(3.0000)))).unwrap(), rejected(TransactionType::Deposit, 1, 1, RejectionReason::DuplicateTransaction));
     let acct = engine.accounts.get(&1).unwrap();
     assert_eq!(acct.available, dec!(3.0000));
     assert_eq!(acct.total, dec!(3.0000));
@@ -221,7 +228,7 @@ fn test_duplicate_withdrawal_ignored() {
     let mut engine = Engine::new();
     engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(5.0000)))).unwrap();
     engine.process_transaction(transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(2.0000)))).unwrap();
-    engine.process_transaction(transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(2.0000)))).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(2.0000)))).unwrap(), rejected(TransactionType::Withdrawal, 1, 2, RejectionReason::DuplicateTransaction));
     let acct = engine.accounts.get(&1).unwrap();
     assert_eq!(acct.available, dec!(3.0000));
     assert_eq!(acct.total, dec!(3.0000));
@@ -232,7 +239,7 @@ fn test_dispute_mismatched_client_ignored() {
     let mut engine = Engine::new();
     engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(4.0000)))).unwrap();
     // dispute by wrong client
-    engine.process_transaction(transaction(TransactionType::Dispute, 2, 1, None)).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Dispute, 2, 1, None)).unwrap(), rejected(TransactionType::Dispute, 2, 1, RejectionReason::ClientMismatch { owner_client_id: 1 }));
     let acct = engine.accounts.get(&1).unwrap();
     assert_eq!(acct.available, dec!(4.0000));
     assert_eq!(acct.held, dec!(0.0000));
@@ -244,7 +251,7 @@ fn test_resolve_mismatched_client_ignored() {
     engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(4.0000)))).unwrap();
     engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap();
     // resolve by wrong client
-    engine.process_transaction(transaction(TransactionType::Resolve, 2, 1, None)).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Resolve, 2, 1, None)).unwrap(), rejected(TransactionType::Resolve, 2, 1, RejectionReason::ClientMismatch { owner_client_id: 1 }));
     let acct = engine.accounts.get(&1).unwrap();
     assert_eq!(acct.held, dec!(4.0000));
     assert_eq!(acct.available, dec!(0.0000));
@@ -256,7 +263,7 @@ fn test_chargeback_mismatched_client_ignored() {
     engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(4.0000)))).unwrap();
     engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap();
     // chargeback by wrong client
-    engine.process_transaction(transaction(TransactionType::Chargeback, 2, 1, None)).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Chargeback, 2, 1, None)).unwrap(), rejected(TransactionType::Chargeback, 2, 1, RejectionReason::ClientMismatch { owner_client_id: 1 }));
     let acct = engine.accounts.get(&1).unwrap();
     assert_eq!(acct.held, dec!(4.0000));
     assert_eq!(acct.total, dec!(4.0000));
@@ -272,7 +279,7 @@ fn test_withdrawal_on_locked_account_is_ignored() {
     engine.process_transaction(transaction(TransactionType::Chargeback, 1, 1, None)).unwrap();
 
     // Attempt withdrawal after lock
-    engine.process_transaction(transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(1.0000)))).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(1.0000)))).unwrap(), rejected(TransactionType::Withdrawal, 1, 2, RejectionReason::AccountLocked));
     let acct = engine.accounts.get(&1).unwrap();
     assert!(acct.locked);
     assert_eq!(acct.available, dec!(0.0000));
@@ -313,7 +320,7 @@ fn test_duplicate_chargeback_ignored() {
     engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap();
     engine.process_transaction(transaction(TransactionType::Chargeback, 1, 1, None)).unwrap();
     // Second chargeback should be skipped
-    engine.process_transaction(transaction(TransactionType::Chargeback, 1, 1, None)).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Chargeback, 1, 1, None)).unwrap(), rejected(TransactionType::Chargeback, 1, 1, RejectionReason::NotDisputed));
 
     let acct = engine.accounts.get(&1).unwrap();
     assert!(acct.locked);
@@ -328,7 +335,7 @@ fn test_withdrawal_reusing_deposit_transaction_id_is_ignored() {
     // Initial deposit with tx=1
     engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(5.0000)))).unwrap();
     // Attempt withdrawal using same tx id=1
-    engine.process_transaction(transaction(TransactionType::Withdrawal, 1, 1, Some(dec!(2.0000)))).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Withdrawal, 1, 1, Some(dec!(2.0000)))).unwrap(), rejected(TransactionType::Withdrawal, 1, 1, RejectionReason::DuplicateTransaction));
     // Ensure deposit untouched and withdrawal not applied
     let acct = engine.accounts.get(&1).unwrap();
     assert_eq!(acct.available, dec!(5.0000));
@@ -343,7 +350,7 @@ fn test_deposit_reusing_withdrawal_transaction_id_is_ignored() {
     // Attempt withdrawal using unique tx id=2
     engine.process_transaction(transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(2.0000)))).unwrap();
     // Attempt withdrawal using same tx id=2
-    engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(2.0000)))).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(2.0000)))).unwrap(), rejected(TransactionType::Deposit, 1, 1, RejectionReason::DuplicateTransaction));
     // Ensure deposit untouched and withdrawal not applied
     let acct = engine.accounts.get(&1).unwrap();
     assert_eq!(acct.available, dec!(3.0000));
@@ -362,3 +369,621 @@ fn test_deposit_varied_decimal_precision() {
     assert_eq!(acct.available, dec!(1.2346));
     assert_eq!(acct.total, dec!(1.2346));
 }
+
+/// Helper to build an engine with a deposit of 10 and a withdrawal (tx 2) of 4 for client 1
+fn engine_with_withdrawal(policy: WithdrawalDisputePolicy) -> Engine {
+    let mut engine = Engine::with_config(EngineConfig { withdrawal_disputes: policy, ..EngineConfig::default() });
+    engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(10.0000)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(4.0000)))).unwrap();
+    engine
+}
+
+#[rstest]
+fn test_withdrawal_dispute_rejected_by_default() {
+    let mut engine = Engine::new();
+    engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(10.0000)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(4.0000)))).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Dispute, 1, 2, None)).unwrap(), rejected(TransactionType::Dispute, 1, 2, RejectionReason::WithdrawalNotDisputable));
+}
+
+#[rstest]
+#[case(TransactionType::Dispute, RejectionReason::WithdrawalNotDisputable)]
+#[case(TransactionType::Resolve, RejectionReason::NotDisputed)]
+#[case(TransactionType::Chargeback, RejectionReason::NotDisputed)]
+fn test_reject_policy_leaves_withdrawal_untouched(#[case] transaction_type: TransactionType, #[case] reason: RejectionReason) {
+    let mut engine = engine_with_withdrawal(WithdrawalDisputePolicy::Reject);
+    assert_eq!(engine.process_transaction(transaction(transaction_type, 1, 2, None)).unwrap(), rejected(transaction_type, 1, 2, reason));
+
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.available, dec!(6.0000));
+    assert_eq!(account.held, dec!(0.0000));
+    assert_eq!(account.total, dec!(6.0000));
+    assert!(!account.locked);
+    assert!(engine.disputes.open_transaction_ids().is_empty());
+}
+
+#[rstest]
+fn test_credit_pending_dispute_holds_withdrawn_amount() {
+    let mut engine = engine_with_withdrawal(WithdrawalDisputePolicy::CreditPending);
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Dispute, 1, 2, None)).unwrap(), TransactionOutcome::Applied);
+
+    // Available is not debited a second time
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.available, dec!(6.0000));
+    assert_eq!(account.held, dec!(4.0000));
+    assert_eq!(account.total, dec!(10.0000));
+    assert!(engine.disputes.is_open(2));
+}
+
+#[rstest]
+fn test_credit_pending_resolve_drops_pending_credit() {
+    let mut engine = engine_with_withdrawal(WithdrawalDisputePolicy::CreditPending);
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 2, None)).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Resolve, 1, 2, None)).unwrap(), TransactionOutcome::Applied);
+
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.available, dec!(6.0000));
+    assert_eq!(account.held, dec!(0.0000));
+    assert_eq!(account.total, dec!(6.0000));
+    assert!(!account.locked);
+    assert!(engine.disputes.open_transaction_ids().is_empty());
+}
+
+#[rstest]
+fn test_credit_pending_chargeback_credits_client_and_locks() {
+    let mut engine = engine_with_withdrawal(WithdrawalDisputePolicy::CreditPending);
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 2, None)).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Chargeback, 1, 2, None)).unwrap(), TransactionOutcome::Applied);
+
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.available, dec!(10.0000));
+    assert_eq!(account.held, dec!(0.0000));
+    assert_eq!(account.total, dec!(10.0000));
+    assert!(account.locked);
+    assert!(engine.disputes.open_transaction_ids().is_empty());
+}
+
+#[rstest]
+fn test_credit_pending_policy_keeps_deposit_disputes_unchanged() {
+    let mut engine = engine_with_withdrawal(WithdrawalDisputePolicy::CreditPending);
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap();
+
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.available, dec!(-4.0000));
+    assert_eq!(account.held, dec!(10.0000));
+    assert_eq!(account.total, dec!(6.0000));
+}
+
+#[rstest]
+fn test_deposit_overflow_is_error_and_leaves_account_untouched() {
+    let mut engine = Engine::new();
+    engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(MAX_BALANCE))).unwrap();
+    let result = engine.process_transaction(transaction(TransactionType::Deposit, 1, 2, Some(dec!(0.0001))));
+    assert!(matches!(result, Err(ApplicationError::BalanceOverflow { client_id: 1, transaction_id: 2 })));
+
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.available, MAX_BALANCE);
+    assert_eq!(account.total, MAX_BALANCE);
+    // The failed deposit is not stored, so it can't be disputed
+    assert!(!engine.transactions.contains_key(&2));
+}
+
+#[rstest]
+fn test_dispute_overflow_is_error_and_leaves_account_untouched() {
+    let mut engine = Engine::new();
+    engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(MAX_BALANCE))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Withdrawal, 1, 2, Some(MAX_BALANCE))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Deposit, 1, 3, Some(MAX_BALANCE))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap();
+    // Holding another MAX_BALANCE would overflow held
+    let before = engine.accounts.get(&1).unwrap().clone();
+    let result = engine.process_transaction(transaction(TransactionType::Dispute, 1, 3, None));
+    assert!(matches!(result, Err(ApplicationError::BalanceOverflow { client_id: 1, transaction_id: 3 })));
+    assert_eq!(engine.accounts.get(&1).unwrap(), &before);
+    assert!(!engine.disputes.is_open(3));
+}
+
+#[rstest]
+#[case(WithdrawalDisputePolicy::Reject)]
+#[case(WithdrawalDisputePolicy::CreditPending)]
+fn test_fuzz_near_max_amounts_never_panic(#[case] policy: WithdrawalDisputePolicy) {
+    // Deterministic pseudo-random records with amounts close to MAX_BALANCE, so most balance mutations overflow
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut next = move |bound: u64| {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (state >> 33) % bound
+    };
+    let mut engine = Engine::with_config(EngineConfig { withdrawal_disputes: policy, ..EngineConfig::default() });
+    for _ in 0..5_000 {
+        let transaction_type = match next(5) {
+            0 => TransactionType::Deposit,
+            1 => TransactionType::Withdrawal,
+            2 => TransactionType::Dispute,
+            3 => TransactionType::Resolve,
+            _ => TransactionType::Chargeback,
+        };
+        let amount = match (transaction_type, next(3)) {
+            (TransactionType::Deposit | TransactionType::Withdrawal, 0) => Some(MAX_BALANCE - Decimal::from(next(1_000))),
+            (TransactionType::Deposit | TransactionType::Withdrawal, 1) => Some(MAX_BALANCE / dec!(2) + Decimal::from(next(1_000))),
+            (TransactionType::Deposit | TransactionType::Withdrawal, _) => Some(Decimal::new(next(1_000_000) as i64 + 1, 4)),
+            _ => None,
+        };
+        let record = transaction(transaction_type, next(3) as u16, next(200) as u32, amount);
+        let client_id = record.client_id;
+        let before = engine.accounts.get(&client_id).cloned();
+
+        match engine.process_transaction(record) {
+            Ok(_) => {}
+            Err(ApplicationError::BalanceOverflow { .. }) => assert_eq!(engine.accounts.get(&client_id).cloned(), before),
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+        for account in engine.accounts.values() {
+            assert_eq!(account.available + account.held, account.total);
+        }
+    }
+}
+
+#[rstest]
+fn test_deposit_beyond_four_decimal_range_is_overflow() {
+    let mut engine = Engine::new();
+    // Decimal::MAX itself fits in a Decimal, but not with four exact decimal places
+    let result = engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(Decimal::MAX)));
+    assert!(matches!(result, Err(ApplicationError::BalanceOverflow { client_id: 1, transaction_id: 1 })));
+    assert!(engine.accounts.is_empty());
+}
+
+/// Helper to create a TransactionRecord in a named currency
+fn in_currency(transaction_type: TransactionType, client_id: u16, transaction_id: u32, amount: Option<Decimal>, currency: &str) -> TransactionRecord {
+    TransactionRecord { currency: Some(currency.to_string()), ..transaction(transaction_type, client_id, transaction_id, amount) }
+}
+
+#[rstest]
+fn test_currencies_are_kept_in_separate_balances() {
+    let mut engine = Engine::new();
+    engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(2.0000)))).unwrap();
+    engine.process_transaction(in_currency(TransactionType::Deposit, 1, 2, Some(dec!(10.00)), "USD")).unwrap();
+    engine.process_transaction(in_currency(TransactionType::Deposit, 1, 3, Some(dec!(500)), "JPY")).unwrap();
+    engine.process_transaction(in_currency(TransactionType::Withdrawal, 1, 4, Some(dec!(3.50)), "USD")).unwrap();
+
+    // Funds in other currencies don't cover a withdrawal
+    assert_eq!(
+        engine.process_transaction(in_currency(TransactionType::Withdrawal, 1, 5, Some(dec!(7.00)), "USD")).unwrap(),
+        rejected(TransactionType::Withdrawal, 1, 5, RejectionReason::InsufficientFunds)
+    );
+
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.available, dec!(2.0000));
+    assert_eq!(account.balance(Some("USD")).available, dec!(6.50));
+    assert_eq!(account.balance(Some("JPY")).total, dec!(500));
+    assert_eq!(account.balance(Some("EUR")).total, dec!(0));
+    engine.check_invariants().unwrap();
+}
+
+#[rstest]
+#[case(TransactionType::Dispute)]
+#[case(TransactionType::Resolve)]
+#[case(TransactionType::Chargeback)]
+fn test_dispute_rows_in_another_currency_rejected(#[case] transaction_type: TransactionType) {
+    let mut engine = Engine::new();
+    engine.process_transaction(in_currency(TransactionType::Deposit, 1, 1, Some(dec!(10.00)), "USD")).unwrap();
+    if transaction_type != TransactionType::Dispute {
+        engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap();
+    }
+    assert_eq!(
+        engine.process_transaction(in_currency(transaction_type, 1, 1, None, "EUR")).unwrap(),
+        rejected(transaction_type, 1, 1, RejectionReason::CurrencyMismatch)
+    );
+    // Naming the default currency's transaction with a code is a mismatch too
+    engine.process_transaction(transaction(TransactionType::Deposit, 1, 2, Some(dec!(1.0000)))).unwrap();
+    assert_eq!(
+        engine.process_transaction(in_currency(TransactionType::Dispute, 1, 2, None, "USD")).unwrap(),
+        rejected(TransactionType::Dispute, 1, 2, RejectionReason::CurrencyMismatch)
+    );
+}
+
+#[rstest]
+fn test_dispute_holds_funds_in_the_transaction_currency() {
+    let mut engine = Engine::new();
+    engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(5.0000)))).unwrap();
+    engine.process_transaction(in_currency(TransactionType::Deposit, 1, 2, Some(dec!(10.00)), "USD")).unwrap();
+    // Rows without a currency take the disputed transaction's
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Dispute, 1, 2, Some(dec!(4.00)))).unwrap(), TransactionOutcome::Applied);
+    assert_eq!(engine.process_transaction(in_currency(TransactionType::Chargeback, 1, 2, None, "USD")).unwrap(), TransactionOutcome::Applied);
+
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.balance(None).total, dec!(5.0000));
+    assert_eq!(account.balance(Some("USD")).available, dec!(6.00));
+    assert_eq!(account.balance(Some("USD")).held, dec!(0));
+    assert_eq!(account.balance(Some("USD")).total, dec!(6.00));
+    assert!(account.locked);
+    engine.check_invariants().unwrap();
+}
+
+#[rstest]
+fn test_close_requires_every_currency_empty() {
+    let mut engine = Engine::new();
+    engine.process_transaction(in_currency(TransactionType::Deposit, 1, 1, Some(dec!(10.00)), "USD")).unwrap();
+    assert_eq!(
+        engine.process_transaction(transaction(TransactionType::Close, 1, 100, None)).unwrap(),
+        rejected(TransactionType::Close, 1, 100, RejectionReason::NonZeroBalance)
+    );
+    engine.process_transaction(in_currency(TransactionType::Withdrawal, 1, 2, Some(dec!(10.00)), "USD")).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Close, 1, 100, None)).unwrap(), TransactionOutcome::Applied);
+}
+
+/// Helper to create a Convert record from `from` into `to` at `timestamp`
+fn convert(client_id: u16, transaction_id: u32, amount: Decimal, from: &str, to: &str, timestamp: Option<u64>) -> TransactionRecord {
+    TransactionRecord { target_currency: Some(to.to_string()), timestamp, ..in_currency(TransactionType::Convert, client_id, transaction_id, Some(amount), from) }
+}
+
+/// Helper to build an engine with USD to EUR rates changing at 100, and USD to JPY at 150.123, for client 1 holding 100 USD
+fn converting_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.rates.insert("USD", "EUR", 0, dec!(0.9));
+    engine.rates.insert("USD", "EUR", 100, dec!(0.8));
+    engine.rates.insert("USD", "JPY", 0, dec!(150.123));
+    engine.process_transaction(in_currency(TransactionType::Deposit, 1, 1, Some(dec!(100.00)), "USD")).unwrap();
+    engine
+}
+
+#[rstest]
+#[case(99, dec!(9.00))]
+#[case(100, dec!(8.00))]
+fn test_convert_applies_rate_in_effect_at_timestamp(#[case] timestamp: u64, #[case] expected: Decimal) {
+    let mut engine = converting_engine();
+    assert_eq!(engine.process_transaction(convert(1, 2, dec!(10.00), "USD", "EUR", Some(timestamp))).unwrap(), TransactionOutcome::Applied);
+
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.balance(Some("USD")).available, dec!(90.00));
+    assert_eq!(account.balance(Some("EUR")).available, expected);
+    // The applied rate is stored with the transaction
+    assert_eq!(engine.transactions.get(&2).unwrap().rate, Some(expected / dec!(10)));
+    engine.check_invariants().unwrap();
+}
+
+#[rstest]
+#[case::missing_currency(TransactionRecord { currency: None, ..convert(1, 2, dec!(1.00), "USD", "EUR", Some(0)) }, RejectionReason::InvalidConversion)]
+#[case::missing_target(TransactionRecord { target_currency: None, ..convert(1, 2, dec!(1.00), "USD", "EUR", Some(0)) }, RejectionReason::InvalidConversion)]
+#[case::same_currency(convert(1, 2, dec!(1.00), "USD", "USD", Some(0)), RejectionReason::InvalidConversion)]
+#[case::missing_timestamp(convert(1, 2, dec!(1.00), "USD", "EUR", None), RejectionReason::MissingTimestamp)]
+#[case::unknown_rate(convert(1, 2, dec!(1.00), "EUR", "USD", Some(0)), RejectionReason::UnknownRate)]
+#[case::insufficient_funds(convert(1, 2, dec!(100.01), "USD", "EUR", Some(0)), RejectionReason::InsufficientFunds)]
+// 0.001 JPY rounds to nothing
+#[case::converts_to_nothing(convert(1, 2, dec!(0.000001), "USD", "JPY", Some(0)), RejectionReason::NonPositiveAmount)]
+#[case::duplicate(convert(1, 1, dec!(1.00), "USD", "EUR", Some(0)), RejectionReason::DuplicateTransaction)]
+#[case::unknown_account(convert(2, 2, dec!(1.00), "USD", "EUR", Some(0)), RejectionReason::UnknownAccount)]
+fn test_invalid_conversions_rejected(#[case] record: TransactionRecord, #[case] reason: RejectionReason) {
+    let mut engine = converting_engine();
+    let before = engine.accounts.clone();
+    let client_id = record.client_id;
+    let transaction_id = record.transaction_id;
+    assert_eq!(engine.process_transaction(record).unwrap(), rejected(TransactionType::Convert, client_id, transaction_id, reason));
+    assert_eq!(engine.accounts, before);
+}
+
+#[rstest]
+fn test_disputed_conversion_holds_converted_funds() {
+    let mut engine = converting_engine();
+    engine.process_transaction(convert(1, 2, dec!(10.00), "USD", "JPY", Some(0))).unwrap();
+    assert_eq!(engine.accounts.get(&1).unwrap().balance(Some("JPY")).available, dec!(1501));
+
+    // Disputes act on the converted funds, so they name the target currency
+    assert_eq!(
+        engine.process_transaction(in_currency(TransactionType::Dispute, 1, 2, None, "USD")).unwrap(),
+        rejected(TransactionType::Dispute, 1, 2, RejectionReason::CurrencyMismatch)
+    );
+    assert_eq!(engine.process_transaction(in_currency(TransactionType::Dispute, 1, 2, None, "JPY")).unwrap(), TransactionOutcome::Applied);
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.balance(Some("JPY")).held, dec!(1501));
+    assert_eq!(account.balance(Some("USD")).total, dec!(90.00));
+    engine.check_invariants().unwrap();
+}
+
+#[rstest]
+fn test_conversion_chargeback_reverses_at_original_rate() {
+    let mut engine = converting_engine();
+    engine.process_transaction(convert(1, 2, dec!(10.00), "USD", "EUR", Some(0))).unwrap();
+    // Rates changing afterwards don't matter, the stored rate does
+    engine.rates.insert("USD", "EUR", 1, dec!(2));
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 2, None)).unwrap();
+    engine.process_transaction(transaction(TransactionType::Chargeback, 1, 2, None)).unwrap();
+
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.balance(Some("EUR")).total, dec!(0.00));
+    assert_eq!(account.balance(Some("USD")).available, dec!(100.00));
+    assert!(account.locked);
+    engine.check_invariants().unwrap();
+}
+
+#[rstest]
+fn test_partial_conversion_chargebacks_add_up_to_converted_amount() {
+    let mut engine = converting_engine();
+    // 10.00 USD converts to 1501 JPY, which doesn't convert back to whole cents
+    engine.process_transaction(convert(1, 2, dec!(10.00), "USD", "JPY", Some(0))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 2, None)).unwrap();
+    engine.process_transaction(transaction(TransactionType::Chargeback, 1, 2, Some(dec!(500)))).unwrap();
+    assert_eq!(engine.accounts.get(&1).unwrap().balance(Some("USD")).available, dec!(93.33));
+    engine.process_transaction(transaction(TransactionType::Chargeback, 1, 2, Some(dec!(1000)))).unwrap();
+    assert_eq!(engine.accounts.get(&1).unwrap().balance(Some("USD")).available, dec!(99.99));
+    engine.process_transaction(transaction(TransactionType::Chargeback, 1, 2, None)).unwrap();
+
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.balance(Some("USD")).available, dec!(100.00));
+    assert_eq!(account.balance(Some("JPY")).total, dec!(0));
+    engine.check_invariants().unwrap();
+}
+
+#[rstest]
+fn test_conversion_chargeback_overflow_is_error_and_leaves_dispute_untouched() {
+    let mut engine = converting_engine();
+    engine.process_transaction(convert(1, 2, dec!(10.00), "USD", "EUR", Some(0))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 2, None)).unwrap();
+    // Reversing the conversion would take the USD balance past MAX_BALANCE
+    engine.process_transaction(in_currency(TransactionType::Deposit, 1, 3, Some(MAX_BALANCE - dec!(90.00)), "USD")).unwrap();
+    engine.enable_journal();
+    let (accounts, disputes) = (engine.accounts.clone(), engine.disputes.clone());
+
+    let result = engine.process_transaction(transaction(TransactionType::Chargeback, 1, 2, None));
+    assert!(matches!(result, Err(ApplicationError::BalanceOverflow { client_id: 1, transaction_id: 2 })));
+    assert_eq!(engine.accounts, accounts);
+    assert_eq!(engine.disputes, disputes);
+    assert!(engine.take_events().is_empty());
+    engine.check_invariants().unwrap();
+}
+
+/// Helper to create a Transfer record from `client_id` to `counterparty`
+fn transfer(client_id: u16, transaction_id: u32, amount: Decimal, counterparty: Option<u16>) -> TransactionRecord {
+    TransactionRecord { counterparty, ..transaction(TransactionType::Transfer, client_id, transaction_id, Some(amount)) }
+}
+
+/// Helper to build an engine with client 1 holding 100, client 2 holding 10, client 3 frozen and client 4 closed
+fn transferring_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(100)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Deposit, 2, 2, Some(dec!(10)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Deposit, 3, 3, Some(dec!(1)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Freeze, 3, 30, None)).unwrap();
+    engine.process_transaction(transaction(TransactionType::Deposit, 4, 4, Some(dec!(1)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Withdrawal, 4, 5, Some(dec!(1)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Close, 4, 40, None)).unwrap();
+    engine
+}
+
+#[rstest]
+fn test_transfer_moves_funds_between_accounts() {
+    let mut engine = transferring_engine();
+    assert_eq!(engine.process_transaction(transfer(1, 10, dec!(25), Some(2))).unwrap(), TransactionOutcome::Applied);
+
+    assert_eq!(engine.accounts.get(&1).unwrap().available, dec!(75));
+    assert_eq!(engine.accounts.get(&1).unwrap().total, dec!(75));
+    assert_eq!(engine.accounts.get(&2).unwrap().available, dec!(35));
+    assert_eq!(engine.accounts.get(&2).unwrap().total, dec!(35));
+    assert_eq!(engine.transactions.get(&10).unwrap().counterparty, Some(2));
+    engine.check_invariants().unwrap();
+}
+
+#[rstest]
+#[case::missing_amount(TransactionRecord { amount: None, ..transfer(1, 10, dec!(1), Some(2)) }, RejectionReason::MissingAmount)]
+#[case::missing_counterparty(transfer(1, 10, dec!(1), None), RejectionReason::InvalidCounterparty)]
+#[case::to_itself(transfer(1, 10, dec!(1), Some(1)), RejectionReason::InvalidCounterparty)]
+#[case::unknown_account(transfer(9, 10, dec!(1), Some(1)), RejectionReason::UnknownAccount)]
+#[case::locked_account(transfer(3, 10, dec!(1), Some(1)), RejectionReason::AccountLocked)]
+#[case::closed_account(transfer(4, 10, dec!(1), Some(1)), RejectionReason::AccountClosed)]
+#[case::unknown_counterparty(transfer(1, 10, dec!(1), Some(9)), RejectionReason::UnknownCounterparty)]
+#[case::locked_counterparty(transfer(1, 10, dec!(1), Some(3)), RejectionReason::CounterpartyLocked)]
+#[case::closed_counterparty(transfer(1, 10, dec!(1), Some(4)), RejectionReason::CounterpartyClosed)]
+#[case::duplicate(transfer(1, 2, dec!(1), Some(2)), RejectionReason::DuplicateTransaction)]
+#[case::insufficient_funds(transfer(1, 10, dec!(100.0001), Some(2)), RejectionReason::InsufficientFunds)]
+// Funds in another currency don't cover it
+#[case::other_currency(TransactionRecord { currency: Some("USD".to_string()), ..transfer(1, 10, dec!(1), Some(2)) }, RejectionReason::InsufficientFunds)]
+fn test_invalid_transfers_rejected(#[case] record: TransactionRecord, #[case] reason: RejectionReason) {
+    let mut engine = transferring_engine();
+    let before = engine.accounts.clone();
+    let client_id = record.client_id;
+    let transaction_id = record.transaction_id;
+    assert_eq!(engine.process_transaction(record).unwrap(), rejected(TransactionType::Transfer, client_id, transaction_id, reason));
+    assert_eq!(engine.accounts, before);
+}
+
+#[rstest]
+fn test_transfer_overflow_is_error_and_leaves_both_accounts_untouched() {
+    let mut engine = transferring_engine();
+    engine.process_transaction(transaction(TransactionType::Deposit, 2, 6, Some(MAX_BALANCE - dec!(10)))).unwrap();
+    let before = engine.accounts.clone();
+    assert!(matches!(engine.process_transaction(transfer(1, 10, dec!(1), Some(2))), Err(ApplicationError::BalanceOverflow { .. })));
+    assert_eq!(engine.accounts, before);
+}
+
+#[rstest]
+fn test_disputed_transfer_holds_funds_on_recipient() {
+    let mut engine = transferring_engine();
+    engine.process_transaction(transfer(1, 10, dec!(25), Some(2))).unwrap();
+
+    // Only the sender disputes a transfer
+    assert_eq!(
+        engine.process_transaction(transaction(TransactionType::Dispute, 2, 10, None)).unwrap(),
+        rejected(TransactionType::Dispute, 2, 10, RejectionReason::ClientMismatch { owner_client_id: 1 })
+    );
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Dispute, 1, 10, None)).unwrap(), TransactionOutcome::Applied);
+    let recipient = engine.accounts.get(&2).unwrap();
+    assert_eq!((recipient.available, recipient.held, recipient.total), (dec!(10), dec!(25), dec!(35)));
+    assert_eq!(engine.accounts.get(&1).unwrap().available, dec!(75));
+    engine.check_invariants().unwrap();
+
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Resolve, 1, 10, None)).unwrap(), TransactionOutcome::Applied);
+    let recipient = engine.accounts.get(&2).unwrap();
+    assert_eq!((recipient.available, recipient.held, recipient.total), (dec!(35), dec!(0), dec!(35)));
+    engine.check_invariants().unwrap();
+}
+
+#[rstest]
+fn test_transfer_chargeback_pays_back_sender_and_locks_recipient() {
+    let mut engine = transferring_engine();
+    engine.process_transaction(transfer(1, 10, dec!(25), Some(2))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 10, None)).unwrap();
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Chargeback, 1, 10, Some(dec!(5)))).unwrap(), TransactionOutcome::Applied);
+
+    let recipient = engine.accounts.get(&2).unwrap();
+    assert_eq!((recipient.available, recipient.held, recipient.total), (dec!(10), dec!(20), dec!(30)));
+    assert!(recipient.locked);
+    let sender = engine.accounts.get(&1).unwrap();
+    assert_eq!((sender.available, sender.total), (dec!(80), dec!(80)));
+    assert!(!sender.locked);
+    engine.check_invariants().unwrap();
+}
+
+#[rstest]
+fn test_transfer_chargeback_to_closed_sender_rejected() {
+    let mut engine = transferring_engine();
+    engine.process_transaction(transfer(1, 10, dec!(100), Some(2))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Close, 1, 100, None)).unwrap();
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 10, None)).unwrap();
+    let before = engine.accounts.clone();
+    assert_eq!(
+        engine.process_transaction(transaction(TransactionType::Chargeback, 1, 10, None)).unwrap(),
+        rejected(TransactionType::Chargeback, 1, 10, RejectionReason::AccountClosed)
+    );
+    assert_eq!(engine.accounts, before);
+}
+
+#[rstest]
+fn test_transfer_chargeback_overflow_is_error_and_leaves_both_accounts_untouched() {
+    let mut engine = transferring_engine();
+    engine.process_transaction(transfer(1, 10, dec!(25), Some(2))).unwrap();
+    // Paying the transfer back would take the sender past MAX_BALANCE
+    engine.process_transaction(transaction(TransactionType::Deposit, 1, 11, Some(MAX_BALANCE - dec!(75)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 10, None)).unwrap();
+    engine.enable_journal();
+    let (accounts, disputes) = (engine.accounts.clone(), engine.disputes.clone());
+
+    let result = engine.process_transaction(transaction(TransactionType::Chargeback, 1, 10, None));
+    assert!(matches!(result, Err(ApplicationError::BalanceOverflow { client_id: 1, transaction_id: 10 })));
+    assert_eq!(engine.accounts, accounts);
+    assert!(!engine.accounts.get(&2).unwrap().locked);
+    assert_eq!(engine.disputes, disputes);
+    assert!(engine.take_events().is_empty());
+    engine.check_invariants().unwrap();
+}
+
+/// Helper to create a Refund record of `original` for `client_id`
+fn refund(client_id: u16, transaction_id: u32, amount: Decimal, original: Option<u32>) -> TransactionRecord {
+    TransactionRecord { original_transaction_id: original, ..transaction(TransactionType::Refund, client_id, transaction_id, Some(amount)) }
+}
+
+/// Helper to build an engine where client 1 withdrew 40 of 100 as tx 2 and 10 of 20 USD as tx 4, client 2 withdrew
+/// 5 as tx 6 and client 3 withdrew 1 as tx 8 before being frozen
+fn refunding_engine(config: EngineConfig) -> Engine {
+    let mut engine = Engine::with_config(config);
+    let usd = |record: TransactionRecord| TransactionRecord { currency: Some("USD".to_string()), ..record };
+    engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(100)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(40)))).unwrap();
+    engine.process_transaction(usd(transaction(TransactionType::Deposit, 1, 3, Some(dec!(20))))).unwrap();
+    engine.process_transaction(usd(transaction(TransactionType::Withdrawal, 1, 4, Some(dec!(10))))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Deposit, 2, 5, Some(dec!(10)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Withdrawal, 2, 6, Some(dec!(5)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Deposit, 3, 7, Some(dec!(5)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Withdrawal, 3, 8, Some(dec!(1)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Freeze, 3, 30, None)).unwrap();
+    engine
+}
+
+#[rstest]
+fn test_refunds_credit_client_up_to_withdrawn_amount() {
+    let mut engine = refunding_engine(EngineConfig::default());
+    assert_eq!(engine.process_transaction(refund(1, 10, dec!(15), Some(2))).unwrap(), TransactionOutcome::Applied);
+    assert_eq!(engine.process_transaction(refund(1, 11, dec!(25), Some(2))).unwrap(), TransactionOutcome::Applied);
+
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!((account.available, account.total), (dec!(100), dec!(100)));
+    // Refunds are stored under their own ID, and the withdrawal keeps count of them
+    let stored = engine.transactions.get(&11).unwrap();
+    assert_eq!((stored.transaction_type, stored.amount, stored.original_transaction_id), (TransactionType::Refund, Some(dec!(25)), Some(2)));
+    assert_eq!(engine.transactions.get(&2).unwrap().refunded, Some(dec!(40)));
+    engine.check_invariants().unwrap();
+
+    assert_eq!(
+        engine.process_transaction(refund(1, 12, dec!(0.0001), Some(2))).unwrap(),
+        rejected(TransactionType::Refund, 1, 12, RejectionReason::ExceedsRefundableAmount)
+    );
+}
+
+#[rstest]
+fn test_refund_credits_currency_of_withdrawal() {
+    let mut engine = refunding_engine(EngineConfig::default());
+    assert_eq!(engine.process_transaction(refund(1, 10, dec!(10), Some(4))).unwrap(), TransactionOutcome::Applied);
+
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.balance(Some("USD")).available, dec!(20));
+    assert_eq!(account.available, dec!(60));
+    assert_eq!(engine.transactions.get(&10).unwrap().currency.as_deref(), Some("USD"));
+    engine.check_invariants().unwrap();
+}
+
+#[rstest]
+#[case::missing_amount(TransactionRecord { amount: None, ..refund(1, 10, dec!(1), Some(2)) }, RejectionReason::MissingAmount)]
+#[case::missing_original(refund(1, 10, dec!(1), None), RejectionReason::UnknownTransaction)]
+#[case::unknown_original(refund(1, 10, dec!(1), Some(99)), RejectionReason::UnknownTransaction)]
+#[case::deposit(refund(1, 10, dec!(1), Some(1)), RejectionReason::NotRefundable)]
+#[case::other_client(refund(2, 10, dec!(1), Some(2)), RejectionReason::ClientMismatch { owner_client_id: 1 })]
+#[case::other_currency(TransactionRecord { currency: Some("USD".to_string()), ..refund(1, 10, dec!(1), Some(2)) }, RejectionReason::CurrencyMismatch)]
+#[case::locked_account(refund(3, 10, dec!(1), Some(8)), RejectionReason::AccountLocked)]
+#[case::duplicate(refund(1, 5, dec!(1), Some(2)), RejectionReason::DuplicateTransaction)]
+#[case::more_than_withdrawn(refund(1, 10, dec!(40.0001), Some(2)), RejectionReason::ExceedsRefundableAmount)]
+fn test_invalid_refunds_rejected(#[case] record: TransactionRecord, #[case] reason: RejectionReason) {
+    let mut engine = refunding_engine(EngineConfig::default());
+    let before = engine.accounts.clone();
+    let (client_id, transaction_id) = (record.client_id, record.transaction_id);
+    assert_eq!(engine.process_transaction(record).unwrap(), rejected(TransactionType::Refund, client_id, transaction_id, reason));
+    assert_eq!(engine.accounts, before);
+    assert_eq!(engine.transactions.get(&2).unwrap().refunded, None);
+}
+
+#[rstest]
+fn test_refund_is_disputed_like_a_deposit() {
+    let mut engine = refunding_engine(EngineConfig::default());
+    engine.process_transaction(refund(1, 10, dec!(15), Some(2))).unwrap();
+    // A refund of a refund is not a thing
+    assert_eq!(
+        engine.process_transaction(refund(1, 11, dec!(1), Some(10))).unwrap(),
+        rejected(TransactionType::Refund, 1, 11, RejectionReason::NotRefundable)
+    );
+
+    assert_eq!(engine.process_transaction(transaction(TransactionType::Dispute, 1, 10, None)).unwrap(), TransactionOutcome::Applied);
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!((account.available, account.held, account.total), (dec!(60), dec!(15), dec!(75)));
+    engine.process_transaction(transaction(TransactionType::Chargeback, 1, 10, None)).unwrap();
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!((account.available, account.held, account.total, account.locked), (dec!(60), dec!(0), dec!(60), true));
+    engine.check_invariants().unwrap();
+}
+
+#[rstest]
+fn test_refunds_and_withdrawal_disputes_never_pay_back_more_than_withdrawn() {
+    let mut engine = refunding_engine(EngineConfig { withdrawal_disputes: WithdrawalDisputePolicy::CreditPending, ..EngineConfig::default() });
+
+    // Funds held by an open dispute of the withdrawal may still be credited back by its chargeback
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 2, Some(dec!(30)))).unwrap();
+    assert_eq!(
+        engine.process_transaction(refund(1, 10, dec!(10.0001), Some(2))).unwrap(),
+        rejected(TransactionType::Refund, 1, 10, RejectionReason::ExceedsRefundableAmount)
+    );
+    engine.process_transaction(transaction(TransactionType::Chargeback, 1, 2, Some(dec!(10)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Resolve, 1, 2, None)).unwrap();
+    engine.process_transaction(transaction(TransactionType::Unlock, 1, 100, None)).unwrap();
+    assert_eq!(engine.process_transaction(refund(1, 10, dec!(25), Some(2))).unwrap(), TransactionOutcome::Applied);
+
+    // Only what was neither charged back nor refunded is left to dispute
+    assert_eq!(
+        engine.process_transaction(transaction(TransactionType::Dispute, 1, 2, Some(dec!(5.0001)))).unwrap(),
+        rejected(TransactionType::Dispute, 1, 2, RejectionReason::ExceedsDisputableAmount)
+    );
+    assert_eq!(engine.process_transaction(refund(1, 11, dec!(5), Some(2))).unwrap(), TransactionOutcome::Applied);
+    assert_eq!(
+        engine.process_transaction(transaction(TransactionType::Dispute, 1, 2, None)).unwrap(),
+        rejected(TransactionType::Dispute, 1, 2, RejectionReason::AlreadyRefunded)
+    );
+
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!((account.available, account.total), (dec!(100), dec!(100)));
+    engine.check_invariants().unwrap();
+}
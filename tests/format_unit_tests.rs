@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use flate2::write::GzEncoder;
+use payments_engine::format::{self, AccountRow, Compression, OutputFormat};
+use payments_engine::model::{Account, Balance};
+use rstest::rstest;
+use rust_decimal_macros::dec;
+
+/// Helper to build accounts for clients 2 and 1, so output order has to be sorted
+fn accounts() -> HashMap<u16, Account> {
+    let mut accounts = HashMap::new();
+    accounts.insert(2, Account { available: dec!(1.5), held: dec!(0), total: dec!(1.5), locked: true, closed: false, reserved: dec!(0), currencies: Default::default() });
+    accounts.insert(1, Account { available: dec!(-0.25), held: dec!(3.0000), total: dec!(2.75), locked: false, closed: false, reserved: dec!(0), currencies: Default::default() });
+    accounts
+}
+
+/// Helper to write accounts to a string
+fn written(accounts: &HashMap<u16, Account>, output_format: OutputFormat) -> String {
+    let mut buffer = Vec::new();
+    format::write_accounts(accounts, output_format, &mut buffer).unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+#[rstest]
+#[case(OutputFormat::Csv, "client,available,held,total,locked\n1,-0.2500,3.0000,2.7500,false\n2,1.5000,0.0000,1.5000,true\n")]
+#[case(OutputFormat::Jsonl, concat!(
+    r#"{"client":1,"available":"-0.2500","held":"3.0000","total":"2.7500","locked":false}"#, "\n",
+    r#"{"client":2,"available":"1.5000","held":"0.0000","total":"1.5000","locked":true}"#, "\n",
+))]
+#[case(OutputFormat::Json, concat!(
+    r#"[{"client":1,"available":"-0.2500","held":"3.0000","total":"2.7500","locked":false},"#,
+    r#"{"client":2,"available":"1.5000","held":"0.0000","total":"1.5000","locked":true}]"#, "\n",
+))]
+fn test_accounts_written_in_each_format(#[case] output_format: OutputFormat, #[case] expected: &str) {
+    assert_eq!(written(&accounts(), output_format), expected);
+}
+
+#[rstest]
+#[case(OutputFormat::Csv, "client,available,held,total,locked\n")]
+#[case(OutputFormat::Jsonl, "")]
+#[case(OutputFormat::Json, "[]\n")]
+fn test_no_accounts_written(#[case] output_format: OutputFormat, #[case] expected: &str) {
+    assert_eq!(written(&HashMap::new(), output_format), expected);
+}
+
+#[rstest]
+#[case(r#"{"type":"deposit","client":1,"tx":1,"amount":"79228162514264337593543.9503"}"#)]
+// Through a float this would come out as 79228162514264340000000
+#[case(r#"{"type":"deposit","client":1,"tx":1,"amount":79228162514264337593543.9503}"#)]
+fn test_json_amounts_read_from_exact_text(#[case] line: &str) {
+    let record = format::parse_json_record(line).unwrap();
+    assert_eq!(record.amount, Some(dec!(79228162514264337593543.9503)));
+}
+
+#[rstest]
+fn test_json_output_round_trips() {
+    let rows: Vec<AccountRow> = serde_json::from_str(&written(&accounts(), OutputFormat::Json)).unwrap();
+    assert_eq!(rows[1], AccountRow::new(2, &accounts()[&2]));
+}
+
+/// Helper to gzip `data` as a single member
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Helper to read `data` back through decompress
+fn decompressed(data: Vec<u8>) -> String {
+    let mut text = String::new();
+    format::decompress(std::io::Cursor::new(data)).unwrap().read_to_string(&mut text).unwrap();
+    text
+}
+
+#[rstest]
+#[case(b"type,client,tx,amount\n", Compression::None)]
+#[case(b"", Compression::None)]
+#[case(&[0x1f, 0x8b, 0x08], Compression::Gzip)]
+#[case(&[0x28, 0xb5, 0x2f, 0xfd, 0x00], Compression::Zstd)]
+// Too short to be the zstd magic
+#[case(&[0x28, 0xb5], Compression::None)]
+fn test_compression_detected_from_magic_bytes(#[case] start: &[u8], #[case] expected: Compression) {
+    assert_eq!(Compression::detect(start), expected);
+}
+
+#[rstest]
+fn test_decompress_reads_every_format() {
+    let text = "type,client,tx,amount\ndeposit,1,1,1.0000\n";
+    assert_eq!(decompressed(text.as_bytes().to_vec()), text);
+    assert_eq!(decompressed(gzip(text.as_bytes())), text);
+    assert_eq!(decompressed(zstd::encode_all(text.as_bytes(), 0).unwrap()), text);
+    // Inputs shorter than any magic are passed through untouched
+    assert_eq!(decompressed(b"t".to_vec()), "t");
+    assert_eq!(decompressed(Vec::new()), "");
+}
+
+#[rstest]
+fn test_decompress_reads_concatenated_members_and_frames() {
+    let mut members = gzip(b"type,client,tx,amount\n");
+    members.extend(gzip(b"deposit,1,1,1.0000\n"));
+    assert_eq!(decompressed(members), "type,client,tx,amount\ndeposit,1,1,1.0000\n");
+
+    let mut frames = zstd::encode_all(&b"type,client,tx,amount\n"[..], 0).unwrap();
+    frames.extend(zstd::encode_all(&b"deposit,1,1,1.0000\n"[..], 0).unwrap());
+    assert_eq!(decompressed(frames), "type,client,tx,amount\ndeposit,1,1,1.0000\n");
+}
+
+/// Helper to list the file names in a directory
+fn file_names(directory: &std::path::Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(directory).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned()).collect();
+    names.sort();
+    names
+}
+
+#[rstest]
+fn test_write_atomically_replaces_file_only_on_success() {
+    let directory = std::env::temp_dir().join(format!("payments_engine_atomic_{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("accounts.csv");
+    std::fs::write(&path, "previous").unwrap();
+
+    // A failed write leaves the previous content and no temporary file behind
+    let result = format::write_atomically(&path, |writer| {
+        writer.write_all(b"partial")?;
+        Err(std::io::Error::other("crashed").into())
+    });
+    assert!(result.is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "previous");
+    assert_eq!(file_names(&directory), ["accounts.csv"]);
+
+    format::write_atomically(&path, |writer| format::write_accounts(&accounts(), OutputFormat::Csv, writer)).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), written(&accounts(), OutputFormat::Csv));
+    assert_eq!(file_names(&directory), ["accounts.csv"]);
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+/// Helper to build an account holding USD and JPY, with nothing left in the default currency
+fn currency_accounts() -> HashMap<u16, Account> {
+    let mut account = Account::new();
+    account.set_balance(Some("USD"), Balance { available: dec!(7.50), held: dec!(2.00), total: dec!(9.50), reserved: dec!(0) });
+    account.set_balance(Some("JPY"), Balance { available: dec!(300), held: dec!(0), total: dec!(300), reserved: dec!(0) });
+    let mut accounts = accounts();
+    accounts.insert(3, account);
+    accounts
+}
+
+#[rstest]
+#[case(OutputFormat::Csv, concat!(
+    "client,available,held,total,locked,currency\n",
+    "1,-0.2500,3.0000,2.7500,false,\n",
+    "2,1.5000,0.0000,1.5000,true,\n",
+    "3,300.0000,0.0000,300.0000,false,JPY\n",
+    "3,7.5000,2.0000,9.5000,false,USD\n",
+))]
+#[case(OutputFormat::Jsonl, concat!(
+    r#"{"client":1,"available":"-0.2500","held":"3.0000","total":"2.7500","locked":false}"#, "\n",
+    r#"{"client":2,"available":"1.5000","held":"0.0000","total":"1.5000","locked":true}"#, "\n",
+    r#"{"client":3,"available":"300.0000","held":"0.0000","total":"300.0000","locked":false,"currency":"JPY"}"#, "\n",
+    r#"{"client":3,"available":"7.5000","held":"2.0000","total":"9.5000","locked":false,"currency":"USD"}"#, "\n",
+))]
+fn test_currency_balances_written_as_rows(#[case] output_format: OutputFormat, #[case] expected: &str) {
+    assert_eq!(written(&currency_accounts(), output_format), expected);
+}
+
+#[rstest]
+#[case(OutputFormat::Csv, concat!(
+    "client,available,held,total,locked,reserved\n",
+    "1,-0.2500,3.0000,2.7500,false,0.0000\n",
+    "2,1.5000,0.0000,1.5000,true,0.0000\n",
+    "3,6.0000,0.0000,10.0000,false,4.0000\n",
+))]
+#[case(OutputFormat::Jsonl, concat!(
+    r#"{"client":1,"available":"-0.2500","held":"3.0000","total":"2.7500","locked":false}"#, "\n",
+    r#"{"client":2,"available":"1.5000","held":"0.0000","total":"1.5000","locked":true}"#, "\n",
+    r#"{"client":3,"available":"6.0000","held":"0.0000","total":"10.0000","locked":false,"reserved":"4.0000"}"#, "\n",
+))]
+fn test_reserved_funds_written_when_any_account_has_them(#[case] output_format: OutputFormat, #[case] expected: &str) {
+    let mut accounts = accounts();
+    accounts.insert(3, Account { available: dec!(6), held: dec!(0), total: dec!(10), locked: false, closed: false, reserved: dec!(4), currencies: Default::default() });
+    assert_eq!(written(&accounts, output_format), expected);
+}
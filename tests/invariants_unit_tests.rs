@@ -0,0 +1,98 @@
+use payments_engine::engine::Engine;
+use payments_engine::error::ApplicationError;
+use payments_engine::model::{TransactionRecord, TransactionType};
+use rstest::rstest;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+/// Helper to create a TransactionRecord easily
+fn transaction(transaction_type: TransactionType, client_id: u16, transaction_id: u32, amount: Option<Decimal>) -> TransactionRecord {
+    TransactionRecord::new(transaction_type, client_id, transaction_id, amount)
+}
+
+/// Helper to build an engine exercising every transaction type, leaving tx 3 disputed
+fn busy_engine() -> Engine {
+    let mut engine = Engine::new();
+    for record in [
+        transaction(TransactionType::Deposit, 1, 1, Some(dec!(10.0000))),
+        transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(2.5000))),
+        transaction(TransactionType::Deposit, 1, 3, Some(dec!(4.0000))),
+        transaction(TransactionType::Dispute, 1, 3, None),
+        transaction(TransactionType::Deposit, 2, 4, Some(dec!(1.0000))),
+        transaction(TransactionType::Dispute, 2, 4, None),
+        transaction(TransactionType::Resolve, 2, 4, None),
+        transaction(TransactionType::Deposit, 3, 5, Some(dec!(6.0000))),
+        transaction(TransactionType::Dispute, 3, 5, None),
+        transaction(TransactionType::Chargeback, 3, 5, None),
+    ] {
+        engine.process_transaction(record).unwrap();
+    }
+    engine
+}
+
+fn assert_violation(engine: &Engine, expected_client_id: u16) {
+    match engine.check_invariants() {
+        Err(ApplicationError::InvariantViolation { client_id, .. }) => assert_eq!(client_id, expected_client_id),
+        other => panic!("expected an invariant violation, got {:?}", other),
+    }
+}
+
+#[rstest]
+fn test_invariants_hold_after_processing() {
+    let engine = busy_engine();
+    assert!(engine.check_invariants().is_ok());
+    assert!(Engine::new().check_invariants().is_ok());
+}
+
+#[rstest]
+fn test_total_mismatch_detected() {
+    let mut engine = busy_engine();
+    engine.accounts.get_mut(&2).unwrap().total += dec!(0.0001);
+    assert_violation(&engine, 2);
+}
+
+#[rstest]
+fn test_negative_held_detected() {
+    let mut engine = busy_engine();
+    let account = engine.accounts.get_mut(&2).unwrap();
+    account.held = dec!(-1.0000);
+    account.available += dec!(1.0000);
+    assert_violation(&engine, 2);
+}
+
+#[rstest]
+fn test_held_not_matching_disputes_detected() {
+    let mut engine = busy_engine();
+    // Tx 3 is held for client 1, but closing the dispute without releasing funds leaves them unaccounted for
+    engine.disputes.release(3, dec!(4.0000), engine.record_sequence());
+    assert_violation(&engine, 1);
+
+    // A dispute opened without holding funds is caught too
+    let mut engine = busy_engine();
+    engine.disputes.open(1, dec!(1.0000), engine.record_sequence());
+    assert_violation(&engine, 1);
+}
+
+#[rstest]
+fn test_dispute_for_client_without_account_detected() {
+    let mut engine = busy_engine();
+    engine.accounts.remove(&1);
+    assert_violation(&engine, 1);
+}
+
+#[rstest]
+fn test_dispute_on_unknown_transaction_detected() {
+    let mut engine = busy_engine();
+    engine.disputes.open(99, dec!(1.0000), engine.record_sequence());
+    assert!(matches!(engine.check_invariants(), Err(ApplicationError::TransactionNotFound { transaction_id: 99, .. })));
+}
+
+#[cfg(debug_assertions)]
+#[rstest]
+#[should_panic(expected = "Invariant Violation")]
+fn test_debug_build_asserts_after_each_transaction() {
+    let mut engine = busy_engine();
+    engine.accounts.get_mut(&2).unwrap().total += dec!(1.0000);
+    // The next applied record for client 2 trips the check
+    let _ = engine.process_transaction(transaction(TransactionType::Deposit, 2, 6, Some(dec!(1.0000))));
+}
@@ -0,0 +1,390 @@
+use payments_engine::config::{EngineConfig, WithdrawalDisputePolicy};
+use payments_engine::engine::Engine;
+use payments_engine::error::ApplicationError;
+use payments_engine::journal::{self, JournalWriter, LedgerEvent, LedgerEventKind};
+use payments_engine::model::{TransactionRecord, TransactionType};
+use rstest::rstest;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+/// Helper to create a TransactionRecord easily
+fn transaction(transaction_type: TransactionType, client_id: u16, transaction_id: u32, amount: Option<Decimal>) -> TransactionRecord {
+    TransactionRecord::new(transaction_type, client_id, transaction_id, amount)
+}
+
+/// Helper to run records through a journaling engine and return it along with the serialized journal
+fn journaled_engine(records: Vec<TransactionRecord>) -> (Engine, Vec<u8>) {
+    let mut engine = Engine::new();
+    engine.enable_journal();
+    let mut buffer = Vec::new();
+    {
+        let mut writer = JournalWriter::from_writer(&mut buffer);
+        for record in records {
+            engine.process_transaction(record).unwrap();
+            writer.append(&engine.take_events()).unwrap();
+        }
+    }
+    (engine, buffer)
+}
+
+#[rstest]
+fn test_events_recorded_for_applied_changes_only() {
+    let mut engine = Engine::new();
+    engine.enable_journal();
+    engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(5.0000)))).unwrap();
+    // Rejected for insufficient funds, so no event
+    engine.process_transaction(transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(9.0000)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap();
+    engine.process_transaction(transaction(TransactionType::Chargeback, 1, 1, None)).unwrap();
+
+    let kinds: Vec<LedgerEventKind> = engine.take_events().iter().map(|event| event.kind).collect();
+    assert_eq!(kinds, vec![
+        LedgerEventKind::DepositCredited,
+        LedgerEventKind::FundsHeld,
+        LedgerEventKind::ChargebackPosted,
+        LedgerEventKind::AccountLocked,
+    ]);
+    assert!(engine.take_events().is_empty(), "Events should be drained");
+}
+
+#[rstest]
+fn test_no_events_recorded_without_journal() {
+    let mut engine = Engine::new();
+    engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(5.0000)))).unwrap();
+    assert!(engine.take_events().is_empty());
+}
+
+#[rstest]
+fn test_replay_rebuilds_identical_engine() {
+    let (engine, buffer) = journaled_engine(vec![
+        transaction(TransactionType::Deposit, 1, 1, Some(dec!(10.0000))),
+        transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(2.5000))),
+        transaction(TransactionType::Deposit, 2, 3, Some(dec!(4.0000))),
+        transaction(TransactionType::Dispute, 2, 3, None),
+        transaction(TransactionType::Deposit, 3, 4, Some(dec!(1.0000))),
+        transaction(TransactionType::Dispute, 3, 4, None),
+        transaction(TransactionType::Resolve, 3, 4, None),
+        transaction(TransactionType::Dispute, 1, 1, None),
+        transaction(TransactionType::Chargeback, 1, 1, None),
+    ]);
+
+    let replayed = journal::replay(buffer.as_slice()).unwrap();
+    assert_eq!(replayed.accounts, engine.accounts);
+    assert_eq!(replayed.disputes, engine.disputes);
+    assert_eq!(replayed.transactions.len(), engine.transactions.len());
+    assert!(replayed.accounts.get(&1).unwrap().locked);
+}
+
+#[rstest]
+fn test_replayed_engine_rejects_duplicate_transactions() {
+    let (_, buffer) = journaled_engine(vec![
+        transaction(TransactionType::Deposit, 1, 1, Some(dec!(10.0000))),
+    ]);
+
+    let mut replayed = journal::replay(buffer.as_slice()).unwrap();
+    let outcome = replayed.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(10.0000)))).unwrap();
+    assert!(!outcome.is_applied());
+    assert_eq!(replayed.accounts.get(&1).unwrap().total, dec!(10.0000));
+}
+
+#[rstest]
+fn test_replay_detects_tampered_journal() {
+    let (_, buffer) = journaled_engine(vec![
+        transaction(TransactionType::Deposit, 1, 1, Some(dec!(10.0000))),
+        transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(2.5000))),
+    ]);
+
+    // Inflate the withdrawal amount without touching the recorded resulting balances
+    let tampered = String::from_utf8(buffer).unwrap().replace("withdrawal_debited,1,2,2.5000", "withdrawal_debited,1,2,1.5000");
+    match journal::replay(tampered.as_bytes()) {
+        Err(ApplicationError::JournalMismatch { sequence, event, client_id }) => {
+            assert_eq!(sequence, 1);
+            assert_eq!(event, LedgerEventKind::WithdrawalDebited);
+            assert_eq!(client_id, 1);
+        }
+        other => panic!("Expected JournalMismatch, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[rstest]
+fn test_replay_detects_missing_event() {
+    let (_, buffer) = journaled_engine(vec![
+        transaction(TransactionType::Deposit, 1, 1, Some(dec!(10.0000))),
+        transaction(TransactionType::Deposit, 1, 2, Some(dec!(1.0000))),
+        transaction(TransactionType::Deposit, 1, 3, Some(dec!(1.0000))),
+    ]);
+
+    // Drop the second event
+    let journal = String::from_utf8(buffer).unwrap();
+    let truncated: Vec<&str> = journal.lines().enumerate().filter(|(index, _)| *index != 2).map(|(_, line)| line).collect();
+    assert!(matches!(
+        journal::replay(truncated.join("\n").as_bytes()),
+        Err(ApplicationError::JournalOutOfOrder { expected: 1, found: 2 })
+    ));
+}
+
+#[rstest]
+fn test_replay_reproduces_withdrawal_disputes() {
+    let mut engine = Engine::with_config(EngineConfig { withdrawal_disputes: WithdrawalDisputePolicy::CreditPending, ..EngineConfig::default() });
+    engine.enable_journal();
+    for record in [
+        transaction(TransactionType::Deposit, 1, 1, Some(dec!(10.0000))),
+        transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(4.0000))),
+        transaction(TransactionType::Withdrawal, 1, 3, Some(dec!(1.0000))),
+        transaction(TransactionType::Dispute, 1, 2, None),
+        transaction(TransactionType::Resolve, 1, 2, None),
+        transaction(TransactionType::Dispute, 1, 3, None),
+        transaction(TransactionType::Chargeback, 1, 3, None),
+    ] {
+        engine.process_transaction(record).unwrap();
+    }
+    let events = engine.take_events();
+    let kinds: Vec<LedgerEventKind> = events.iter().map(|event| event.kind).collect();
+    assert_eq!(kinds[3..], [
+        LedgerEventKind::CreditPending,
+        LedgerEventKind::CreditCancelled,
+        LedgerEventKind::CreditPending,
+        LedgerEventKind::CreditPosted,
+        LedgerEventKind::AccountLocked,
+    ]);
+
+    let mut buffer = Vec::new();
+    JournalWriter::from_writer(&mut buffer).append(&events).unwrap();
+    let replayed = journal::replay(buffer.as_slice()).unwrap();
+    assert_eq!(replayed.accounts, engine.accounts);
+}
+
+#[rstest]
+fn test_replay_reproduces_large_balances_exactly() {
+    // Balances with all 28 digits in use must survive the round trip through the CSV journal
+    let (engine, buffer) = journaled_engine(vec![
+        transaction(TransactionType::Deposit, 1, 1, Some(dec!(7922816251426433759354395.0335))),
+        transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(0.0001))),
+    ]);
+
+    let replayed = journal::replay(buffer.as_slice()).unwrap();
+    assert_eq!(replayed.accounts, engine.accounts);
+    assert_eq!(replayed.accounts.get(&1).unwrap().available, dec!(7922816251426433759354395.0334));
+}
+
+#[rstest]
+fn test_replay_reproduces_partial_disputes() {
+    let (engine, buffer) = journaled_engine(vec![
+        transaction(TransactionType::Deposit, 1, 1, Some(dec!(10.0000))),
+        transaction(TransactionType::Dispute, 1, 1, Some(dec!(6.0000))),
+        transaction(TransactionType::Resolve, 1, 1, Some(dec!(1.0000))),
+        transaction(TransactionType::Chargeback, 1, 1, Some(dec!(2.0000))),
+    ]);
+
+    let replayed = journal::replay(buffer.as_slice()).unwrap();
+    assert_eq!(replayed.accounts, engine.accounts);
+    assert_eq!(replayed.disputes, engine.disputes);
+    assert_eq!(replayed.dispute_history(1)[0].held(), dec!(3.0000));
+}
+
+#[rstest]
+fn test_replay_reproduces_administrative_events() {
+    let adjustment = TransactionRecord { reason: Some("goodwill credit".to_string()), ..transaction(TransactionType::Adjustment, 1, 100, Some(dec!(-2.0000))) };
+    let (engine, buffer) = journaled_engine(vec![
+        transaction(TransactionType::Deposit, 1, 1, Some(dec!(10.0000))),
+        transaction(TransactionType::Freeze, 1, 101, None),
+        transaction(TransactionType::Unlock, 1, 102, None),
+        adjustment,
+        transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(8.0000))),
+        transaction(TransactionType::Close, 1, 103, None),
+    ]);
+
+    let events: Vec<LedgerEvent> = csv::Reader::from_reader(buffer.as_slice()).deserialize().collect::<Result<_, _>>().unwrap();
+    let kinds: Vec<LedgerEventKind> = events.iter().map(|event| event.kind).collect();
+    assert_eq!(kinds, [
+        LedgerEventKind::DepositCredited,
+        LedgerEventKind::AccountFrozen,
+        LedgerEventKind::AccountUnlocked,
+        LedgerEventKind::BalanceAdjusted,
+        LedgerEventKind::WithdrawalDebited,
+        LedgerEventKind::AccountClosed,
+    ]);
+    assert_eq!(events[3].reason.as_deref(), Some("goodwill credit"));
+
+    let replayed = journal::replay(buffer.as_slice()).unwrap();
+    assert_eq!(replayed.accounts, engine.accounts);
+    assert!(replayed.accounts.get(&1).unwrap().closed);
+}
+
+#[rstest]
+fn test_replay_reproduces_currency_balances() {
+    let in_currency = |record: TransactionRecord, currency: &str| TransactionRecord { currency: Some(currency.to_string()), ..record };
+    let (engine, buffer) = journaled_engine(vec![
+        transaction(TransactionType::Deposit, 1, 1, Some(dec!(1.0000))),
+        in_currency(transaction(TransactionType::Deposit, 1, 2, Some(dec!(25.50))), "USD"),
+        in_currency(transaction(TransactionType::Deposit, 1, 3, Some(dec!(900))), "JPY"),
+        in_currency(transaction(TransactionType::Withdrawal, 1, 4, Some(dec!(5.25))), "USD"),
+        transaction(TransactionType::Dispute, 1, 3, Some(dec!(400))),
+        transaction(TransactionType::Chargeback, 1, 3, None),
+    ]);
+
+    let events: Vec<LedgerEvent> = csv::Reader::from_reader(buffer.as_slice()).deserialize().collect::<Result<_, _>>().unwrap();
+    let currencies: Vec<Option<&str>> = events.iter().map(|event| event.currency.as_deref()).collect();
+    assert_eq!(currencies, [None, Some("USD"), Some("JPY"), Some("USD"), Some("JPY"), Some("JPY"), Some("JPY")]);
+
+    let replayed = journal::replay(buffer.as_slice()).unwrap();
+    assert_eq!(replayed.accounts, engine.accounts);
+    assert_eq!(replayed.disputes, engine.disputes);
+    assert_eq!(replayed.accounts.get(&1).unwrap().balance(Some("JPY")).total, dec!(500));
+    replayed.check_invariants().unwrap();
+}
+
+#[rstest]
+fn test_replay_reproduces_conversions_at_their_rate() {
+    let mut engine = Engine::new();
+    engine.enable_journal();
+    engine.rates.insert("USD", "JPY", 0, dec!(150.123));
+    let mut buffer = Vec::new();
+    {
+        let mut writer = JournalWriter::from_writer(&mut buffer);
+        let deposit = TransactionRecord { currency: Some("USD".to_string()), ..transaction(TransactionType::Deposit, 1, 1, Some(dec!(20.00))) };
+        let convert = TransactionRecord {
+            currency: Some("USD".to_string()),
+            target_currency: Some("JPY".to_string()),
+            timestamp: Some(5),
+            ..transaction(TransactionType::Convert, 1, 2, Some(dec!(10.00)))
+        };
+        for record in [deposit, convert, transaction(TransactionType::Dispute, 1, 2, None), transaction(TransactionType::Chargeback, 1, 2, Some(dec!(500)))] {
+            engine.process_transaction(record).unwrap();
+            writer.append(&engine.take_events()).unwrap();
+        }
+    }
+
+    let events: Vec<LedgerEvent> = csv::Reader::from_reader(buffer.as_slice()).deserialize().collect::<Result<_, _>>().unwrap();
+    let kinds: Vec<LedgerEventKind> = events.iter().map(|event| event.kind).collect();
+    assert_eq!(kinds, [
+        LedgerEventKind::DepositCredited,
+        LedgerEventKind::ConversionDebited,
+        LedgerEventKind::ConversionCredited,
+        LedgerEventKind::FundsHeld,
+        LedgerEventKind::ChargebackPosted,
+        LedgerEventKind::ConversionReversed,
+        LedgerEventKind::AccountLocked,
+    ]);
+    assert_eq!(events[5].amount, Some(dec!(3.33)));
+
+    // Replay needs no rate table, the journal carries the applied rate
+    let replayed = journal::replay(buffer.as_slice()).unwrap();
+    assert_eq!(replayed.accounts, engine.accounts);
+    assert_eq!(replayed.disputes, engine.disputes);
+    let conversion = replayed.transactions.get(&2).unwrap();
+    assert_eq!((conversion.currency.as_deref(), conversion.target_currency.as_deref()), (Some("USD"), Some("JPY")));
+    assert_eq!((conversion.rate, conversion.converted_amount()), (Some(dec!(150.123)), Some(dec!(1501))));
+    replayed.check_invariants().unwrap();
+}
+
+#[rstest]
+fn test_replay_reproduces_transfers_and_their_chargebacks() {
+    let (engine, buffer) = journaled_engine(vec![
+        transaction(TransactionType::Deposit, 1, 1, Some(dec!(20.0000))),
+        transaction(TransactionType::Deposit, 2, 2, Some(dec!(1.0000))),
+        TransactionRecord { counterparty: Some(2), ..transaction(TransactionType::Transfer, 1, 3, Some(dec!(15.0000))) },
+        transaction(TransactionType::Dispute, 1, 3, None),
+        transaction(TransactionType::Chargeback, 1, 3, Some(dec!(5.0000))),
+    ]);
+
+    let events: Vec<LedgerEvent> = csv::Reader::from_reader(buffer.as_slice()).deserialize().collect::<Result<_, _>>().unwrap();
+    let summary: Vec<(LedgerEventKind, u16, Option<u16>)> = events.iter().map(|event| (event.kind, event.client_id, event.counterparty)).collect();
+    assert_eq!(summary, [
+        (LedgerEventKind::DepositCredited, 1, None),
+        (LedgerEventKind::DepositCredited, 2, None),
+        (LedgerEventKind::TransferDebited, 1, Some(2)),
+        (LedgerEventKind::TransferCredited, 2, Some(1)),
+        // The dispute holds, and the chargeback takes, the recipient's funds
+        (LedgerEventKind::FundsHeld, 2, None),
+        (LedgerEventKind::ChargebackPosted, 2, None),
+        (LedgerEventKind::AccountLocked, 2, None),
+        (LedgerEventKind::TransferReversed, 1, Some(2)),
+    ]);
+
+    let replayed = journal::replay(buffer.as_slice()).unwrap();
+    assert_eq!(replayed.accounts, engine.accounts);
+    assert_eq!(replayed.disputes, engine.disputes);
+    assert_eq!(replayed.transactions.get(&3).unwrap().counterparty, Some(2));
+    replayed.check_invariants().unwrap();
+}
+
+#[rstest]
+fn test_replay_reproduces_reservations_and_open_authorizations() {
+    let mut engine = Engine::with_config(EngineConfig { authorization_expiry_records: Some(2), ..EngineConfig::default() });
+    engine.enable_journal();
+    let mut buffer = Vec::new();
+    {
+        let mut writer = JournalWriter::from_writer(&mut buffer);
+        for record in [
+            transaction(TransactionType::Deposit, 1, 1, Some(dec!(100.0000))),
+            transaction(TransactionType::Authorize, 1, 2, Some(dec!(40.0000))),
+            transaction(TransactionType::Authorize, 1, 3, Some(dec!(10.0000))),
+            transaction(TransactionType::Capture, 1, 2, Some(dec!(15.0000))),
+            transaction(TransactionType::Deposit, 2, 4, Some(dec!(1.0000))),
+            transaction(TransactionType::Deposit, 2, 5, Some(dec!(1.0000))),
+            transaction(TransactionType::Authorize, 1, 6, Some(dec!(5.0000))),
+        ] {
+            engine.process_transaction(record).unwrap();
+            writer.append(&engine.take_events()).unwrap();
+        }
+    }
+
+    let events: Vec<LedgerEvent> = csv::Reader::from_reader(buffer.as_slice()).deserialize().collect::<Result<_, _>>().unwrap();
+    let summary: Vec<(LedgerEventKind, u32, Decimal)> = events.iter().map(|event| (event.kind, event.transaction_id, event.reserved)).collect();
+    assert_eq!(summary, [
+        (LedgerEventKind::DepositCredited, 1, dec!(0)),
+        (LedgerEventKind::FundsReserved, 2, dec!(40)),
+        (LedgerEventKind::FundsReserved, 3, dec!(50)),
+        (LedgerEventKind::ReservationCaptured, 2, dec!(35)),
+        (LedgerEventKind::ReservationReleased, 2, dec!(10)),
+        (LedgerEventKind::DepositCredited, 4, dec!(0)),
+        // Swept before the record it expired at
+        (LedgerEventKind::ReservationExpired, 3, dec!(0)),
+        (LedgerEventKind::DepositCredited, 5, dec!(0)),
+        (LedgerEventKind::FundsReserved, 6, dec!(5)),
+    ]);
+    assert_eq!(events[8].expires_after, Some(9));
+
+    let replayed = journal::replay(buffer.as_slice()).unwrap();
+    assert_eq!(replayed.accounts, engine.accounts);
+    assert_eq!(replayed.authorizations, engine.authorizations);
+    assert_eq!(replayed.transactions.get(&2).unwrap().transaction_type, TransactionType::Withdrawal);
+    replayed.check_invariants().unwrap();
+}
+
+#[rstest]
+fn test_replay_reproduces_refunds_and_their_disputes() {
+    let refund = |transaction_id: u32, amount: Decimal| TransactionRecord {
+        original_transaction_id: Some(2),
+        ..transaction(TransactionType::Refund, 1, transaction_id, Some(amount))
+    };
+    let (engine, buffer) = journaled_engine(vec![
+        transaction(TransactionType::Deposit, 1, 1, Some(dec!(20.0000))),
+        transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(15.0000))),
+        refund(3, dec!(5.0000)),
+        refund(4, dec!(10.0000)),
+        transaction(TransactionType::Dispute, 1, 4, Some(dec!(2.0000))),
+    ]);
+
+    let events: Vec<LedgerEvent> = csv::Reader::from_reader(buffer.as_slice()).deserialize().collect::<Result<_, _>>().unwrap();
+    let summary: Vec<(LedgerEventKind, u32, Option<u32>)> = events.iter().map(|event| (event.kind, event.transaction_id, event.original_transaction_id)).collect();
+    assert_eq!(summary, [
+        (LedgerEventKind::DepositCredited, 1, None),
+        (LedgerEventKind::WithdrawalDebited, 2, None),
+        (LedgerEventKind::RefundCredited, 3, Some(2)),
+        (LedgerEventKind::RefundCredited, 4, Some(2)),
+        (LedgerEventKind::FundsHeld, 4, None),
+    ]);
+
+    let mut replayed = journal::replay(buffer.as_slice()).unwrap();
+    assert_eq!(replayed.accounts, engine.accounts);
+    assert_eq!(replayed.disputes, engine.disputes);
+    assert_eq!(replayed.transactions.get(&2).unwrap().refunded, Some(dec!(15.0000)));
+    assert_eq!(replayed.transactions.get(&4).unwrap().original_transaction_id, Some(2));
+    replayed.check_invariants().unwrap();
+
+    // The replayed withdrawal still knows it was refunded in full
+    let outcome = replayed.process_transaction(refund(5, dec!(0.0001))).unwrap();
+    assert!(!outcome.is_applied());
+}
@@ -0,0 +1,79 @@
+use csv::StringRecord;
+use rstest::rstest;
+use rust_decimal_macros::dec;
+use payments_engine::error::ApplicationError;
+use payments_engine::mapping::{Column, InputMapping};
+use payments_engine::model::TransactionType;
+
+fn record(fields: &[&str]) -> StringRecord {
+    StringRecord::from(fields.to_vec())
+}
+
+#[test]
+fn test_empty_mapping_reads_standard_csv() {
+    let mapping = InputMapping::from_toml("").unwrap();
+    assert_eq!(mapping, InputMapping::default());
+    assert_eq!((mapping.delimiter, mapping.quote), (',', '"'));
+
+    let mut columns = mapping.resolve(&record(&["type", "client", "tx", "amount"])).unwrap();
+    let parsed = columns.deserialize(&record(&["deposit", "1", "7", "2.5"])).unwrap();
+    assert_eq!(parsed.transaction_type, TransactionType::Deposit);
+    assert_eq!((parsed.client_id, parsed.transaction_id, parsed.amount), (1, 7, Some(dec!(2.5))));
+    assert_eq!(parsed.reason, None);
+}
+
+#[test]
+fn test_columns_by_alias_and_position() {
+    let mapping = InputMapping::from_toml(r#"
+        [columns]
+        type = "Kind"
+        client = ["ClientId", "Customer"]
+        tx = 4
+        amount = "Value"
+
+        [types]
+        DEP = "deposit"
+    "#).unwrap();
+    assert_eq!(mapping.columns["client"], Column::Headers(vec!["ClientId".to_string(), "Customer".to_string()]));
+
+    // Columns in any order, and unmapped extra columns, are fine
+    let mut columns = mapping.resolve(&record(&["Value", "Kind", "Customer", "Ref", "Branch"])).unwrap();
+    let parsed = columns.deserialize(&record(&["3.0", "DEP", "2", "9", "north"])).unwrap();
+    assert_eq!(parsed.transaction_type, TransactionType::Deposit);
+    assert_eq!((parsed.client_id, parsed.transaction_id, parsed.amount), (2, 9, Some(dec!(3.0))));
+
+    // Type names without an alias are read as they are
+    let parsed = columns.deserialize(&record(&["1.0", "withdrawal", "2", "10", "north"])).unwrap();
+    assert_eq!(parsed.transaction_type, TransactionType::Withdrawal);
+}
+
+#[test]
+fn test_short_row_leaves_out_missing_columns() {
+    let mapping = InputMapping::from_toml("[columns]\ntx = 1\ntype = 2\nclient = 3").unwrap();
+    let mut columns = mapping.resolve(&record(&["id", "kind", "client", "amount"])).unwrap();
+    let parsed = columns.deserialize(&record(&["4", "dispute", "1"])).unwrap();
+    assert_eq!((parsed.transaction_type, parsed.transaction_id, parsed.amount), (TransactionType::Dispute, 4, None));
+
+    // A row missing a required column fails to parse like any other bad row
+    assert!(columns.deserialize(&record(&["4", "dispute"])).is_err());
+}
+
+#[rstest]
+#[case::missing_header("[columns]\ntype = \"Kind\"")]
+#[case::missing_aliases("[columns]\nclient = [\"ClientId\", \"Customer\"]")]
+fn test_resolve_fails_on_missing_header(#[case] text: &str) {
+    let mapping = InputMapping::from_toml(text).unwrap();
+    let result = mapping.resolve(&record(&["type", "client", "tx", "amount"]));
+    assert!(matches!(result, Err(ApplicationError::InvalidMapping { .. })));
+}
+
+#[rstest]
+#[case::unknown_field("[columns]\nmemo = 5")]
+#[case::position_zero("[columns]\ntx = 0")]
+#[case::unknown_type("[types]\nPAY = \"payout\"")]
+#[case::non_ascii_delimiter("delimiter = \"§\"")]
+#[case::long_delimiter("delimiter = \";;\"")]
+#[case::unknown_key("separator = \";\"")]
+fn test_invalid_mapping(#[case] text: &str) {
+    assert!(matches!(InputMapping::from_toml(text), Err(ApplicationError::InvalidMapping { .. })));
+}
@@ -0,0 +1,249 @@
+use payments_engine::config::EngineConfig;
+use payments_engine::engine::Engine;
+use payments_engine::model::{TransactionOutcome, TransactionRecord, TransactionType};
+use payments_engine::parallel::ShardedEngine;
+use rstest::rstest;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+/// Helper to create a TransactionRecord easily
+fn transaction(transaction_type: TransactionType, client_id: u16, transaction_id: u32, amount: Option<Decimal>) -> TransactionRecord {
+    TransactionRecord::new(transaction_type, client_id, transaction_id, amount)
+}
+
+/// Deterministic pseudo-random record stream. Few clients and a small transaction ID space make clients collide on
+/// transaction IDs and reference each other's transactions in disputes, which are the cases sharding must get right.
+/// With `authorizations`, records also authorize, capture and void funds, and carry mostly increasing timestamps.
+fn generated_records(count: usize, seed: u64, authorizations: bool) -> Vec<TransactionRecord> {
+    let mut state = seed;
+    let mut next = move |bound: u64| {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (state >> 33) % bound
+    };
+    (0..count)
+        .map(|index| {
+            let transaction_type = match next(if authorizations { 14 } else { 10 }) {
+                0..=3 => TransactionType::Deposit,
+                4..=5 => TransactionType::Withdrawal,
+                6..=7 => TransactionType::Dispute,
+                8 => TransactionType::Resolve,
+                9 => TransactionType::Chargeback,
+                10..=11 => TransactionType::Authorize,
+                12 => TransactionType::Capture,
+                _ => TransactionType::Void,
+            };
+            let amount = match transaction_type {
+                TransactionType::Deposit | TransactionType::Withdrawal | TransactionType::Authorize => Some(Decimal::new(next(100_000) as i64, 4)),
+                TransactionType::Capture | TransactionType::Void if next(2) == 0 => Some(Decimal::new(next(50_000) as i64, 4)),
+                _ => None,
+            };
+            let record = transaction(transaction_type, next(12) as u16, next(150) as u32, amount);
+            match authorizations && next(4) > 0 {
+                true => TransactionRecord { timestamp: Some(index as u64 + next(20)), ..record },
+                false => record,
+            }
+        })
+        .collect()
+}
+
+/// Runs records through a single engine, returning it and every non-applied outcome in order
+fn run_serial(records: &[TransactionRecord], config: EngineConfig) -> (Engine, Vec<(usize, TransactionOutcome)>) {
+    let mut engine = Engine::with_config(config);
+    let mut outcomes = Vec::new();
+    for (index, record) in records.iter().enumerate() {
+        let outcome = engine.process_transaction(record.clone()).unwrap();
+        if !outcome.is_applied() {
+            outcomes.push((index, outcome));
+        }
+    }
+    (engine, outcomes)
+}
+
+fn run_sharded(records: &[TransactionRecord], config: EngineConfig, workers: usize) -> (Engine, Vec<(usize, TransactionOutcome)>) {
+    let mut sharded = ShardedEngine::new(Engine::with_config(config), workers);
+    for (index, record) in records.iter().enumerate() {
+        sharded.submit(index, record.clone()).unwrap();
+    }
+    let (engine, results) = sharded.finish().unwrap();
+    let outcomes = results.into_iter().map(|(index, _, result)| (index, result.unwrap())).collect();
+    (engine, outcomes)
+}
+
+#[rstest]
+#[case(1)]
+#[case(2)]
+#[case(3)]
+#[case(5)]
+#[case(8)]
+fn test_sharded_matches_serial(#[case] workers: usize) {
+    for seed in 0..20 {
+        let records = generated_records(600, seed, false);
+        let (serial, serial_outcomes) = run_serial(&records, EngineConfig::default());
+        let (sharded, sharded_outcomes) = run_sharded(&records, EngineConfig::default(), workers);
+
+        assert_eq!(sharded.accounts, serial.accounts, "accounts differ for seed {}", seed);
+        assert_eq!(sharded.disputes, serial.disputes, "disputes differ for seed {}", seed);
+        assert_eq!(sharded.transactions.len(), serial.transactions.len(), "transactions differ for seed {}", seed);
+        assert_eq!(sharded_outcomes, serial_outcomes, "outcomes differ for seed {}", seed);
+    }
+}
+
+/// Helper to list the stored transactions by ID, with the type and amount they were stored as
+fn stored(engine: &Engine) -> Vec<(u32, TransactionType, Option<Decimal>)> {
+    let mut stored: Vec<_> = engine.transactions.values().map(|record| (record.transaction_id, record.transaction_type, record.amount)).collect();
+    stored.sort_unstable_by_key(|(transaction_id, _, _)| *transaction_id);
+    stored
+}
+
+#[rstest]
+#[case::by_records(Some(7), None)]
+#[case::by_seconds(None, Some(9))]
+#[case::both(Some(20), Some(5))]
+fn test_sharded_expires_authorizations_like_serial(#[case] records: Option<u64>, #[case] seconds: Option<u64>) {
+    let config = EngineConfig { authorization_expiry_records: records, authorization_expiry_seconds: seconds, ..EngineConfig::default() };
+    for workers in [1, 3, 5] {
+        for seed in 0..10 {
+            let records = generated_records(600, seed, true);
+            let (serial, serial_outcomes) = run_serial(&records, config);
+            let (sharded, sharded_outcomes) = run_sharded(&records, config, workers);
+
+            assert_eq!(sharded.accounts, serial.accounts, "accounts differ for seed {}", seed);
+            assert_eq!(sharded.authorizations, serial.authorizations, "authorizations differ for seed {}", seed);
+            assert_eq!(stored(&sharded), stored(&serial), "transactions differ for seed {}", seed);
+            assert_eq!(sharded_outcomes, serial_outcomes, "outcomes differ for seed {}", seed);
+            sharded.check_invariants().unwrap();
+        }
+    }
+}
+
+#[rstest]
+fn test_sharded_rejects_transaction_id_stored_by_other_shard() {
+    let records = vec![
+        transaction(TransactionType::Deposit, 1, 7, Some(dec!(5.0000))),
+        // Client 2 lives on the other shard and reuses tx 7
+        transaction(TransactionType::Deposit, 2, 7, Some(dec!(3.0000))),
+    ];
+    let (engine, outcomes) = run_sharded(&records, EngineConfig::default(), 2);
+    assert!(!engine.accounts.contains_key(&2));
+    assert_eq!(outcomes.len(), 1);
+    assert_eq!(outcomes[0].0, 1);
+}
+
+#[rstest]
+fn test_sharded_allows_transaction_id_rejected_by_other_shard() {
+    let records = vec![
+        // Rejected for insufficient funds, so tx 7 is never stored
+        transaction(TransactionType::Deposit, 1, 1, Some(dec!(1.0000))),
+        transaction(TransactionType::Withdrawal, 1, 7, Some(dec!(5.0000))),
+        transaction(TransactionType::Deposit, 2, 7, Some(dec!(3.0000))),
+        transaction(TransactionType::Dispute, 2, 7, None),
+    ];
+    let (engine, outcomes) = run_sharded(&records, EngineConfig::default(), 2);
+    assert_eq!(engine.accounts.get(&2).unwrap().held, dec!(3.0000));
+    assert_eq!(outcomes.iter().map(|(index, _)| *index).collect::<Vec<_>>(), vec![1]);
+}
+
+#[rstest]
+#[case(2)]
+#[case(3)]
+fn test_sharded_refunds_match_serial(#[case] workers: usize) {
+    let refund = |client_id: u16, transaction_id: u32, original: u32| TransactionRecord {
+        original_transaction_id: Some(original),
+        ..transaction(TransactionType::Refund, client_id, transaction_id, Some(dec!(1.0000)))
+    };
+    let records = vec![
+        transaction(TransactionType::Deposit, 1, 1, Some(dec!(5.0000))),
+        transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(2.0000))),
+        transaction(TransactionType::Deposit, 2, 3, Some(dec!(5.0000))),
+        transaction(TransactionType::Withdrawal, 2, 4, Some(dec!(2.0000))),
+        refund(1, 5, 2),
+        // Another client's withdrawal, which lives on the other shard
+        refund(2, 6, 2),
+        // Tx 5 was stored by the other shard
+        refund(2, 5, 4),
+        refund(2, 7, 99),
+        refund(2, 8, 4),
+        refund(1, 9, 2),
+        refund(1, 10, 2),
+        transaction(TransactionType::Dispute, 2, 8, None),
+    ];
+    let (serial, serial_outcomes) = run_serial(&records, EngineConfig::default());
+    let (sharded, sharded_outcomes) = run_sharded(&records, EngineConfig::default(), workers);
+
+    assert_eq!(sharded.accounts, serial.accounts);
+    assert_eq!(sharded.disputes, serial.disputes);
+    assert_eq!(stored(&sharded), stored(&serial));
+    assert_eq!(sharded.transactions.get(&2).unwrap().refunded, Some(dec!(2.0000)));
+    assert_eq!(sharded_outcomes, serial_outcomes);
+    assert_eq!(sharded_outcomes.iter().map(|(index, _)| *index).collect::<Vec<_>>(), vec![5, 6, 7, 10]);
+}
+
+#[rstest]
+fn test_sharded_resumes_from_existing_engine() {
+    let mut engine = Engine::new();
+    engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(5.0000)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Deposit, 2, 2, Some(dec!(4.0000)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Dispute, 2, 2, None)).unwrap();
+
+    let mut sharded = ShardedEngine::new(engine, 2);
+    sharded.submit(0, transaction(TransactionType::Chargeback, 2, 2, None)).unwrap();
+    sharded.submit(1, transaction(TransactionType::Dispute, 1, 1, None)).unwrap();
+    let (engine, results) = sharded.finish().unwrap();
+
+    assert!(results.is_empty());
+    assert!(engine.accounts.get(&2).unwrap().locked);
+    assert_eq!(engine.accounts.get(&1).unwrap().held, dec!(5.0000));
+    assert!(engine.disputes.is_open(1));
+}
+
+/// Helper to create a Transfer record from `client_id` to `counterparty`
+fn transfer(client_id: u16, transaction_id: u32, amount: Decimal, counterparty: u16) -> TransactionRecord {
+    TransactionRecord { counterparty: Some(counterparty), ..transaction(TransactionType::Transfer, client_id, transaction_id, Some(amount)) }
+}
+
+#[rstest]
+#[case(2)]
+#[case(3)]
+#[case(5)]
+fn test_sharded_transfers_match_serial(#[case] workers: usize) {
+    for seed in 0..10 {
+        // Transfers part way through, some between clients of the same shard and some between clients of two
+        let mut records = generated_records(600, seed, false);
+        for index in (100..600).step_by(40) {
+            let (client_id, transaction_id) = (records[index].client_id, records[index].transaction_id);
+            records[index] = transfer(client_id, transaction_id, dec!(1.0000), (client_id + index as u16 / 40) % 12);
+        }
+        let (serial, serial_outcomes) = run_serial(&records, EngineConfig::default());
+        let (sharded, sharded_outcomes) = run_sharded(&records, EngineConfig::default(), workers);
+
+        assert_eq!(sharded.accounts, serial.accounts, "accounts differ for seed {}", seed);
+        assert_eq!(sharded.disputes, serial.disputes, "disputes differ for seed {}", seed);
+        assert_eq!(stored(&sharded), stored(&serial), "transactions differ for seed {}", seed);
+        assert_eq!(sharded_outcomes, serial_outcomes, "outcomes differ for seed {}", seed);
+        sharded.check_invariants().unwrap();
+    }
+}
+
+#[rstest]
+fn test_sharded_applies_transfers_resumed_from_existing_engine() {
+    let mut engine = Engine::new();
+    engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(5.0000)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Deposit, 2, 2, Some(dec!(4.0000)))).unwrap();
+    engine.process_transaction(transfer(1, 3, dec!(1.0000), 2)).unwrap();
+
+    // Clients 1 and 2 live on different shards, and the chargeback touches both
+    let mut sharded = ShardedEngine::new(engine, 2);
+    sharded.submit(0, transaction(TransactionType::Dispute, 1, 3, None)).unwrap();
+    sharded.submit(1, transaction(TransactionType::Chargeback, 1, 3, None)).unwrap();
+    sharded.submit(2, transfer(2, 4, dec!(1.0000), 1)).unwrap();
+    let (engine, results) = sharded.finish().unwrap();
+
+    let sender = engine.accounts.get(&1).unwrap();
+    assert_eq!((sender.available, sender.total), (dec!(5.0000), dec!(5.0000)));
+    let recipient = engine.accounts.get(&2).unwrap();
+    assert_eq!((recipient.available, recipient.total), (dec!(4.0000), dec!(4.0000)));
+    assert!(recipient.locked);
+    // The locked recipient can't send funds either
+    assert_eq!(results.iter().map(|(index, _, _)| *index).collect::<Vec<_>>(), vec![2]);
+    engine.check_invariants().unwrap();
+}
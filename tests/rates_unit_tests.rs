@@ -0,0 +1,60 @@
+use rstest::rstest;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use payments_engine::error::ApplicationError;
+use payments_engine::rates::RateTable;
+
+/// Helper to read a rate table from CSV text
+fn table(text: &str) -> Result<RateTable, ApplicationError> {
+    RateTable::from_reader(text.as_bytes())
+}
+
+#[rstest]
+// Before the first rate of the pair, nothing is in effect
+#[case(99, None)]
+#[case(100, Some(dec!(0.90)))]
+#[case(199, Some(dec!(0.90)))]
+#[case(200, Some(dec!(0.92)))]
+#[case(u64::MAX, Some(dec!(0.92)))]
+fn test_rate_in_effect_at_timestamp(#[case] timestamp: u64, #[case] expected: Option<Decimal>) {
+    // Rows in any order, with codes in any case
+    let rates = table("from,to,rate,effective_from\nUSD,EUR,0.92,200\nusd, eur ,0.90,100\n").unwrap();
+    assert_eq!(rates.rate("USD", "EUR", timestamp), expected);
+}
+
+#[test]
+fn test_rates_only_apply_in_listed_direction() {
+    let rates = table("from,to,rate,effective_from\nUSD,JPY,150.25,0\n").unwrap();
+    assert_eq!(rates.rate("USD", "JPY", 0), Some(dec!(150.25)));
+    assert_eq!(rates.rate("JPY", "USD", 0), None);
+    assert_eq!(rates.rate("USD", "GBP", 0), None);
+}
+
+#[rstest]
+#[case::unknown_currency("USD,XYZ,1.5,0", "line 2: currency `XYZ` is not supported")]
+#[case::same_currency("USD,usd,1,0", "line 2: rate converts USD into itself")]
+#[case::zero_rate("USD,EUR,0,0", "line 2: rate 0 must be greater than zero")]
+#[case::negative_rate("USD,EUR,-0.5,0", "line 2: rate -0.5 must be greater than zero")]
+#[case::duplicate("USD,EUR,0.9,10\nUSD,EUR,0.8,10", "line 3: USD to EUR already has a rate effective from 10")]
+fn test_invalid_rate_rows_rejected(#[case] rows: &str, #[case] expected: &str) {
+    match table(&format!("from,to,rate,effective_from\n{}\n", rows)) {
+        Err(ApplicationError::InvalidRates { reason }) => assert_eq!(reason, expected),
+        other => panic!("Expected InvalidRates, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_unparsable_rate_row_rejected() {
+    assert!(matches!(table("from,to,rate,effective_from\nUSD,EUR,abc,0\n"), Err(ApplicationError::InvalidRates { .. })));
+    assert!(matches!(table("from,to,rate,effective_from\nUSD,EUR,0.9,yesterday\n"), Err(ApplicationError::InvalidRates { .. })));
+}
+
+#[test]
+fn test_insert_keeps_first_rate_for_a_timestamp() {
+    let mut rates = RateTable::default();
+    assert!(rates.insert("USD", "EUR", 10, dec!(0.9)));
+    assert!(!rates.insert("USD", "EUR", 10, dec!(0.8)));
+    assert!(rates.insert("USD", "EUR", 5, dec!(0.7)));
+    assert_eq!(rates.rate("USD", "EUR", 10), Some(dec!(0.9)));
+    assert_eq!(rates.rate("USD", "EUR", 9), Some(dec!(0.7)));
+}
@@ -0,0 +1,74 @@
+use std::io::{BufRead, Cursor};
+use csv::StringRecord;
+use rstest::rstest;
+use payments_engine::error::ApplicationError;
+use payments_engine::mapping::InputMapping;
+use payments_engine::schema::{self, LATEST_SCHEMA};
+
+#[test]
+fn test_schema_versions_only_append_columns() {
+    assert_eq!(schema::columns(1).unwrap(), ["type", "client", "tx", "amount"]);
+    assert_eq!(schema::columns(2).unwrap(), ["type", "client", "tx", "amount", "reason"]);
+    for version in 2..=LATEST_SCHEMA {
+        let (older, newer) = (schema::columns(version - 1).unwrap(), schema::columns(version).unwrap());
+        assert!(newer.starts_with(older));
+    }
+    assert!(matches!(schema::columns(0), Err(ApplicationError::InvalidSchema { .. })));
+    assert!(matches!(schema::columns(LATEST_SCHEMA + 1), Err(ApplicationError::InvalidSchema { .. })));
+}
+
+#[rstest]
+#[case::plain("#schema=1", Some(1))]
+#[case::spaced("# schema = 2 \r\n", Some(2))]
+#[case::comment("# exported by partner", None)]
+#[case::other_key("#version=2", None)]
+#[case::not_a_comment("type,client,tx,amount", None)]
+fn test_parse_marker(#[case] line: &str, #[case] expected: Option<u32>) {
+    assert_eq!(schema::parse_marker(line).transpose().unwrap(), expected);
+}
+
+#[test]
+fn test_parse_marker_without_version() {
+    assert!(matches!(schema::parse_marker("#schema=two"), Some(Err(ApplicationError::InvalidSchema { .. }))));
+}
+
+#[test]
+fn test_read_marker() {
+    // The marker line is taken off the stream and returned
+    let mut input = Cursor::new("#schema=1\ntype,client,tx,amount\n");
+    let (line, version) = schema::read_marker(&mut input).unwrap();
+    assert_eq!((line.as_slice(), version), (b"#schema=1\n".as_slice(), Some(1)));
+    assert_eq!(input.lines().next().unwrap().unwrap(), "type,client,tx,amount");
+
+    // Files without a leading comment are left untouched
+    let mut input = Cursor::new("type,client,tx,amount\n");
+    assert_eq!(schema::read_marker(&mut input).unwrap(), (Vec::new(), None));
+    assert_eq!(input.position(), 0);
+
+    // Declaring a version this build does not know fails up front
+    assert!(matches!(schema::read_marker(&mut Cursor::new("#schema=99\n")), Err(ApplicationError::InvalidSchema { .. })));
+}
+
+#[rstest]
+#[case::exact(1, &["type", "client", "tx", "amount"], true)]
+#[case::missing_column(2, &["type", "client", "tx", "amount"], false)]
+#[case::newer_column(1, &["type", "client", "tx", "amount", "reason"], false)]
+#[case::reordered(1, &["client", "type", "tx", "amount"], false)]
+fn test_check_header(#[case] version: u32, #[case] headers: &[&str], #[case] valid: bool) {
+    assert_eq!(schema::check_header(version, &StringRecord::from(headers.to_vec())).is_ok(), valid);
+}
+
+#[rstest]
+#[case::all_columns(1, true)]
+#[case::missing_column(2, false)]
+fn test_mapped_columns_checked_against_schema(#[case] version: u32, #[case] valid: bool) {
+    // Mapped columns satisfy the schema no matter what the partner calls them
+    let mapping = InputMapping::from_toml("[columns]\ntype = \"Kind\"\namount = 5").unwrap();
+    let columns = mapping.resolve(&StringRecord::from(vec!["Kind", "client", "tx", "Branch", "Value"])).unwrap();
+    assert_eq!(columns.check_schema(version).is_ok(), valid);
+
+    // A mapped column beyond the declared schema is refused
+    let mapping = InputMapping::from_toml("[columns]\nreason = \"Note\"").unwrap();
+    let columns = mapping.resolve(&StringRecord::from(vec!["type", "client", "tx", "amount", "Note"])).unwrap();
+    assert!(matches!(columns.check_schema(1), Err(ApplicationError::InvalidSchema { .. })));
+}
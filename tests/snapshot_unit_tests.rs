@@ -0,0 +1,166 @@
+use payments_engine::config::EngineConfig;
+use payments_engine::engine::Engine;
+use payments_engine::error::ApplicationError;
+use payments_engine::model::{RejectionReason, TransactionOutcome, TransactionRecord, TransactionType};
+use payments_engine::snapshot::{self, SNAPSHOT_VERSION};
+use rstest::rstest;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+/// Helper to create a TransactionRecord easily
+fn transaction(transaction_type: TransactionType, client_id: u16, transaction_id: u32, amount: Option<Decimal>) -> TransactionRecord {
+    TransactionRecord::new(transaction_type, client_id, transaction_id, amount)
+}
+
+/// Helper to build a "day one" engine and its snapshot
+fn day_one() -> (Engine, Vec<u8>) {
+    let mut engine = Engine::new();
+    engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(10.0000)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(2.0000)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Deposit, 2, 3, Some(dec!(4.0000)))).unwrap();
+    engine.process_transaction(transaction(TransactionType::Dispute, 2, 3, None)).unwrap();
+    let mut buffer = Vec::new();
+    snapshot::write_snapshot(&engine, &mut buffer).unwrap();
+    (engine, buffer)
+}
+
+fn load(buffer: &[u8]) -> Result<Engine, ApplicationError> {
+    let mut engine = Engine::new();
+    snapshot::load_snapshot_into(&mut engine, buffer)?;
+    Ok(engine)
+}
+
+#[rstest]
+fn test_snapshot_round_trip() {
+    let (engine, buffer) = day_one();
+    let restored = load(&buffer).unwrap();
+    assert_eq!(restored.accounts, engine.accounts);
+    assert_eq!(restored.disputes, engine.disputes);
+    assert_eq!(restored.transactions.len(), 3);
+}
+
+#[rstest]
+fn test_next_day_disputes_reference_previous_day() {
+    let (_, buffer) = day_one();
+    let mut engine = load(&buffer).unwrap();
+
+    // Day N+1 resolves a dispute opened on day N, and disputes a day N deposit
+    engine.process_transaction(transaction(TransactionType::Resolve, 2, 3, None)).unwrap();
+    engine.process_transaction(transaction(TransactionType::Dispute, 1, 1, None)).unwrap();
+    // Day N transaction IDs remain taken
+    assert_eq!(
+        engine.process_transaction(transaction(TransactionType::Deposit, 1, 2, Some(dec!(1.0000)))).unwrap(),
+        TransactionOutcome::Rejected { client_id: 1, transaction_id: 2, transaction_type: TransactionType::Deposit, reason: RejectionReason::DuplicateTransaction },
+    );
+
+    let account1 = engine.accounts.get(&1).unwrap();
+    assert_eq!(account1.available, dec!(-2.0000));
+    assert_eq!(account1.held, dec!(10.0000));
+    let account2 = engine.accounts.get(&2).unwrap();
+    assert_eq!(account2.available, dec!(4.0000));
+    assert_eq!(account2.held, dec!(0.0000));
+}
+
+#[rstest]
+fn test_open_authorizations_expire_after_resume() {
+    let mut engine = Engine::with_config(EngineConfig { authorization_expiry_seconds: Some(60), ..EngineConfig::default() });
+    engine.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(10.0000)))).unwrap();
+    engine.process_transaction(TransactionRecord { timestamp: Some(100), ..transaction(TransactionType::Authorize, 1, 2, Some(dec!(4.0000))) }).unwrap();
+    let mut buffer = Vec::new();
+    snapshot::write_snapshot(&engine, &mut buffer).unwrap();
+
+    // The expiry was fixed when the funds were reserved, so it holds without the day N configuration
+    let mut restored = load(&buffer).unwrap();
+    assert_eq!(restored.authorizations, engine.authorizations);
+    assert_eq!(restored.clock(), Some(100));
+    assert_eq!(restored.accounts.get(&1).unwrap().reserved, dec!(4.0000));
+
+    restored.process_transaction(TransactionRecord { timestamp: Some(160), ..transaction(TransactionType::Deposit, 2, 3, Some(dec!(1.0000))) }).unwrap();
+    assert!(!restored.authorizations.is_open(2));
+    assert_eq!(restored.accounts.get(&1).unwrap().available, dec!(10.0000));
+    restored.check_invariants().unwrap();
+}
+
+/// A snapshot written by a version 7 build: an open authorization and a dispute, before refunds were added
+const VERSION_7_SNAPSHOT: &str = r#"{"header":{"version":7,"journal_sequence":4,"record_sequence":4,"clock":100}}
+{"account":{"client_id":1,"account":{"available":"6.0000","held":"0.0000","total":"10.0000","locked":false,"reserved":"4.0000","closed":false}}}
+{"account":{"client_id":2,"account":{"available":"0.0000","held":"4.0000","total":"4.0000","locked":false,"reserved":"0.0000","closed":false}}}
+{"transaction":{"type":"deposit","client":1,"tx":1,"amount":"10.0000"}}
+{"transaction":{"type":"authorize","client":1,"tx":2,"amount":"4.0000","timestamp":100}}
+{"transaction":{"type":"deposit","client":2,"tx":3,"amount":"4.0000"}}
+{"dispute":{"transaction_id":3,"history":[{"state":"open","amount":"4.0000","released":"0","charged_back":"0","opened_at":4,"closed_at":null}]}}
+{"authorization":{"transaction_id":2,"authorization":{"client_id":1,"currency":null,"amount":"4.0000","authorized_at":2,"expires_after":null,"expires_at":null}}}
+{"trailer":{"accounts":2,"transactions":3,"disputes":1,"authorizations":1}}
+"#;
+
+#[rstest]
+fn test_snapshot_loads_older_version() {
+    let mut engine = load(VERSION_7_SNAPSHOT.as_bytes()).unwrap();
+    engine.check_invariants().unwrap();
+    assert_eq!(engine.clock(), Some(100));
+    assert_eq!(engine.accounts.get(&1).unwrap().reserved, dec!(4.0000));
+    assert!(engine.authorizations.is_open(2));
+
+    // Day N+1 captures the authorization and resolves the dispute opened by the version 7 build
+    engine.process_transaction(transaction(TransactionType::Capture, 1, 2, None)).unwrap();
+    engine.process_transaction(transaction(TransactionType::Resolve, 2, 3, None)).unwrap();
+    let account1 = engine.accounts.get(&1).unwrap();
+    assert_eq!(account1.available, dec!(6.0000));
+    assert_eq!(account1.reserved, dec!(0.0000));
+    assert_eq!(account1.total, dec!(6.0000));
+    let account2 = engine.accounts.get(&2).unwrap();
+    assert_eq!(account2.available, dec!(4.0000));
+    assert_eq!(account2.held, dec!(0.0000));
+    engine.check_invariants().unwrap();
+}
+
+/// Disputes of snapshots before version 3 have no amounts, and version 1 lists only open disputes without a history
+#[rstest]
+#[case::version_1(r#"{"dispute":{"transaction_id":3}}"#, 1)]
+#[case::version_2(r#"{"dispute":{"transaction_id":3,"history":[{"state":"resolved","opened_at":1,"closed_at":2},{"state":"open","opened_at":3,"closed_at":null}]}}"#, 2)]
+fn test_snapshot_loads_whole_transaction_disputes(#[case] dispute: &str, #[case] version: u32) {
+    let text = format!(
+        "{{\"header\":{{\"version\":{version},\"journal_sequence\":2}}}}\n\
+         {{\"account\":{{\"client_id\":2,\"account\":{{\"available\":\"0.0000\",\"held\":\"4.0000\",\"total\":\"4.0000\",\"locked\":false}}}}}}\n\
+         {{\"transaction\":{{\"type\":\"deposit\",\"client\":2,\"tx\":3,\"amount\":\"4.0000\"}}}}\n\
+         {dispute}\n\
+         {{\"trailer\":{{\"accounts\":1,\"transactions\":1,\"disputes\":1}}}}\n"
+    );
+    let mut engine = load(text.as_bytes()).unwrap();
+    let open = engine.disputes.latest(3).unwrap();
+    assert_eq!(open.amount, dec!(4.0000));
+    assert_eq!(open.released, dec!(0));
+
+    engine.process_transaction(transaction(TransactionType::Chargeback, 2, 3, None)).unwrap();
+    let account2 = engine.accounts.get(&2).unwrap();
+    assert_eq!(account2.held, dec!(0.0000));
+    assert_eq!(account2.total, dec!(0.0000));
+    assert!(account2.locked);
+    engine.check_invariants().unwrap();
+}
+
+#[rstest]
+fn test_snapshot_rejects_unknown_version() {
+    let (_, buffer) = day_one();
+    let text = String::from_utf8(buffer).unwrap().replacen(&format!("\"version\":{}", SNAPSHOT_VERSION), "\"version\":99", 1);
+    assert!(matches!(
+        load(text.as_bytes()),
+        Err(ApplicationError::UnsupportedSnapshotVersion { found: 99, supported: SNAPSHOT_VERSION })
+    ));
+}
+
+#[rstest]
+fn test_snapshot_rejects_truncated_file() {
+    let (_, buffer) = day_one();
+    let text = String::from_utf8(buffer).unwrap();
+    let truncated: Vec<&str> = text.lines().take(4).collect();
+    assert!(matches!(load(truncated.join("\n").as_bytes()), Err(ApplicationError::InvalidSnapshot { .. })));
+}
+
+#[rstest]
+fn test_snapshot_rejects_missing_header() {
+    let (_, buffer) = day_one();
+    let text = String::from_utf8(buffer).unwrap();
+    let headless: Vec<&str> = text.lines().skip(1).collect();
+    assert!(matches!(load(headless.join("\n").as_bytes()), Err(ApplicationError::InvalidSnapshot { .. })));
+}
@@ -0,0 +1,91 @@
+use std::path::{Path, PathBuf};
+use payments_engine::engine::Engine;
+use payments_engine::model::{Account, TransactionRecord, TransactionType};
+use payments_engine::storage::{AccountStore, SqliteAccountStore, SqliteTransactionStore, TransactionStore};
+use rstest::rstest;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+/// Helper to create a TransactionRecord easily
+fn transaction(transaction_type: TransactionType, client_id: u16, transaction_id: u32, amount: Option<Decimal>) -> TransactionRecord {
+    TransactionRecord::new(transaction_type, client_id, transaction_id, amount)
+}
+
+/// Helper to get a fresh database path per test
+fn database_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("payments_engine_{}_{}.db", name, std::process::id()))
+}
+
+/// Helper to remove a database along with its WAL side files
+fn remove_database(path: &Path) {
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+    }
+}
+
+fn sqlite_engine(path: &Path) -> Engine<SqliteAccountStore, SqliteTransactionStore> {
+    Engine::with_stores(SqliteAccountStore::open(path).unwrap(), SqliteTransactionStore::open(path).unwrap())
+}
+
+fn scenario() -> Vec<TransactionRecord> {
+    vec![
+        transaction(TransactionType::Deposit, 1, 1, Some(dec!(10.0000))),
+        transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(2.5000))),
+        transaction(TransactionType::Withdrawal, 1, 3, Some(dec!(20.0000))),
+        transaction(TransactionType::Deposit, 2, 4, Some(dec!(4.0000))),
+        transaction(TransactionType::Deposit, 2, 4, Some(dec!(4.0000))),
+        transaction(TransactionType::Dispute, 2, 4, None),
+        transaction(TransactionType::Deposit, 3, 5, Some(dec!(1.2345))),
+        transaction(TransactionType::Dispute, 3, 5, None),
+        transaction(TransactionType::Chargeback, 3, 5, None),
+        transaction(TransactionType::Deposit, 3, 6, Some(dec!(1.0000))),
+    ]
+}
+
+#[rstest]
+fn test_sqlite_engine_matches_in_memory_engine() {
+    let path = database_path("matches_in_memory");
+    let mut in_memory = Engine::new();
+    let mut on_disk = sqlite_engine(&path);
+    for record in scenario() {
+        let expected = in_memory.process_transaction(record.clone()).unwrap();
+        assert_eq!(on_disk.process_transaction(record).unwrap(), expected);
+    }
+
+    assert_eq!(on_disk.accounts.client_ids().unwrap(), vec![1, 2, 3]);
+    for client_id in on_disk.accounts.client_ids().unwrap() {
+        assert_eq!(on_disk.accounts.get_account(client_id).unwrap().as_ref(), in_memory.accounts.get(&client_id));
+    }
+    assert_eq!(on_disk.disputes, in_memory.disputes);
+    remove_database(&path);
+}
+
+#[rstest]
+fn test_sqlite_transaction_store_round_trip() {
+    let path = database_path("transaction_round_trip");
+    let mut store = SqliteTransactionStore::open(&path).unwrap();
+    assert!(!store.contains_transaction(7).unwrap());
+    store.put_transaction(transaction(TransactionType::Withdrawal, 3, 7, Some(dec!(0.0001)))).unwrap();
+
+    let stored = store.get_transaction(7).unwrap().unwrap();
+    assert!(store.contains_transaction(7).unwrap());
+    assert_eq!(stored.transaction_type, TransactionType::Withdrawal);
+    assert_eq!(stored.client_id, 3);
+    assert_eq!(stored.amount, Some(dec!(0.0001)));
+    assert!(store.get_transaction(8).unwrap().is_none());
+    remove_database(&path);
+}
+
+#[rstest]
+fn test_sqlite_store_is_cleared_on_open() {
+    let path = database_path("cleared_on_open");
+    {
+        let mut store = SqliteAccountStore::open(&path).unwrap();
+        store.put_account(1, Account::new()).unwrap();
+        assert_eq!(store.client_ids().unwrap(), vec![1]);
+    }
+    let store = SqliteAccountStore::open(&path).unwrap();
+    assert!(store.client_ids().unwrap().is_empty());
+    assert!(store.get_account(1).unwrap().is_none());
+    remove_database(&path);
+}
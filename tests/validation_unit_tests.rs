@@ -0,0 +1,161 @@
+use payments_engine::config::{PrecisionPolicy, ValidationConfig};
+use payments_engine::currency;
+use payments_engine::model::{RejectionReason, TransactionRecord, TransactionType};
+use payments_engine::validation::validate;
+use rstest::rstest;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+/// Helper to create a TransactionRecord easily
+fn transaction(transaction_type: TransactionType, client_id: u16, transaction_id: u32, amount: Option<Decimal>) -> TransactionRecord {
+    TransactionRecord::new(transaction_type, client_id, transaction_id, amount)
+}
+
+const REJECT: ValidationConfig = ValidationConfig { precision: PrecisionPolicy::Reject };
+const ROUND: ValidationConfig = ValidationConfig { precision: PrecisionPolicy::Round };
+
+#[rstest]
+#[case(TransactionType::Deposit, dec!(1.0000))]
+#[case(TransactionType::Deposit, dec!(0.0001))]
+#[case(TransactionType::Withdrawal, dec!(2.5))]
+// Trailing zeros beyond four places carry no extra precision
+#[case(TransactionType::Withdrawal, dec!(3.12340000))]
+fn test_valid_amounts_pass_unchanged(#[case] transaction_type: TransactionType, #[case] amount: Decimal) {
+    let mut record = transaction(transaction_type, 1, 1, Some(amount));
+    assert_eq!(validate(&mut record, &REJECT), Ok(()));
+    assert_eq!(record.amount, Some(amount));
+}
+
+#[rstest]
+#[case(TransactionType::Deposit, dec!(0))]
+#[case(TransactionType::Deposit, dec!(-5.0000))]
+#[case(TransactionType::Withdrawal, dec!(0.0000))]
+#[case(TransactionType::Withdrawal, dec!(-0.0001))]
+fn test_non_positive_amounts_rejected(#[case] transaction_type: TransactionType, #[case] amount: Decimal) {
+    let mut record = transaction(transaction_type, 1, 1, Some(amount));
+    assert_eq!(validate(&mut record, &REJECT), Err(RejectionReason::NonPositiveAmount));
+    assert_eq!(validate(&mut record, &ROUND), Err(RejectionReason::NonPositiveAmount));
+}
+
+#[rstest]
+#[case(TransactionType::Dispute)]
+#[case(TransactionType::Resolve)]
+#[case(TransactionType::Chargeback)]
+fn test_partial_amount_on_dispute_rows_validated(#[case] transaction_type: TransactionType) {
+    let mut record = transaction(transaction_type, 1, 1, Some(dec!(1.0000)));
+    assert_eq!(validate(&mut record, &REJECT), Ok(()));
+
+    let mut record = transaction(transaction_type, 1, 1, Some(dec!(-1.0000)));
+    assert_eq!(validate(&mut record, &REJECT), Err(RejectionReason::NonPositiveAmount));
+
+    let mut record = transaction(transaction_type, 1, 1, Some(dec!(1.00005)));
+    assert_eq!(validate(&mut record, &REJECT), Err(RejectionReason::ExcessPrecision));
+    assert_eq!(validate(&mut record, &ROUND), Ok(()));
+    assert_eq!(record.amount, Some(dec!(1.0001)));
+
+    let mut record = transaction(transaction_type, 1, 1, None);
+    assert_eq!(validate(&mut record, &REJECT), Ok(()));
+}
+
+#[rstest]
+fn test_missing_amount_left_to_engine() {
+    let mut record = transaction(TransactionType::Deposit, 1, 1, None);
+    assert_eq!(validate(&mut record, &REJECT), Ok(()));
+    assert_eq!(record.amount, None);
+}
+
+#[rstest]
+fn test_excess_precision_rejected_by_reject_policy() {
+    let mut record = transaction(TransactionType::Deposit, 1, 1, Some(dec!(1.123456)));
+    assert_eq!(validate(&mut record, &REJECT), Err(RejectionReason::ExcessPrecision));
+    assert_eq!(record.amount, Some(dec!(1.123456)));
+}
+
+#[rstest]
+#[case(dec!(1.123456), dec!(1.1235))]
+#[case(dec!(1.12344), dec!(1.1234))]
+// Midpoints round away from zero
+#[case(dec!(2.00005), dec!(2.0001))]
+fn test_excess_precision_rounded_by_round_policy(#[case] amount: Decimal, #[case] expected: Decimal) {
+    let mut record = transaction(TransactionType::Withdrawal, 1, 1, Some(amount));
+    assert_eq!(validate(&mut record, &ROUND), Ok(()));
+    assert_eq!(record.amount, Some(expected));
+}
+
+#[rstest]
+fn test_amount_rounding_to_zero_rejected() {
+    let mut record = transaction(TransactionType::Deposit, 1, 1, Some(dec!(0.00004)));
+    assert_eq!(validate(&mut record, &ROUND), Err(RejectionReason::NonPositiveAmount));
+}
+
+#[rstest]
+fn test_adjustment_amounts_may_be_negative_but_not_zero() {
+    let mut record = transaction(TransactionType::Adjustment, 1, 1, Some(dec!(-2.5000)));
+    assert_eq!(validate(&mut record, &REJECT), Ok(()));
+
+    let mut record = transaction(TransactionType::Adjustment, 1, 1, Some(dec!(0.0000)));
+    assert_eq!(validate(&mut record, &REJECT), Err(RejectionReason::ZeroAmount));
+
+    let mut record = transaction(TransactionType::Adjustment, 1, 1, Some(dec!(-0.00004)));
+    assert_eq!(validate(&mut record, &REJECT), Err(RejectionReason::ExcessPrecision));
+    assert_eq!(validate(&mut record, &ROUND), Err(RejectionReason::ZeroAmount));
+}
+
+/// Helper to create a TransactionRecord in a named currency
+fn in_currency(transaction_type: TransactionType, amount: Option<Decimal>, currency: &str) -> TransactionRecord {
+    TransactionRecord { currency: Some(currency.to_string()), ..transaction(transaction_type, 1, 1, amount) }
+}
+
+#[rstest]
+#[case("USD", dec!(1.25))]
+#[case("usd", dec!(1.25))]
+#[case("JPY", dec!(500))]
+#[case("KWD", dec!(0.125))]
+#[case("CLF", dec!(0.0001))]
+fn test_amounts_within_currency_minor_units_pass(#[case] currency: &str, #[case] amount: Decimal) {
+    let mut record = in_currency(TransactionType::Deposit, Some(amount), currency);
+    assert_eq!(validate(&mut record, &REJECT), Ok(()));
+    assert_eq!(record.currency.as_deref(), Some(currency.to_ascii_uppercase().as_str()));
+}
+
+#[rstest]
+#[case("USD", dec!(1.005), dec!(1.01))]
+#[case("JPY", dec!(500.5), dec!(501))]
+#[case("KWD", dec!(0.1234), dec!(0.123))]
+fn test_amounts_beyond_currency_minor_units_rejected_or_rounded(#[case] currency: &str, #[case] amount: Decimal, #[case] expected: Decimal) {
+    let mut record = in_currency(TransactionType::Deposit, Some(amount), currency);
+    assert_eq!(validate(&mut record, &REJECT), Err(RejectionReason::ExcessPrecision));
+    assert_eq!(validate(&mut record, &ROUND), Ok(()));
+    assert_eq!(record.amount, Some(expected));
+}
+
+#[rstest]
+#[case(TransactionType::Deposit, Some(dec!(1.00)))]
+// Rows without an amount still name a currency that must be known
+#[case(TransactionType::Dispute, None)]
+fn test_unknown_currency_rejected(#[case] transaction_type: TransactionType, #[case] amount: Option<Decimal>) {
+    let mut record = in_currency(transaction_type, amount, "XYZ");
+    assert_eq!(validate(&mut record, &REJECT), Err(RejectionReason::UnknownCurrency));
+    assert_eq!(validate(&mut record, &ROUND), Err(RejectionReason::UnknownCurrency));
+}
+
+#[rstest]
+#[case(None, Some(4))]
+#[case(Some("EUR"), Some(2))]
+#[case(Some("JPY"), Some(0))]
+#[case(Some("BHD"), Some(3))]
+#[case(Some("eur"), None)]
+#[case(Some("XYZ"), None)]
+fn test_currency_minor_units(#[case] currency: Option<&str>, #[case] expected: Option<u32>) {
+    assert_eq!(currency::minor_units(currency), expected);
+}
+
+#[rstest]
+fn test_target_currency_checked_and_input_rate_dropped() {
+    let mut record = TransactionRecord { target_currency: Some("eur".to_string()), rate: Some(dec!(2)), ..in_currency(TransactionType::Convert, Some(dec!(1.00)), "usd") };
+    assert_eq!(validate(&mut record, &REJECT), Ok(()));
+    assert_eq!((record.currency.as_deref(), record.target_currency.as_deref(), record.rate), (Some("USD"), Some("EUR"), None));
+
+    let mut record = TransactionRecord { target_currency: Some("XYZ".to_string()), ..in_currency(TransactionType::Convert, Some(dec!(1.00)), "USD") };
+    assert_eq!(validate(&mut record, &REJECT), Err(RejectionReason::UnknownCurrency));
+}